@@ -0,0 +1,186 @@
+//! Change data capture.
+//!
+//! Each rule maps a key prefix to a stream: after a write command changes a key starting with the prefix,
+//! an entry is appended to the stream with the following fields:
+//! * `command`: the name of the command, e.g. `cbor.set`
+//! * `key`: the name of the key
+//! * `changes`: a CBOR array of `{"path": path, "old": old value, "new": new value}` maps,
+//!   one for each value added (without `old`), removed (without `new`) or replaced by the command
+//!
+//! In a cluster, the captured keys and their stream must belong to the same slot:
+//! see [`check_cluster_rule`].
+
+use crate::{commands::changes, util::CborError};
+use cbor_data::{Cbor, CborBuilder, CborOwned, Writer};
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CdcRule {
+    pub prefix: Vec<u8>,
+    pub stream: Vec<u8>,
+}
+
+static CDC_RULES: RwLock<Vec<CdcRule>> = RwLock::new(Vec::new());
+
+/// Add a rule, replacing the rule with the same prefix if any
+pub fn add_rule(rule: CdcRule) {
+    let mut rules = CDC_RULES.write().unwrap_or_else(|e| e.into_inner());
+    match rules.iter_mut().find(|r| r.prefix == rule.prefix) {
+        Some(existing) => *existing = rule,
+        None => rules.push(rule),
+    }
+}
+
+/// Remove the rule with the given prefix, returning whether it existed
+pub fn remove_rule(prefix: &[u8]) -> bool {
+    let mut rules = CDC_RULES.write().unwrap_or_else(|e| e.into_inner());
+    let len = rules.len();
+    rules.retain(|r| r.prefix != prefix);
+    rules.len() != len
+}
+
+/// Replace all the rules, e.g. with the rules loaded from an RDB
+pub fn set_rules(new_rules: Vec<CdcRule>) {
+    *CDC_RULES.write().unwrap_or_else(|e| e.into_inner()) = new_rules;
+}
+
+pub fn rules() -> Vec<CdcRule> {
+    CDC_RULES.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Check that all the keys starting with the prefix of `rule` belong to the slot of its stream:
+/// the prefix must contain a hash tag, which the keys starting with it share,
+/// and the stream must have the same hash tag
+pub fn check_cluster_rule(rule: &CdcRule) -> Result<(), CborError> {
+    match (hash_tag(&rule.prefix), hash_tag(&rule.stream)) {
+        (Some(prefix_tag), Some(stream_tag)) if prefix_tag == stream_tag => Ok(()),
+        _ => Err(CborError::Err(
+            "in cluster mode, prefix must contain a hash tag, e.g. `{orders}:`, shared by stream"
+                .into(),
+        )),
+    }
+}
+
+/// The hash tag of a key name, as defined by the Redis Cluster specification:
+/// the non-empty bytes between the first `{` and the first `}` after it
+fn hash_tag(key_name: &[u8]) -> Option<&[u8]> {
+    let start = key_name.iter().position(|b| *b == b'{')? + 1;
+    let len = key_name[start..].iter().position(|b| *b == b'}')?;
+    (len > 0).then(|| &key_name[start..start + len])
+}
+
+/// The streams capturing the changes of `key_name`
+pub fn streams(key_name: &[u8]) -> Vec<Vec<u8>> {
+    CDC_RULES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter(|r| key_name.starts_with(&r.prefix))
+        .map(|r| r.stream.clone())
+        .collect()
+}
+
+/// The `changes` field of the entry capturing a change from `old` to `new`.
+///
+/// `old` is `None` for a new key and `new` is `None` for a deleted key:
+/// the whole document is then the only change, at the root path.
+pub fn change_entry(old: Option<&Cbor>, new: Option<&Cbor>) -> CborOwned {
+    match (old, new) {
+        (Some(old), Some(new)) => changes(old, new),
+        _ => CborBuilder::new().write_array(None, |builder| {
+            builder.write_dict(None, |builder| {
+                builder.with_key("path", |b| {
+                    b.write_array(None, |builder| {
+                        builder.write_str("$", None);
+                    })
+                });
+                if let Some(old) = old {
+                    builder.with_key("old", |b| b.write_item(old));
+                }
+                if let Some(new) = new {
+                    builder.with_key("new", |b| b.write_item(new));
+                }
+            });
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_rule, change_entry, check_cluster_rule, hash_tag, remove_rule, rules, streams, CdcRule,
+    };
+    use crate::util::{cbor_to_diag, diag_to_cbor};
+
+    #[test]
+    fn rules_by_prefix() {
+        let rule = |prefix: &str, stream: &str| CdcRule {
+            prefix: prefix.as_bytes().to_vec(),
+            stream: stream.as_bytes().to_vec(),
+        };
+
+        add_rule(rule("{orders}:", "{orders}:cdc"));
+        add_rule(rule("{orders}:eu:", "{orders}:cdc:eu"));
+        add_rule(rule("{orders}:", "{orders}:audit"));
+        assert_eq!(
+            vec![
+                rule("{orders}:", "{orders}:audit"),
+                rule("{orders}:eu:", "{orders}:cdc:eu")
+            ],
+            rules()
+        );
+
+        assert_eq!(
+            vec![b"{orders}:audit".to_vec(), b"{orders}:cdc:eu".to_vec()],
+            streams(b"{orders}:eu:1")
+        );
+        assert_eq!(vec![b"{orders}:audit".to_vec()], streams(b"{orders}:us:1"));
+        assert!(streams(b"users:1").is_empty());
+
+        assert!(remove_rule(b"{orders}:"));
+        assert!(!remove_rule(b"{orders}:"));
+        assert!(remove_rule(b"{orders}:eu:"));
+        assert!(rules().is_empty());
+    }
+
+    #[test]
+    fn cluster_rules() {
+        let rule = |prefix: &str, stream: &str| CdcRule {
+            prefix: prefix.as_bytes().to_vec(),
+            stream: stream.as_bytes().to_vec(),
+        };
+
+        assert_eq!(Some(b"orders".as_slice()), hash_tag(b"{orders}:1"));
+        assert_eq!(Some(b"a".as_slice()), hash_tag(b"x{a}{b}"));
+        assert_eq!(None, hash_tag(b"{}{orders}"));
+        assert_eq!(None, hash_tag(b"{orders"));
+        assert_eq!(None, hash_tag(b"orders:"));
+
+        assert!(check_cluster_rule(&rule("{orders}:", "{orders}:cdc")).is_ok());
+        assert!(check_cluster_rule(&rule("eu:{orders}", "cdc:{orders}")).is_ok());
+        // keys starting with the prefix may have different hash tags
+        assert!(check_cluster_rule(&rule("orders:", "orders:cdc")).is_err());
+        assert!(check_cluster_rule(&rule("{orders", "{orders}:cdc")).is_err());
+        assert!(check_cluster_rule(&rule("", "{orders}:cdc")).is_err());
+        assert!(check_cluster_rule(&rule("{orders}:", "{users}:cdc")).is_err());
+    }
+
+    #[test]
+    fn entry() {
+        let old = diag_to_cbor(r#"{"a":1,"b":2}"#);
+        let new = diag_to_cbor(r#"{"a":1,"b":3}"#);
+
+        assert_eq!(
+            r#"[{"path":["$","b"],"old":2,"new":3}]"#,
+            cbor_to_diag(&change_entry(Some(&old), Some(&new)))
+        );
+        assert_eq!(
+            r#"[{"path":["$"],"new":{"a":1,"b":3}}]"#,
+            cbor_to_diag(&change_entry(None, Some(&new)))
+        );
+        assert_eq!(
+            r#"[{"path":["$"],"old":{"a":1,"b":2}}]"#,
+            cbor_to_diag(&change_entry(Some(&old), None))
+        );
+    }
+}
@@ -1,13 +1,17 @@
-use crate::util::{apply_changes, CborExt, CborKeyWritable, CborPathExt, NextArgExt};
+use crate::util::{
+    apply_changes, split_format_arg, value_from_arg, CborError, CborKeyWritable, CborPathExt,
+    NextArgExt,
+};
 use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
 use cborpath::CborPath;
 use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
 use std::borrow::Cow;
 
 ///
-/// CBOR.ARRAPPEND key path value [value ...]
+/// CBOR.ARRAPPEND key path value [value ...] [FORMAT CBOR | JSON | DIAG]
 ///
-pub fn cbor_arr_append(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+pub fn cbor_arr_append(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let (args, format) = split_format_arg(&argv, 4)?;
     let mut args = args.iter().skip(1).peekable();
 
     let key_name = args.next_arg()?;
@@ -17,33 +21,33 @@ pub fn cbor_arr_append(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     args.peek().ok_or(RedisError::WrongArity)?;
 
     let values = args.try_fold(Vec::with_capacity(args.len()), |mut acc, arg| {
-        let value = Cbor::from_arg(arg)?;
+        let value = value_from_arg(arg, format)?;
         acc.push(value);
-        Result::<Vec<&Cbor>, RedisError>::Ok(acc)
+        Result::<Vec<Cow<Cbor>>, RedisError>::Ok(acc)
     })?;
 
     let key = ctx.open_key_writable(key_name);
-    let cbor_path = CborPath::from_arg(path)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
 
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    let (new_value, array_sizes) = array_append(existing, &cbor_path, values);
+    let values = values.iter().map(|v| v.as_ref()).collect();
+    let (new_value, array_sizes) = array_append(existing, &cbor_path, values)?;
 
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.arrappend", key_name)?;
+        apply_changes(ctx, "cbor.arrappend", &argv, &key, Some(new_value))?;
     }
 
     Ok(array_sizes.into())
 }
 
-fn array_append<'a>(
+pub(crate) fn array_append<'a>(
     existing: &'a Cbor,
     cbor_path: &CborPath,
     values: Vec<&'a Cbor>,
-) -> (Option<CborOwned>, Vec<RedisValue>) {
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
     let mut array_sizes = Vec::<RedisValue>::new();
 
     let new_value = cbor_path
@@ -70,9 +74,9 @@ fn array_append<'a>(
                 Ok(Some(Cow::Borrowed(old_value)))
             }
         })
-        .unwrap();
+        .map_err(CborError::path_write)?;
 
-    (new_value, array_sizes)
+    Ok((new_value, array_sizes))
 }
 
 #[cfg(test)]
@@ -90,7 +94,8 @@ mod tests {
 
         // ["$"]
         let cbor_path = CborPath::root();
-        let (new_value, array_sizes) = array_append(&cbor, &cbor_path, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_append(&cbor, &cbor_path, vec![&item1, &item2]).unwrap();
 
         assert_eq!(
             r#"["a","b","c","d","e"]"#,
@@ -107,7 +112,8 @@ mod tests {
 
         // ["$", "foo"]
         let cbor_path = CborPath::builder().key("foo").build();
-        let (new_value, array_sizes) = array_append(&cbor, &cbor_path, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_append(&cbor, &cbor_path, vec![&item1, &item2]).unwrap();
 
         assert_eq!(
             r#"{"foo":["a","b","c","d","e"]}"#,
@@ -124,7 +130,8 @@ mod tests {
 
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
-        let (new_value, array_sizes) = array_append(&cbor, &cbor_path, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_append(&cbor, &cbor_path, vec![&item1, &item2]).unwrap();
 
         assert_eq!(
             r#"{"foo":["a","b","c","d","e"],"bar":[1,2,3,4,"d","e"]}"#,
@@ -144,7 +151,8 @@ mod tests {
 
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
-        let (new_value, array_sizes) = array_append(&cbor, &cbor_path, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_append(&cbor, &cbor_path, vec![&item1, &item2]).unwrap();
 
         assert_eq!(
             r#"{"foo":12,"bar":[1,2,3,"d","e"]}"#,
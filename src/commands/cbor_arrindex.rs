@@ -1,12 +1,15 @@
-use crate::util::{normalize_index, CborExt, CborKey, CborPathExt, NextArgExt};
+use crate::util::{
+    normalize_index, split_format_arg, value_from_arg, CborError, CborKey, CborPathExt, NextArgExt,
+};
 use cbor_data::{Cbor, CborOwned, ItemKind};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 
 ///
-/// CBOR.ARRINDEX key path value [start [stop]]
+/// CBOR.ARRINDEX key path value [start [stop]] [FORMAT CBOR | JSON | DIAG]
 ///
 pub fn cbor_arr_index(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let (args, format) = split_format_arg(&args, 4)?;
     let mut args = args.iter().skip(1);
 
     let key_name = args.next_arg()?;
@@ -15,15 +18,15 @@ pub fn cbor_arr_index(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let start = args.next().map_or(Ok(0), |v| v.parse_integer())? as isize;
     let stop = args.next().map_or(Ok(-1), |v| v.parse_integer())? as isize;
 
-    let cbor_path = CborPath::from_arg(path)?;
-    let value = Cbor::from_arg(value)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
+    let value = value_from_arg(value, format)?;
 
     let key = ctx.open_key(key_name);
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    Ok(array_index(existing, &cbor_path, value, start, stop).into())
+    Ok(array_index(existing, &cbor_path, &value, start, stop).into())
 }
 
 fn array_index(
@@ -1,5 +1,6 @@
 use crate::util::{
-    apply_changes, normalize_index, CborExt, CborKeyWritable, CborPathExt, NextArgExt,
+    apply_changes, normalize_index, split_format_arg, value_from_arg, CborError, CborKeyWritable,
+    CborPathExt, NextArgExt,
 };
 use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
 use cborpath::CborPath;
@@ -7,9 +8,10 @@ use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
 use std::borrow::Cow;
 
 ///
-/// CBOR.ARRINSERT key path index value [value ...]
+/// CBOR.ARRINSERT key path index value [value ...] [FORMAT CBOR | JSON | DIAG]
 ///
-pub fn cbor_arr_insert(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+pub fn cbor_arr_insert(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let (args, format) = split_format_arg(&argv, 5)?;
     let mut args = args.iter().skip(1).peekable();
 
     let key_name = args.next_arg()?;
@@ -20,23 +22,23 @@ pub fn cbor_arr_insert(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     args.peek().ok_or(RedisError::WrongArity)?;
 
     let values = args.try_fold(Vec::with_capacity(args.len()), |mut acc, arg| {
-        let value = Cbor::from_arg(arg)?;
+        let value = value_from_arg(arg, format)?;
         acc.push(value);
-        Result::<Vec<&Cbor>, RedisError>::Ok(acc)
+        Result::<Vec<Cow<Cbor>>, RedisError>::Ok(acc)
     })?;
 
     let key = ctx.open_key_writable(key_name);
-    let cbor_path = CborPath::from_arg(path)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
 
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    let (new_value, array_sizes) = array_insert(existing, &cbor_path, index, values);
+    let values = values.iter().map(|v| v.as_ref()).collect();
+    let (new_value, array_sizes) = array_insert(existing, &cbor_path, index, values)?;
 
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.arrappend", key_name)?;
+        apply_changes(ctx, "cbor.arrinsert", &argv, &key, Some(new_value))?;
     }
 
     Ok(array_sizes.into())
@@ -47,7 +49,7 @@ fn array_insert<'a>(
     cbor_path: &CborPath,
     index: isize,
     values: Vec<&'a Cbor>,
-) -> (Option<CborOwned>, Vec<RedisValue>) {
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
     let mut array_sizes = Vec::<RedisValue>::new();
 
     let new_value = cbor_path
@@ -74,9 +76,9 @@ fn array_insert<'a>(
                 Ok(Some(Cow::Borrowed(old_value)))
             }
         })
-        .unwrap();
+        .map_err(CborError::path_write)?;
 
-    (new_value, array_sizes)
+    Ok((new_value, array_sizes))
 }
 
 fn write_array<'a, I>(
@@ -127,28 +129,32 @@ mod tests {
         // ["$"]
         let cbor_path = CborPath::root();
 
-        let (new_value, array_sizes) = array_insert(&cbor, &cbor_path, 3, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_insert(&cbor, &cbor_path, 3, vec![&item1, &item2]).unwrap();
         assert_eq!(
             r#"["a","b","c","d","e"]"#,
             cbor_to_diag(&new_value.unwrap())
         );
         assert_eq!(vec![RedisValue::Integer(5)], array_sizes);
 
-        let (new_value, array_sizes) = array_insert(&cbor, &cbor_path, 2, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_insert(&cbor, &cbor_path, 2, vec![&item1, &item2]).unwrap();
         assert_eq!(
             r#"["a","b","d","e","c"]"#,
             cbor_to_diag(&new_value.unwrap())
         );
         assert_eq!(vec![RedisValue::Integer(5)], array_sizes);
 
-        let (new_value, array_sizes) = array_insert(&cbor, &cbor_path, -1, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_insert(&cbor, &cbor_path, -1, vec![&item1, &item2]).unwrap();
         assert_eq!(
             r#"["a","b","d","e","c"]"#,
             cbor_to_diag(&new_value.unwrap())
         );
         assert_eq!(vec![RedisValue::Integer(5)], array_sizes);
 
-        let (new_value, array_sizes) = array_insert(&cbor, &cbor_path, -3, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_insert(&cbor, &cbor_path, -3, vec![&item1, &item2]).unwrap();
         assert_eq!(
             r#"["d","e","a","b","c"]"#,
             cbor_to_diag(&new_value.unwrap())
@@ -164,7 +170,8 @@ mod tests {
 
         // ["$", "foo"]
         let cbor_path = CborPath::builder().key("foo").build();
-        let (new_value, array_sizes) = array_insert(&cbor, &cbor_path, 2, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_insert(&cbor, &cbor_path, 2, vec![&item1, &item2]).unwrap();
 
         assert_eq!(
             r#"{"foo":["a","b","d","e","c"]}"#,
@@ -181,7 +188,8 @@ mod tests {
 
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
-        let (new_value, array_sizes) = array_insert(&cbor, &cbor_path, 2, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_insert(&cbor, &cbor_path, 2, vec![&item1, &item2]).unwrap();
 
         assert_eq!(
             r#"{"foo":["a","b","d","e","c"],"bar":[1,2,"d","e",3,4]}"#,
@@ -201,7 +209,8 @@ mod tests {
 
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
-        let (new_value, array_sizes) = array_insert(&cbor, &cbor_path, 2, vec![&item1, &item2]);
+        let (new_value, array_sizes) =
+            array_insert(&cbor, &cbor_path, 2, vec![&item1, &item2]).unwrap();
 
         assert_eq!(
             r#"{"foo":12,"bar":[1,2,"d","e",3]}"#,
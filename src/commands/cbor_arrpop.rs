@@ -1,20 +1,22 @@
 use std::borrow::Cow;
 
-use crate::util::{apply_changes, normalize_index, CborKeyWritable, CborPathExt, NextArgExt};
+use crate::util::{
+    apply_changes, normalize_index, CborError, CborKeyWritable, CborPathExt, NextArgExt,
+};
 use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 
 ///
 /// CBOR.ARRPOP key [path [index]]
 ///
 /// Remove and return an element from the index in the array at path in key
-pub fn cbor_arr_pop(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    let mut args = args.iter().skip(1);
+pub fn cbor_arr_pop(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let mut args = argv.iter().skip(1);
 
     let key_name = args.next_arg()?;
     let cbor_path = match args.next_arg() {
-        Ok(cbor_path) => CborPath::from_arg(cbor_path)?,
+        Ok(cbor_path) => CborPath::from_arg(ctx, cbor_path)?,
         Err(_) => CborPath::root(),
     };
     let index = args.next().map_or(Ok(-1), |v| v.parse_integer())? as isize;
@@ -22,14 +24,13 @@ pub fn cbor_arr_pop(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let key = ctx.open_key_writable(key_name);
 
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    let (new_value, popped_items) = array_pop(existing, &cbor_path, index);
+    let (new_value, popped_items) = array_pop(existing, &cbor_path, index)?;
 
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.arrpop", key_name)?;
+        apply_changes(ctx, "cbor.arrpop", &argv, &key, Some(new_value))?;
     }
 
     Ok(popped_items.into())
@@ -39,7 +40,7 @@ fn array_pop(
     existing: &CborOwned,
     cbor_path: &CborPath,
     index: isize,
-) -> (Option<CborOwned>, Vec<RedisValue>) {
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
     let mut popped_items = Vec::<RedisValue>::new();
 
     let new_value = cbor_path
@@ -66,9 +67,9 @@ fn array_pop(
                 Ok(Some(Cow::Borrowed(old_value)))
             }
         })
-        .unwrap();
+        .map_err(CborError::path_write)?;
 
-    (new_value, popped_items)
+    Ok((new_value, popped_items))
 }
 
 fn write_array<'a, I>(
@@ -114,21 +115,21 @@ mod tests {
         // ["$"]
         let cbor_path = CborPath::root();
 
-        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 0);
+        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 0).unwrap();
         assert_eq!(r#"["b","c"]"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![RedisValue::StringBuffer(diag_to_bytes(r#""a""#))],
             popped_items
         );
 
-        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 2);
+        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 2).unwrap();
         assert_eq!(r#"["a","b"]"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![RedisValue::StringBuffer(diag_to_bytes(r#""c""#))],
             popped_items
         );
 
-        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, -1);
+        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, -1).unwrap();
         assert_eq!(r#"["a","b"]"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![RedisValue::StringBuffer(diag_to_bytes(r#""c""#))],
@@ -143,21 +144,21 @@ mod tests {
         // ["$", "foo"]
         let cbor_path = CborPath::builder().key("foo").build();
 
-        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 0);
+        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 0).unwrap();
         assert_eq!(r#"{"foo":["b","c"]}"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![RedisValue::StringBuffer(diag_to_bytes(r#""a""#))],
             popped_items
         );
 
-        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 2);
+        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 2).unwrap();
         assert_eq!(r#"{"foo":["a","b"]}"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![RedisValue::StringBuffer(diag_to_bytes(r#""c""#))],
             popped_items
         );
 
-        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, -1);
+        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, -1).unwrap();
         assert_eq!(r#"{"foo":["a","b"]}"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![RedisValue::StringBuffer(diag_to_bytes(r#""c""#))],
@@ -172,7 +173,7 @@ mod tests {
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
 
-        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 2);
+        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 2).unwrap();
         assert_eq!(
             r#"{"foo":["a","b"],"bar":[1,2,4]}"#,
             cbor_to_diag(&new_value.unwrap())
@@ -193,7 +194,7 @@ mod tests {
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
 
-        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 2);
+        let (new_value, popped_items) = array_pop(&cbor, &cbor_path, 2).unwrap();
         assert_eq!(r#"{"foo":12,"bar":[1,2]}"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(vec![RedisValue::Null, RedisValue::StringBuffer(diag_to_bytes("3"))], popped_items);
     }
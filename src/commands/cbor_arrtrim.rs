@@ -1,18 +1,20 @@
-use crate::util::{apply_changes, normalize_index, CborKeyWritable, CborPathExt, NextArgExt};
+use crate::util::{
+    apply_changes, normalize_index, CborError, CborKeyWritable, CborPathExt, NextArgExt,
+};
 use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 use std::borrow::Cow;
 
 ///
 /// CBOR.ARRTRIM key [path [start [stop]]]
 ///
-pub fn cbor_arr_trim(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    let mut args = args.iter().skip(1);
+pub fn cbor_arr_trim(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let mut args = argv.iter().skip(1);
 
     let key_name = args.next_arg()?;
     let cbor_path = match args.next_arg() {
-        Ok(cbor_path) => CborPath::from_arg(cbor_path)?,
+        Ok(cbor_path) => CborPath::from_arg(ctx, cbor_path)?,
         Err(_) => CborPath::root(),
     };
     let start = args.next().map_or(Ok(0), |v| v.parse_integer())? as isize;
@@ -21,14 +23,13 @@ pub fn cbor_arr_trim(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let key = ctx.open_key_writable(key_name);
 
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    let (new_value, array_sizes) = array_trim(existing, &cbor_path, start, stop);
+    let (new_value, array_sizes) = array_trim(existing, &cbor_path, start, stop)?;
 
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.arrpop", key_name)?;
+        apply_changes(ctx, "cbor.arrtrim", &argv, &key, Some(new_value))?;
     }
 
     Ok(array_sizes.into())
@@ -39,7 +40,7 @@ fn array_trim(
     cbor_path: &CborPath,
     start: isize,
     stop: isize,
-) -> (Option<CborOwned>, Vec<RedisValue>) {
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
     let mut array_sizes = Vec::<RedisValue>::new();
 
     let new_value = cbor_path
@@ -68,9 +69,9 @@ fn array_trim(
                 Ok(Some(Cow::Borrowed(old_value)))
             }
         })
-        .unwrap();
+        .map_err(CborError::path_write)?;
 
-    (new_value, array_sizes)
+    Ok((new_value, array_sizes))
 }
 
 fn write_array<'a, I>(
@@ -113,19 +114,19 @@ mod tests {
         // ["$"]
         let cbor_path = CborPath::root();
 
-        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 1, 3);
+        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 1, 3).unwrap();
         assert_eq!(r#"["b","c","d"]"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(vec![RedisValue::Integer(3)], array_sizes);
 
-        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, -4, -2);
+        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, -4, -2).unwrap();
         assert_eq!(r#"["b","c","d"]"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(vec![RedisValue::Integer(3)], array_sizes);
 
-        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 2, 1);
+        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 2, 1).unwrap();
         assert_eq!(r#"[]"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(vec![RedisValue::Integer(0)], array_sizes);
 
-        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 4, 4);
+        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 4, 4).unwrap();
         assert_eq!(r#"["e"]"#, cbor_to_diag(&new_value.unwrap()));
         assert_eq!(vec![RedisValue::Integer(1)], array_sizes);
     }
@@ -136,7 +137,7 @@ mod tests {
 
         // ["$", "foo"]
         let cbor_path = CborPath::builder().key("foo").build();
-        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 1, 3);
+        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 1, 3).unwrap();
 
         assert_eq!(
             r#"{"foo":["b","c","d"]}"#,
@@ -151,7 +152,7 @@ mod tests {
 
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
-        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 1, 3);
+        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 1, 3).unwrap();
 
         assert_eq!(
             r#"{"foo":["b","c","d"],"bar":[2,3,4]}"#,
@@ -169,7 +170,7 @@ mod tests {
 
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
-        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 1, 3);
+        let (new_value, array_sizes) = array_trim(&cbor, &cbor_path, 1, 3).unwrap();
 
         assert_eq!(
             r#"{"foo":12,"bar":[2,3,4]}"#,
@@ -0,0 +1,61 @@
+use crate::{
+    cdc::{add_rule, check_cluster_rule, remove_rule, rules, CdcRule},
+    util::{CborError, NextArgExt},
+};
+use redis_module::{
+    Context, ContextFlags, RedisError, RedisResult, RedisString, RedisValue, REDIS_OK,
+};
+
+///
+/// CBOR.CDC <subcommand & arguments>
+///
+/// subcommands:
+/// ADD prefix stream
+/// DEL prefix
+/// LIST
+///
+/// Manage the streams capturing the changes of the keys starting with a prefix.
+/// Changes to the rules are replicated, so that promoted replicas keep capturing the same changes.
+pub fn cbor_cdc(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.iter().skip(1);
+
+    let subcommand = args.next_str()?.to_uppercase();
+    let args = args.collect::<Vec<_>>();
+
+    match (subcommand.as_str(), args.as_slice()) {
+        ("ADD", [prefix, stream]) => {
+            let rule = CdcRule {
+                prefix: prefix.as_slice().to_vec(),
+                stream: stream.as_slice().to_vec(),
+            };
+            if ctx.get_flags().contains(ContextFlags::CLUSTER) {
+                check_cluster_rule(&rule)?;
+            }
+            add_rule(rule);
+            let add = ctx.create_string("ADD");
+            ctx.replicate("CBOR.CDC", &[&add, *prefix, *stream][..]);
+            REDIS_OK
+        }
+        ("DEL", [prefix]) => {
+            let removed = remove_rule(prefix.as_slice());
+            if removed {
+                let del = ctx.create_string("DEL");
+                ctx.replicate("CBOR.CDC", &[&del, *prefix][..]);
+            }
+            Ok(RedisValue::Integer(removed.into()))
+        }
+        ("LIST", []) => Ok(RedisValue::Array(
+            rules()
+                .into_iter()
+                .map(|rule| {
+                    RedisValue::Array(vec![
+                        RedisValue::StringBuffer(rule.prefix),
+                        RedisValue::StringBuffer(rule.stream),
+                    ])
+                })
+                .collect(),
+        )),
+        ("ADD" | "DEL" | "LIST", _) => Err(RedisError::WrongArity),
+        _ => Err(CborError::Err("unknown subcommand - expected ADD, DEL or LIST".into()).into()),
+    }
+}
@@ -1,40 +1,42 @@
 use std::borrow::Cow;
 
-use crate::util::{apply_changes, CborKeyWritable, CborPathExt, NextArgExt};
+use crate::util::{apply_changes, CborError, CborKeyWritable, CborPathExt, NextArgExt};
 use cbor_data::{CborBuilder, CborOwned, ItemKind, Writer};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 
 ///
 /// CBOR.CLEAR key [path]
 ///
 /// Clear container values (arrays/objects) and set numeric values to 0 (integers/floats)
-pub fn cbor_clear(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    let mut args = args.iter().skip(1);
+pub fn cbor_clear(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let mut args = argv.iter().skip(1);
 
     let key_name = args.next_arg()?;
     let cbor_path = match args.next_arg() {
-        Ok(cbor_path) => CborPath::from_arg(cbor_path)?,
+        Ok(cbor_path) => CborPath::from_arg(ctx, cbor_path)?,
         Err(_) => CborPath::root(),
     };
 
     let key = ctx.open_key_writable(key_name);
 
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    let (new_value, num_cleared) = clear(existing, &cbor_path);
+    let (new_value, num_cleared) = clear(existing, &cbor_path)?;
 
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.arrpop", key_name)?;
+        apply_changes(ctx, "cbor.clear", &argv, &key, Some(new_value))?;
     }
 
     Ok(RedisValue::Integer(num_cleared as i64))
 }
 
-fn clear(existing: &CborOwned, cbor_path: &CborPath) -> (Option<CborOwned>, usize) {
+fn clear(
+    existing: &CborOwned,
+    cbor_path: &CborPath,
+) -> Result<(Option<CborOwned>, usize), CborError> {
     let mut num_cleared = 0;
     let new_value = cbor_path
         .write(existing, |old_value| {
@@ -65,9 +67,9 @@ fn clear(existing: &CborOwned, cbor_path: &CborPath) -> (Option<CborOwned>, usiz
 
             Ok(Some(Cow::Owned(new_value)))
         })
-        .unwrap();
+        .map_err(CborError::path_write)?;
 
-    (new_value, num_cleared)
+    Ok((new_value, num_cleared))
 }
 
 #[cfg(test)]
@@ -83,7 +85,7 @@ mod tests {
         );
 
         let cbor_path = CborPath::builder().wildcard().build();
-        let (new_value, num_cleared_values) = clear(&cbor, &cbor_path);
+        let (new_value, num_cleared_values) = clear(&cbor, &cbor_path).unwrap();
         let new_value = new_value.unwrap();
 
         assert_eq!(
@@ -1,11 +1,16 @@
-use crate::util::{CborKey, CborOwnedExt, NextArgExt};
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use crate::{
+    config::max_depth,
+    util::{validate, CborError, CborKey, CborOwnedExt, NextArgExt},
+};
+use redis_module::{Context, RedisResult, RedisString, RedisValue, REDIS_OK};
 
 ///
 /// CBOT.DEBUG <subcommand & arguments>
 ///
 /// subcommands:
+/// DIAG key
 /// MEMORY key
+/// VALIDATE key
 /// HELP
 ///
 pub fn cbor_debug(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
@@ -30,17 +35,28 @@ pub fn cbor_debug(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
                 .map(|v| v.mem_usage())
                 .unwrap_or(0)
                 .into())
-        },
+        }
+        "VALIDATE" => {
+            let key = args.next_arg()?;
+            let key = ctx.open_key(key);
+
+            match key.get_cbor_value()? {
+                Some(v) => {
+                    validate(v.as_slice(), max_depth())?;
+                    REDIS_OK
+                }
+                None => Ok(RedisValue::Null),
+            }
+        }
         "HELP" => {
             let results = vec![
                 "DIAG <key> - display key in CBOR diagnostic notation",
                 "MEMORY <key> - reports memory usage",
+                "VALIDATE <key> - checks that key is well-formed CBOR within the maximum nesting depth",
                 "HELP                - this message",
             ];
             Ok(results.into())
         }
-        _ => Err(RedisError::Str(
-            "ERR unknown subcommand - try `CBOR.DEBUG HELP`",
-        )),
+        _ => Err(CborError::Err("unknown subcommand - try `CBOR.DEBUG HELP`".into()).into()),
     }
 }
@@ -0,0 +1,695 @@
+use crate::{
+    digest::canonical,
+    util::{CborError, CborKey, CborPathExt},
+};
+use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
+use cborpath::CborPath;
+use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+/// Maximum number of cells of the LCS tables computed for a diff:
+/// once they are used up, the remaining arrays are compared item by item
+const MAX_LCS_CELLS: usize = 1 << 20;
+
+///
+/// CBOR.DIFF key1 [path1] key2 [path2] [EQUAL]
+///
+/// Return the operations turning the value at path1 in key1 into the value at path2 in key2
+pub fn cbor_diff(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let (args, equal) = match args.split_last() {
+        Some((last, args)) if args.len() >= 3 && last.as_slice().eq_ignore_ascii_case(b"EQUAL") => {
+            (args, true)
+        }
+        _ => (&args[..], false),
+    };
+
+    let (key1, path1, key2, path2) = match &args[1..] {
+        [key1, key2] => (key1, None, key2, None),
+        [key1, path1, key2, path2] => (key1, Some(path1), key2, Some(path2)),
+        // key1 path1 key2 or key1 key2 path2
+        [key1, arg, last] if CborPath::from_arg(ctx, arg).is_ok() => (key1, Some(arg), last, None),
+        [key1, key2, path2] => (key1, None, key2, Some(path2)),
+        _ => return Err(RedisError::WrongArity),
+    };
+
+    let cbor_path1 = path1.map_or(Ok(CborPath::root()), |path| CborPath::from_arg(ctx, path))?;
+    let cbor_path2 = path2.map_or(Ok(CborPath::root()), |path| CborPath::from_arg(ctx, path))?;
+
+    let key1 = ctx.open_key(key1);
+    let key2 = ctx.open_key(key2);
+
+    let (Some(existing1), Some(existing2)) = (key1.get_cbor_value()?, key2.get_cbor_value()?)
+    else {
+        return Err(CborError::nonexistent_key().into());
+    };
+
+    let value1 = read_single(existing1, &cbor_path1)?;
+    let value2 = read_single(existing2, &cbor_path2)?;
+
+    if equal {
+        Ok(RedisValue::Boolean(canonical(value1) == canonical(value2)))
+    } else {
+        Ok(RedisValue::StringBuffer(diff(value1, value2).into_vec()))
+    }
+}
+
+fn read_single<'a>(existing: &'a Cbor, cbor_path: &CborPath) -> Result<&'a Cbor, CborError> {
+    match cbor_path.read(existing).as_slice() {
+        [value] => Ok(*value),
+        [] => Err(CborError::no_match()),
+        _ => Err(CborError::Err("path matches more than one value".into())),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Segment<'a> {
+    Key(&'a Cbor),
+    /// Index of an array item while applying the operations in order,
+    /// and in the old and new arrays
+    Index {
+        patch: usize,
+        old: usize,
+        new: usize,
+    },
+}
+
+/// Which array indexes a path is written with
+#[derive(Clone, Copy)]
+enum Indexes {
+    /// Indexes in the document patched by the previous operations, as in `CBOR.PATCH`
+    Patch,
+    /// Indexes in the old document
+    Old,
+    /// Indexes in the new document
+    New,
+}
+
+struct Operation<'a> {
+    op: &'static str,
+    path: Vec<Segment<'a>>,
+    old_value: Option<&'a Cbor>,
+    new_value: Option<&'a Cbor>,
+}
+
+impl Operation<'_> {
+    /// Indexes of the path in the document holding the value:
+    /// the new one for added values, the old one otherwise
+    fn indexes(&self) -> Indexes {
+        match self.old_value {
+            Some(_) => Indexes::Old,
+            None => Indexes::New,
+        }
+    }
+}
+
+/// Compute the `CBOR.PATCH` operations turning `old` into `new`
+pub(crate) fn diff(old: &Cbor, new: &Cbor) -> CborOwned {
+    write_patch(&operations(old, new, MAX_LCS_CELLS))
+}
+
+fn write_patch(operations: &[Operation]) -> CborOwned {
+    CborBuilder::new().write_array(None, |builder| {
+        for operation in operations {
+            builder.write_dict(None, |builder| {
+                builder.with_key("op", |b| b.write_str(operation.op, None));
+                builder.with_key("path", |b| write_path(b, &operation.path, Indexes::Patch));
+                if let Some(value) = operation.new_value {
+                    builder.with_key("value", |b| b.write_item(value));
+                }
+            });
+        }
+    })
+}
+
+/// Compute the normalized paths of the values added, removed or replaced
+/// when turning `old` into `new`, as a CBOR array of paths.
+///
+/// Unlike in [`diff`], the paths of removed and replaced values refer to `old`
+/// and the paths of added values refer to `new`.
+pub(crate) fn changed_paths(old: &Cbor, new: &Cbor) -> CborOwned {
+    let operations = operations(old, new, MAX_LCS_CELLS);
+
+    CborBuilder::new().write_array(None, |builder| {
+        for operation in &operations {
+            write_path(&mut *builder, &operation.path, operation.indexes());
+        }
+    })
+}
+
+/// Compute the values added, removed or replaced when turning `old` into `new`,
+/// as a CBOR array of `{"path": path, "old": old value, "new": new value}` maps,
+/// without `old` for added values and without `new` for removed values.
+///
+/// As in [`changed_paths`], the paths refer to `old`, or to `new` for added values.
+pub(crate) fn changes(old: &Cbor, new: &Cbor) -> CborOwned {
+    let operations = operations(old, new, MAX_LCS_CELLS);
+
+    CborBuilder::new().write_array(None, |builder| {
+        for operation in &operations {
+            builder.write_dict(None, |builder| {
+                builder.with_key("path", |b| {
+                    write_path(b, &operation.path, operation.indexes())
+                });
+                if let Some(value) = operation.old_value {
+                    builder.with_key("old", |b| b.write_item(value));
+                }
+                if let Some(value) = operation.new_value {
+                    builder.with_key("new", |b| b.write_item(value));
+                }
+            });
+        }
+    })
+}
+
+/// Compute the operations turning `old` into `new`,
+/// the LCS tables of all the arrays having at most `lcs_cells` cells in total
+fn operations<'a>(old: &'a Cbor, new: &'a Cbor, lcs_cells: usize) -> Vec<Operation<'a>> {
+    let mut differ = Differ {
+        hashes: HashMap::new(),
+        lcs_cells,
+        operations: Vec::new(),
+    };
+    differ.hash(old);
+    differ.hash(new);
+    differ.values(old, new, &mut Vec::new());
+    differ.operations
+}
+
+struct Differ<'a> {
+    /// Hashes of all the values of both documents, by location,
+    /// semantically equal values having the same hash
+    hashes: HashMap<(*const u8, usize), u64>,
+    /// Cells left for the LCS tables
+    lcs_cells: usize,
+    operations: Vec<Operation<'a>>,
+}
+
+impl<'a> Differ<'a> {
+    /// Hash `cbor` and all its nested values, bottom-up
+    fn hash(&mut self, cbor: &Cbor) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        cbor.tags().collect::<Vec<_>>().hash(&mut hasher);
+        match cbor.kind() {
+            ItemKind::Array(items) => {
+                let items = items.map(|item| self.hash(item)).collect::<Vec<_>>();
+                (4u8, items).hash(&mut hasher);
+            }
+            ItemKind::Dict(entries) => {
+                // map entries are compared regardless of their order
+                let mut entries = entries
+                    .map(|(key, value)| (self.hash(key), self.hash(value)))
+                    .collect::<Vec<_>>();
+                entries.sort_unstable();
+                (5u8, entries).hash(&mut hasher);
+            }
+            _ => canonical(cbor).hash(&mut hasher),
+        }
+
+        let hash = hasher.finish();
+        self.hashes.insert(location(cbor), hash);
+        hash
+    }
+
+    #[inline]
+    fn hash_of(&self, cbor: &Cbor) -> Option<u64> {
+        self.hashes.get(&location(cbor)).copied()
+    }
+
+    /// Whether `old` and `new` are semantically equal,
+    /// their canonical encodings being compared only when their hashes match
+    fn equal(&self, old: &Cbor, new: &Cbor) -> bool {
+        self.hash_of(old) == self.hash_of(new) && canonical(old) == canonical(new)
+    }
+
+    fn values(&mut self, old: &'a Cbor, new: &'a Cbor, path: &mut Vec<Segment<'a>>) {
+        if self.equal(old, new) {
+            return;
+        }
+
+        if old.tags().eq(new.tags()) {
+            match (old.kind(), new.kind()) {
+                (ItemKind::Dict(old_entries), ItemKind::Dict(new_entries)) => {
+                    let old_entries = old_entries.collect::<Vec<_>>();
+                    let new_entries = new_entries.collect::<Vec<_>>();
+                    // arrays and maps cannot be used as keys in a CBORPath
+                    if old_entries
+                        .iter()
+                        .chain(&new_entries)
+                        .all(|(k, _)| is_path_key(k))
+                    {
+                        self.maps(old_entries, new_entries, path);
+                        return;
+                    }
+                }
+                (ItemKind::Array(old_items), ItemKind::Array(new_items)) => {
+                    self.arrays(old_items.collect(), new_items.collect(), path);
+                    return;
+                }
+                _ => (),
+            }
+        }
+
+        self.operations
+            .push(operation("replace", path, Some(old), Some(new)));
+    }
+
+    fn maps(
+        &mut self,
+        old_entries: Vec<(&'a Cbor, &'a Cbor)>,
+        new_entries: Vec<(&'a Cbor, &'a Cbor)>,
+        path: &mut Vec<Segment<'a>>,
+    ) {
+        for &(key, old_value) in &old_entries {
+            path.push(Segment::Key(key));
+            match new_entries.iter().find(|(k, _)| *k == key) {
+                Some(&(_, new_value)) => self.values(old_value, new_value, path),
+                None => self
+                    .operations
+                    .push(operation("remove", path, Some(old_value), None)),
+            }
+            path.pop();
+        }
+
+        for &(key, new_value) in &new_entries {
+            if !old_entries.iter().any(|(k, _)| *k == key) {
+                path.push(Segment::Key(key));
+                self.operations
+                    .push(operation("add", path, None, Some(new_value)));
+                path.pop();
+            }
+        }
+    }
+
+    fn arrays(
+        &mut self,
+        old_items: Vec<&'a Cbor>,
+        new_items: Vec<&'a Cbor>,
+        path: &mut Vec<Segment<'a>>,
+    ) {
+        let old_hashes = old_items
+            .iter()
+            .map(|item| self.hash_of(item))
+            .collect::<Vec<_>>();
+        let new_hashes = new_items
+            .iter()
+            .map(|item| self.hash_of(item))
+            .collect::<Vec<_>>();
+
+        let prefix = old_hashes
+            .iter()
+            .zip(&new_hashes)
+            .take_while(|(old, new)| old == new)
+            .count();
+        let suffix = old_hashes[prefix..]
+            .iter()
+            .rev()
+            .zip(new_hashes[prefix..].iter().rev())
+            .take_while(|(old, new)| old == new)
+            .count();
+        let (old_end, new_end) = (old_items.len() - suffix, new_items.len() - suffix);
+
+        let edits = (0..prefix)
+            .map(|i| Edit::Keep(i, i))
+            .chain(
+                edit_script(
+                    &old_hashes[prefix..old_end],
+                    &new_hashes[prefix..new_end],
+                    &mut self.lcs_cells,
+                )
+                .into_iter()
+                .map(|edit| edit.offset(prefix)),
+            )
+            .chain((0..suffix).map(|k| Edit::Keep(old_end + k, new_end + k)))
+            .collect::<Vec<_>>();
+
+        // Consecutive deletions and insertions are paired into changes of the same item,
+        // the remaining ones become removals and additions
+        let mut index = 0;
+        let mut edits = edits.into_iter().peekable();
+        while let Some(edit) = edits.next() {
+            let (mut deleted, mut inserted) = (Vec::new(), Vec::new());
+            match edit {
+                // items with the same hash are still compared, in case of a collision
+                Edit::Keep(i, j) => {
+                    self.items(index, (old_items[i], i), (new_items[j], j), path);
+                    index += 1;
+                    continue;
+                }
+                Edit::Delete(i) => deleted.push(i),
+                Edit::Insert(j) => inserted.push(j),
+            }
+            while let Some(edit) = edits.next_if(|edit| !matches!(edit, Edit::Keep(..))) {
+                match edit {
+                    Edit::Delete(i) => deleted.push(i),
+                    Edit::Insert(j) => inserted.push(j),
+                    Edit::Keep(..) => unreachable!(),
+                }
+            }
+
+            let num_changes = deleted.len().min(inserted.len());
+            for (&i, &j) in deleted.iter().zip(&inserted) {
+                self.items(index, (old_items[i], i), (new_items[j], j), path);
+                index += 1;
+            }
+            for &i in &deleted[num_changes..] {
+                path.push(Segment::Index {
+                    patch: index,
+                    old: i,
+                    new: index,
+                });
+                self.operations
+                    .push(operation("remove", path, Some(old_items[i]), None));
+                path.pop();
+            }
+            for &j in &inserted[num_changes..] {
+                path.push(Segment::Index {
+                    patch: index,
+                    old: index,
+                    new: j,
+                });
+                self.operations
+                    .push(operation("add", path, None, Some(new_items[j])));
+                path.pop();
+                index += 1;
+            }
+        }
+    }
+
+    /// Compare the `old` and `new` array items, at `index` in the patched array
+    fn items(
+        &mut self,
+        index: usize,
+        (old_item, old): (&'a Cbor, usize),
+        (new_item, new): (&'a Cbor, usize),
+        path: &mut Vec<Segment<'a>>,
+    ) {
+        path.push(Segment::Index {
+            patch: index,
+            old,
+            new,
+        });
+        self.values(old_item, new_item, path);
+        path.pop();
+    }
+}
+
+/// Location of a value in its document
+#[inline]
+fn location(cbor: &Cbor) -> (*const u8, usize) {
+    let bytes = cbor.as_slice();
+    (bytes.as_ptr(), bytes.len())
+}
+
+fn is_path_key(key: &Cbor) -> bool {
+    !matches!(key.kind(), ItemKind::Array(_) | ItemKind::Dict(_))
+}
+
+enum Edit {
+    Keep(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+impl Edit {
+    fn offset(self, offset: usize) -> Self {
+        match self {
+            Edit::Keep(i, j) => Edit::Keep(offset + i, offset + j),
+            Edit::Delete(i) => Edit::Delete(offset + i),
+            Edit::Insert(j) => Edit::Insert(offset + j),
+        }
+    }
+}
+
+/// Shortest edit script between two sequences of hashes, based on their longest common subsequence.
+///
+/// The LCS table takes its cells from `lcs_cells`:
+/// when there are not enough cells left, all the items are deleted then inserted,
+/// i.e. the arrays are compared item by item.
+fn edit_script<T: PartialEq>(old: &[T], new: &[T], lcs_cells: &mut usize) -> Vec<Edit> {
+    let (n, m) = (old.len(), new.len());
+    let mut edits = Vec::with_capacity(n.max(m));
+
+    let cells = n.saturating_mul(m);
+    if cells > *lcs_cells {
+        edits.extend((0..n).map(Edit::Delete));
+        edits.extend((0..m).map(Edit::Insert));
+        return edits;
+    }
+    *lcs_cells -= cells;
+
+    // lcs[i * (m + 1) + j] is the length of the LCS of old[i..] and new[j..]
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * (m + 1) + j] = if old[i] == new[j] {
+                lcs[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            edits.push(Edit::Keep(i, j));
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1] {
+            edits.push(Edit::Delete(i));
+            i += 1;
+        } else {
+            edits.push(Edit::Insert(j));
+            j += 1;
+        }
+    }
+    edits.extend((i..n).map(Edit::Delete));
+    edits.extend((j..m).map(Edit::Insert));
+
+    edits
+}
+
+fn operation<'a>(
+    op: &'static str,
+    path: &[Segment<'a>],
+    old_value: Option<&'a Cbor>,
+    new_value: Option<&'a Cbor>,
+) -> Operation<'a> {
+    Operation {
+        op,
+        path: path.to_vec(),
+        old_value,
+        new_value,
+    }
+}
+
+fn write_path<W: Writer>(builder: W, path: &[Segment], indexes: Indexes) -> W::Output {
+    builder.write_array(None, |builder| {
+        builder.write_str("$", None);
+        for segment in path {
+            match segment {
+                Segment::Key(key) => {
+                    builder.write_item(key);
+                }
+                Segment::Index { patch, old, new } => {
+                    let index = match indexes {
+                        Indexes::Patch => patch,
+                        Indexes::Old => old,
+                        Indexes::New => new,
+                    };
+                    builder.write_dict(None, |builder| {
+                        builder.with_key("#", |b| b.write_pos(*index as u64, None));
+                    });
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{changed_paths, changes, diff, operations, write_patch};
+    use crate::{
+        commands::{apply_patch, parse_patch},
+        digest::canonical,
+        util::{cbor_to_diag, diag_to_cbor},
+    };
+
+    fn assert_diff(old: &str, new: &str, expected: &str) {
+        let (old, new) = (diag_to_cbor(old), diag_to_cbor(new));
+        let operations = diff(&old, &new);
+        assert_eq!(expected, cbor_to_diag(&operations));
+
+        // applying the diff as a patch must produce a value semantically equal to the new one
+        let patch = parse_patch(&operations).unwrap();
+        let patched = apply_patch(&old, &patch).unwrap().unwrap_or(old);
+        assert_eq!(canonical(&new), canonical(&patched));
+    }
+
+    #[test]
+    fn equal() {
+        assert_diff(r#"{"a":1,"b":[1,2]}"#, r#"{"b":[1,2],"a":1}"#, "[]");
+        assert_diff("[1.0_1]", "[1.0_3]", "[]");
+    }
+
+    #[test]
+    fn scalars() {
+        assert_diff("1", "2", r#"[{"op":"replace","path":["$"],"value":2}]"#);
+        assert_diff(
+            "[1]",
+            r#"{"a":1}"#,
+            r#"[{"op":"replace","path":["$"],"value":{"a":1}}]"#,
+        );
+        assert_diff(
+            "2(h'01')",
+            "3(h'01')",
+            r#"[{"op":"replace","path":["$"],"value":3(h'01')}]"#,
+        );
+    }
+
+    #[test]
+    fn maps() {
+        assert_diff(
+            r#"{"a":1,"b":2,"c":{"d":3,"e":4}}"#,
+            r#"{"a":1,"c":{"d":3,"e":5},"f":6}"#,
+            concat!(
+                r#"[{"op":"remove","path":["$","b"]},"#,
+                r#"{"op":"replace","path":["$","c","e"],"value":5},"#,
+                r#"{"op":"add","path":["$","f"],"value":6}]"#
+            ),
+        );
+        assert_diff(
+            r#"{1:h'00'}"#,
+            r#"{1:h'01'}"#,
+            r#"[{"op":"replace","path":["$",1],"value":h'01'}]"#,
+        );
+        assert_diff(
+            r#"{[1]:1}"#,
+            r#"{[1]:2}"#,
+            r#"[{"op":"replace","path":["$"],"value":{[1]:2}}]"#,
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        assert_diff(
+            "[1,2,3,4]",
+            "[1,3,4,5]",
+            concat!(
+                r##"[{"op":"remove","path":["$",{"#":1}]},"##,
+                r##"{"op":"add","path":["$",{"#":3}],"value":5}]"##
+            ),
+        );
+        assert_diff(
+            "[1,2,3]",
+            "[1,7,8,3]",
+            concat!(
+                r##"[{"op":"replace","path":["$",{"#":1}],"value":7},"##,
+                r##"{"op":"add","path":["$",{"#":2}],"value":8}]"##
+            ),
+        );
+        assert_diff(
+            r#"[{"a":1},{"a":2}]"#,
+            r#"[{"a":1},{"a":3}]"#,
+            r##"[{"op":"replace","path":["$",{"#":1},"a"],"value":3}]"##,
+        );
+        assert_diff(
+            "[1,2,3]",
+            "[0,1,3,4]",
+            concat!(
+                r##"[{"op":"add","path":["$",{"#":0}],"value":0},"##,
+                r##"{"op":"remove","path":["$",{"#":2}]},"##,
+                r##"{"op":"add","path":["$",{"#":3}],"value":4}]"##
+            ),
+        );
+        assert_diff(
+            "[1,2,3]",
+            "[]",
+            concat!(
+                r##"[{"op":"remove","path":["$",{"#":0}]},"##,
+                r##"{"op":"remove","path":["$",{"#":0}]},"##,
+                r##"{"op":"remove","path":["$",{"#":0}]}]"##
+            ),
+        );
+    }
+
+    #[test]
+    fn semantically_equal_items() {
+        assert_diff(
+            r#"[{"a":1,"b":2},[1.0_1],3]"#,
+            r#"[{"b":2,"a":1},[1.0_3],4]"#,
+            r##"[{"op":"replace","path":["$",{"#":2}],"value":4}]"##,
+        );
+        assert_diff(
+            r#"[0,{"a":1,"b":2},3]"#,
+            r#"[{"b":2,"a":1},3]"#,
+            r##"[{"op":"remove","path":["$",{"#":0}]}]"##,
+        );
+    }
+
+    #[test]
+    fn lcs_cells() {
+        let old = diag_to_cbor(r#"{"a":[1,2,3],"b":[1,2,3]}"#);
+        let new = diag_to_cbor(r#"{"a":[2,3,1],"b":[2,3,1]}"#);
+
+        // the 9 cells of the first LCS table use up the budget: the second array is compared item by item
+        assert_eq!(
+            concat!(
+                r##"[{"op":"remove","path":["$","a",{"#":0}]},"##,
+                r##"{"op":"add","path":["$","a",{"#":2}],"value":1},"##,
+                r##"{"op":"replace","path":["$","b",{"#":0}],"value":2},"##,
+                r##"{"op":"replace","path":["$","b",{"#":1}],"value":3},"##,
+                r##"{"op":"replace","path":["$","b",{"#":2}],"value":1}]"##
+            ),
+            cbor_to_diag(&write_patch(&operations(&old, &new, 9)))
+        );
+        assert_eq!(
+            cbor_to_diag(&diff(&old, &new)),
+            cbor_to_diag(&write_patch(&operations(&old, &new, 18)))
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let old = diag_to_cbor(r#"{"a":[1,2,3],"b":{"c":true}}"#);
+        let new = diag_to_cbor(r#"{"a":[1,3],"b":{"c":false},"d":1}"#);
+        assert_eq!(
+            r##"[["$","a",{"#":1}],["$","b","c"],["$","d"]]"##,
+            cbor_to_diag(&changed_paths(&old, &new))
+        );
+        assert_eq!("[]", cbor_to_diag(&changed_paths(&old, &old)));
+
+        // removed values are at their index in the old array, added values in the new one
+        assert_eq!(
+            r##"[["$",{"#":0}],["$",{"#":1}],["$",{"#":2}]]"##,
+            cbor_to_diag(&changed_paths(
+                &diag_to_cbor("[1,2,3]"),
+                &diag_to_cbor("[]")
+            ))
+        );
+        assert_eq!(
+            r##"[["$",{"#":0}],["$",{"#":1}],["$",{"#":3}]]"##,
+            cbor_to_diag(&changed_paths(
+                &diag_to_cbor("[1,2,3]"),
+                &diag_to_cbor("[0,1,3,4]")
+            ))
+        );
+    }
+
+    #[test]
+    fn old_and_new_values() {
+        let old = diag_to_cbor(r#"{"a":[1,2,3],"b":{"c":true}}"#);
+        let new = diag_to_cbor(r#"{"a":[1,3],"b":{"c":false},"d":1}"#);
+        assert_eq!(
+            concat!(
+                r##"[{"path":["$","a",{"#":1}],"old":2},"##,
+                r#"{"path":["$","b","c"],"old":true,"new":false},"#,
+                r#"{"path":["$","d"],"new":1}]"#
+            ),
+            cbor_to_diag(&changes(&old, &new))
+        );
+    }
+}
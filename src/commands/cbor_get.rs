@@ -1,31 +1,45 @@
-use crate::util::{CborKey, CborPathExt, NextArgExt};
+use crate::{
+    config::output_format,
+    util::{is_format_arg, value_to_reply, CborError, CborKey, CborPathExt, Format, NextArgExt},
+};
 use cbor_data::{CborBuilder, CborOwned, Writer};
 use cborpath::CborPath;
 use redis_module::{Context, RedisResult, RedisString, RedisValue};
 
 ///
-/// CBOR.GET key [path]
+/// CBOR.GET key [path] [FORMAT CBOR | JSON]
 ///
-/// Return the value at path in CBOR serialized form
+/// Return the value at path in CBOR serialized form, or as a JSON text
 pub fn cbor_get(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    let mut args = args.iter().skip(1);
+    let mut args = args.iter().skip(1).peekable();
 
     let key = args.next_arg()?;
-    let cbor_path = match args.next_arg() {
-        Ok(cbor_path) => CborPath::from_arg(cbor_path)?,
-        Err(_) => CborPath::root(),
+    let cbor_path = match args.next_if(|arg| !is_format_arg(arg)) {
+        Some(cbor_path) => CborPath::from_arg(ctx, cbor_path)?,
+        None => CborPath::root(),
     };
 
+    let format = match args.next() {
+        Some(arg) if is_format_arg(arg) => Format::from_arg(args.next_arg()?)?,
+        Some(_) => return Err(CborError::syntax().into()),
+        None => output_format(),
+    };
+
+    if args.next().is_some() {
+        return Err(CborError::syntax().into());
+    }
+
     let key = ctx.open_key(key);
     let existing = key.get_cbor_value()?;
 
     match get(existing, &cbor_path) {
-        Some(value) => Ok(RedisValue::StringBuffer(value.into_vec())),
+        Some(value) => Ok(value_to_reply(value, format)),
         None => Ok(RedisValue::Null),
     }
 }
 
-fn get(existing: Option<&CborOwned>, cbor_path: &CborPath) -> Option<CborOwned> {
+/// The values matching `cbor_path` as a CBOR array, or `None` if the key does not exist
+pub(crate) fn get(existing: Option<&CborOwned>, cbor_path: &CborPath) -> Option<CborOwned> {
     existing.map(|value| {
         let results = cbor_path.read(value);
         CborBuilder::new().write_array(None, |builder| {
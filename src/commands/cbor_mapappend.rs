@@ -1,13 +1,17 @@
-use crate::util::{apply_changes, CborExt, CborKeyWritable, CborPathExt, NextArgExt};
+use crate::util::{
+    apply_changes, split_format_arg, value_from_arg, CborError, CborKeyWritable, CborPathExt,
+    NextArgExt,
+};
 use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
 use cborpath::CborPath;
 use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
 use std::borrow::Cow;
 
 ///
-/// CBOR.MAPAPPEND key path [map_key map_value] [map_key map_value ...]
+/// CBOR.MAPAPPEND key path [map_key map_value] [map_key map_value ...] [FORMAT CBOR | JSON | DIAG]
 ///
-pub fn cbor_map_append(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+pub fn cbor_map_append(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let (args, format) = split_format_arg(&argv, 5)?;
     if args.len() < 5 || args.len() % 2 == 0 {
         return Err(RedisError::WrongArity);
     }
@@ -17,33 +21,36 @@ pub fn cbor_map_append(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let key_name = args.next_arg()?;
     let path = args.next_arg()?;
 
-    let mut key_value_pairs = Vec::<(&Cbor, &Cbor)>::with_capacity(args.len());
+    let mut key_value_pairs = Vec::<(Cow<Cbor>, Cow<Cbor>)>::with_capacity(args.len());
     while let (Some(key), Some(value)) = (args.next(), args.next()) {
-        key_value_pairs.push((Cbor::from_arg(key)?, Cbor::from_arg(value)?));
+        key_value_pairs.push((value_from_arg(key, format)?, value_from_arg(value, format)?));
     }
 
     let key = ctx.open_key_writable(key_name);
-    let cbor_path = CborPath::from_arg(path)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
 
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    let (new_value, map_sizes) = map_append(existing, &cbor_path, key_value_pairs);
+    let key_value_pairs = key_value_pairs
+        .iter()
+        .map(|(k, v)| (k.as_ref(), v.as_ref()))
+        .collect();
+    let (new_value, map_sizes) = map_append(existing, &cbor_path, key_value_pairs)?;
 
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.mapappend", key_name)?;
+        apply_changes(ctx, "cbor.mapappend", &argv, &key, Some(new_value))?;
     }
 
     Ok(map_sizes.into())
 }
 
-fn map_append(
+pub(crate) fn map_append(
     existing: &Cbor,
     cbor_path: &CborPath,
     key_value_pairs: Vec::<(&Cbor, &Cbor)>,
-) -> (Option<CborOwned>, Vec<RedisValue>) {
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
     let mut array_sizes = Vec::<RedisValue>::new();
 
     let new_value = cbor_path
@@ -70,9 +77,9 @@ fn map_append(
                 Ok(Some(Cow::Borrowed(old_value)))
             }
         })
-        .unwrap();
+        .map_err(CborError::path_write)?;
 
-    (new_value, array_sizes)
+    Ok((new_value, array_sizes))
 }
 
 #[cfg(test)]
@@ -92,7 +99,7 @@ mod tests {
 
         // ["$"]
         let cbor_path = CborPath::root();
-        let (new_value, array_sizes) = map_append(&cbor, &cbor_path, vec![(&key1, &item1), (&key2, &item2)]);
+        let (new_value, array_sizes) = map_append(&cbor, &cbor_path, vec![(&key1, &item1), (&key2, &item2)]).unwrap();
 
         assert_eq!(
             r#"{"a":1,"b":2,"c":3,"d":4}"#,
@@ -111,7 +118,7 @@ mod tests {
 
         // ["$", "foo"]
         let cbor_path = CborPath::builder().key("foo").build();
-        let (new_value, array_sizes) = map_append(&cbor, &cbor_path, vec![(&key1, &item1), (&key2, &item2)]);
+        let (new_value, array_sizes) = map_append(&cbor, &cbor_path, vec![(&key1, &item1), (&key2, &item2)]).unwrap();
 
         assert_eq!(
             r#"{"foo":{"a":1,"b":2,"c":3,"d":4}}"#,
@@ -130,7 +137,7 @@ mod tests {
 
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
-        let (new_value, array_sizes) = map_append(&cbor, &cbor_path, vec![(&key1, &item1), (&key2, &item2)]);
+        let (new_value, array_sizes) = map_append(&cbor, &cbor_path, vec![(&key1, &item1), (&key2, &item2)]).unwrap();
 
         assert_eq!(
             r#"{"foo":{"a":1,"b":2,"c":3,"d":4},"bar":{"f":1,"g":2,"h":3,"c":3,"d":4}}"#,
@@ -153,7 +160,7 @@ mod tests {
 
         // ["$", {"*":1}]
         let cbor_path = CborPath::builder().wildcard().build();
-        let (new_value, array_sizes) = map_append(&cbor, &cbor_path, vec![(&key1, &item1), (&key2, &item2)]);
+        let (new_value, array_sizes) = map_append(&cbor, &cbor_path, vec![(&key1, &item1), (&key2, &item2)]).unwrap();
 
         assert_eq!(
             r#"{"foo":12,"bar":{"a":1,"b":2,"c":3,"d":4}}"#,
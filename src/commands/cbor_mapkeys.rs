@@ -1,7 +1,7 @@
-use crate::util::{CborKey, CborPathExt, NextArgExt};
+use crate::util::{CborError, CborKey, CborPathExt, NextArgExt};
 use cbor_data::{CborOwned, ItemKind};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 
 ///
 /// CBOR.MAPKEYS key [path]
@@ -12,13 +12,13 @@ pub fn cbor_mapkeys(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
 
     let key = args.next_arg()?;
     let cbor_path = match args.next_arg() {
-        Ok(cbor_path) => CborPath::from_arg(cbor_path)?,
+        Ok(cbor_path) => CborPath::from_arg(ctx, cbor_path)?,
         Err(_) => CborPath::root(),
     };
 
     let key = ctx.open_key(key);
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
     Ok(map_keys(existing, &cbor_path).into())
@@ -0,0 +1,243 @@
+use crate::util::{
+    apply_changes, split_format_arg, value_from_arg, CborError, CborKeyWritable, CborPathExt,
+    NextArgExt,
+};
+use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
+use cborpath::CborPath;
+use redis_module::{Context, RedisResult, RedisString, REDIS_OK};
+use std::borrow::Cow;
+
+///
+/// CBOR.MERGE key path patch [FORMAT CBOR | JSON | DIAG]
+///
+/// Merge patch into the values at path, following RFC 7396 semantics
+pub fn cbor_merge(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let (args, format) = split_format_arg(&argv, 4)?;
+    let mut args = args.iter().skip(1);
+
+    let key_name = args.next_arg()?;
+    let path = args.next_arg()?;
+    let patch = args.next_arg()?;
+
+    if args.next().is_some() {
+        return Err(CborError::syntax().into());
+    }
+
+    let cbor_path = CborPath::from_arg(ctx, path)?;
+    let patch = value_from_arg(patch, format)?;
+
+    let key = ctx.open_key_writable(key_name);
+    let existing = key.get_cbor_value()?;
+
+    match merge(existing, &cbor_path, &patch)? {
+        MergeResult::ErrExpectedRoot => Err(CborError::expected_root().into()),
+        MergeResult::Updated(new_value) => {
+            apply_changes(ctx, "cbor.merge", &argv, &key, Some(new_value))?;
+            REDIS_OK
+        }
+        MergeResult::Deleted => {
+            apply_changes(ctx, "cbor.merge", &argv, &key, None)?;
+            REDIS_OK
+        }
+        MergeResult::NoMatch => REDIS_OK,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MergeResult {
+    ErrExpectedRoot,
+    Updated(CborOwned),
+    Deleted,
+    NoMatch,
+}
+
+fn merge(
+    existing: Option<&CborOwned>,
+    cbor_path: &CborPath,
+    patch: &Cbor,
+) -> Result<MergeResult, CborError> {
+    let result = match existing {
+        Some(existing) if cbor_path.is_root() => match merge_patch(Some(existing), patch) {
+            Some(new_value) => MergeResult::Updated(new_value),
+            None => MergeResult::Deleted,
+        },
+        Some(existing) => {
+            let new_value = cbor_path
+                .write(existing, |old_value| {
+                    Ok(merge_patch(Some(old_value), patch).map(Cow::Owned))
+                })
+                .map_err(CborError::path_write)?;
+            match new_value {
+                Some(new_value) => MergeResult::Updated(new_value),
+                None => MergeResult::NoMatch,
+            }
+        }
+        None if cbor_path.is_root() => match merge_patch(None, patch) {
+            Some(new_value) => MergeResult::Updated(new_value),
+            None => MergeResult::NoMatch,
+        },
+        None => MergeResult::ErrExpectedRoot,
+    };
+
+    Ok(result)
+}
+
+/// Apply a merge patch to `target`, `None` meaning the value is removed
+/// * a `null` patch removes the target
+/// * a map patch is merged recursively into the target,
+///   or into an empty map if the target is not a map
+/// * any other patch replaces the target
+fn merge_patch(target: Option<&Cbor>, patch: &Cbor) -> Option<CborOwned> {
+    match patch.kind() {
+        ItemKind::Null => None,
+        ItemKind::Dict(patch_entries) => {
+            let patch_entries = patch_entries.collect::<Vec<_>>();
+            let target_entries = match target.map(|t| t.kind()) {
+                Some(ItemKind::Dict(dict)) => dict.collect::<Vec<_>>(),
+                _ => Vec::new(),
+            };
+
+            Some(CborBuilder::new().write_dict(None, |builder| {
+                for (key, value) in &target_entries {
+                    match patch_entries.iter().find(|(k, _)| k == key) {
+                        Some((_, patch_value)) => {
+                            if let Some(value) = merge_patch(Some(value), patch_value) {
+                                builder
+                                    .with_cbor_key(|b| b.write_item(key), |b| b.write_item(&value));
+                            }
+                        }
+                        None => {
+                            builder.with_cbor_key(|b| b.write_item(key), |b| b.write_item(value));
+                        }
+                    }
+                }
+                for (key, patch_value) in &patch_entries {
+                    if target_entries.iter().any(|(k, _)| k == key) {
+                        continue;
+                    }
+                    if let Some(value) = merge_patch(None, patch_value) {
+                        builder.with_cbor_key(|b| b.write_item(key), |b| b.write_item(&value));
+                    }
+                }
+            }))
+        }
+        _ => Some(patch.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, merge_patch, MergeResult};
+    use crate::util::{cbor_to_diag, diag_to_cbor};
+    use cborpath::{builder::segment, CborPath};
+
+    fn patch(target: &str, patch: &str) -> Option<String> {
+        merge_patch(Some(&diag_to_cbor(target)), &diag_to_cbor(patch))
+            .map(|value| cbor_to_diag(&value))
+    }
+
+    #[test]
+    fn rfc7396_examples() {
+        let examples = [
+            (r#"{"a":"b"}"#, r#"{"a":"c"}"#, Some(r#"{"a":"c"}"#)),
+            (r#"{"a":"b"}"#, r#"{"b":"c"}"#, Some(r#"{"a":"b","b":"c"}"#)),
+            (r#"{"a":"b"}"#, r#"{"a":null}"#, Some("{}")),
+            (
+                r#"{"a":"b","b":"c"}"#,
+                r#"{"a":null}"#,
+                Some(r#"{"b":"c"}"#),
+            ),
+            (r#"{"a":["b"]}"#, r#"{"a":"c"}"#, Some(r#"{"a":"c"}"#)),
+            (r#"{"a":"c"}"#, r#"{"a":["b"]}"#, Some(r#"{"a":["b"]}"#)),
+            (
+                r#"{"a":{"b":"c"}}"#,
+                r#"{"a":{"b":"d","c":null}}"#,
+                Some(r#"{"a":{"b":"d"}}"#),
+            ),
+            (r#"{"a":[{"b":"c"}]}"#, r#"{"a":[1]}"#, Some(r#"{"a":[1]}"#)),
+            (r#"["a","b"]"#, r#"["c","d"]"#, Some(r#"["c","d"]"#)),
+            (r#"{"a":"b"}"#, r#"["c"]"#, Some(r#"["c"]"#)),
+            (r#"{"a":"foo"}"#, "null", None),
+            (r#"{"a":"foo"}"#, r#""bar""#, Some(r#""bar""#)),
+            (r#"{"e":null}"#, r#"{"a":1}"#, Some(r#"{"e":null,"a":1}"#)),
+            (r#"[1,2]"#, r#"{"a":"b","c":null}"#, Some(r#"{"a":"b"}"#)),
+            (
+                "{}",
+                r#"{"a":{"bb":{"ccc":null}}}"#,
+                Some(r#"{"a":{"bb":{}}}"#),
+            ),
+        ];
+
+        for (target, patch_diag, expected) in examples {
+            assert_eq!(
+                expected.map(str::to_owned),
+                patch(target, patch_diag),
+                "target: {target}, patch: {patch_diag}"
+            );
+        }
+    }
+
+    #[test]
+    fn non_text_keys() {
+        assert_eq!(
+            Some(r#"{1:"b",h'00':{2:3}}"#.to_owned()),
+            patch(
+                r#"{1:"a",h'00':{2:2,3:3}}"#,
+                r#"{1:"b",h'00':{2:3,3:null}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn path() {
+        let existing = diag_to_cbor(r#"{"a":{"b":1,"c":2},"d":{"a":{"e":3}}}"#);
+        let cbor_path = CborPath::builder().descendant(segment().key("a")).build();
+
+        let result = merge(
+            Some(&existing),
+            &cbor_path,
+            &diag_to_cbor(r#"{"b":null,"f":4}"#),
+        )
+        .unwrap();
+        assert!(matches!(result, MergeResult::Updated(cbor)
+            if cbor_to_diag(&cbor) == r#"{"a":{"c":2,"f":4},"d":{"a":{"e":3,"f":4}}}"#));
+
+        let cbor_path = CborPath::builder().key("d").build();
+        let result = merge(Some(&existing), &cbor_path, &diag_to_cbor("null")).unwrap();
+        assert!(matches!(result, MergeResult::Updated(cbor)
+            if cbor_to_diag(&cbor) == r#"{"a":{"b":1,"c":2}}"#));
+
+        let cbor_path = CborPath::builder().key("z").build();
+        let result = merge(Some(&existing), &cbor_path, &diag_to_cbor("1")).unwrap();
+        assert_eq!(MergeResult::NoMatch, result);
+    }
+
+    #[test]
+    fn root() {
+        let result = merge(
+            None,
+            &CborPath::root(),
+            &diag_to_cbor(r#"{"a":1,"b":null}"#),
+        )
+        .unwrap();
+        assert!(
+            matches!(result, MergeResult::Updated(cbor) if cbor_to_diag(&cbor) == r#"{"a":1}"#)
+        );
+
+        let result = merge(
+            None,
+            &CborPath::builder().key("a").build(),
+            &diag_to_cbor("1"),
+        )
+        .unwrap();
+        assert_eq!(MergeResult::ErrExpectedRoot, result);
+
+        let result = merge(
+            Some(&diag_to_cbor("[1]")),
+            &CborPath::root(),
+            &diag_to_cbor("null"),
+        )
+        .unwrap();
+        assert_eq!(MergeResult::Deleted, result);
+    }
+}
@@ -1,10 +1,10 @@
-use crate::util::{CborKey, CborPathExt};
+use crate::util::{split_output_format_arg, value_to_reply, CborKey, CborPathExt, Format};
 use cbor_data::{CborBuilder, CborOwned, Writer};
 use cborpath::CborPath;
 use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
 
 ///
-/// CBOR.MGET key [key ...] path
+/// CBOR.MGET key [key ...] path [FORMAT CBOR | JSON | DIAG]
 ///
 /// Return the values at path from multiple key arguments
 pub fn cbor_mget(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
@@ -12,19 +12,21 @@ pub fn cbor_mget(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
 
+    let (args, format) = split_output_format_arg(&args, 3)?;
+
     let keys = &args[1..args.len() - 1];
     let path = &args[args.len() - 1];
 
-    let cbor_path = CborPath::from_arg(path)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
 
     let keys = keys.iter().map(|key| ctx.open_key(key)).collect::<Vec<_>>();
 
     let existing_values = keys.iter().map(|key| key.get_cbor_value());
 
-    multiple_get(existing_values, &cbor_path)
+    multiple_get(existing_values, &cbor_path, format)
 }
 
-fn multiple_get<'a, I>(existing_values: I, cbor_path: &CborPath) -> RedisResult
+fn multiple_get<'a, I>(existing_values: I, cbor_path: &CborPath, format: Format) -> RedisResult
 where
     I: Iterator<Item = Result<Option<&'a CborOwned>, RedisError>>,
 {
@@ -37,7 +39,7 @@ where
                         builder.write_item(result);
                     }
                 });
-                RedisValue::StringBuffer(new_value.into_vec())
+                value_to_reply(new_value, format)
             }))
         })
         .collect::<Result<Vec<RedisValue>, RedisError>>()?
@@ -47,7 +49,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::multiple_get;
-    use crate::util::{diag_to_bytes, diag_to_cbor};
+    use crate::util::{diag_to_bytes, diag_to_cbor, Format};
     use cborpath::{builder::segment, CborPath};
     use redis_module::{RedisError, RedisValue};
 
@@ -57,7 +59,7 @@ mod tests {
         let value2 = diag_to_cbor(r#"{"a":4, "b": 5, "nested": {"a": 6}, "c": null}"#);
         let values = vec![Ok(Some(&value1)), Ok(Some(&value2)), Ok(None)];
         let path = CborPath::builder().descendant(segment().key("a")).build();
-        let result = multiple_get(values.into_iter(), &path)?;
+        let result = multiple_get(values.into_iter(), &path, Format::Cbor)?;
         assert_eq!(
             RedisValue::Array(vec![
                 RedisValue::StringBuffer(diag_to_bytes("[1,3]")),
@@ -76,9 +78,28 @@ mod tests {
         let value2 = diag_to_cbor(r#"{"a":4, "b": 5, "nested": {"a": 6}, "c": null}"#);
         let values = vec![Ok(Some(&value1)), Ok(Some(&value2)), Err(RedisError::Str("MyError"))];
         let path = CborPath::builder().descendant(segment().key("a")).build();
-        let result = multiple_get(values.into_iter(), &path);
+        let result = multiple_get(values.into_iter(), &path, Format::Cbor);
         assert!(matches!(result, Err(RedisError::Str(e)) if e == "MyError"));
 
         Ok(())
     }
+
+    #[test]
+    fn json() -> Result<(), RedisError> {
+        let value1 = diag_to_cbor(r#"{"a":1, "b": 2, "nested": {"a": h'00ff'}, "c": null}"#);
+        let value2 = diag_to_cbor(r#"{"a":4, "b": 5, "nested": {"a": 6.5}, "c": null}"#);
+        let values = vec![Ok(Some(&value1)), Ok(Some(&value2)), Ok(None)];
+        let path = CborPath::builder().descendant(segment().key("a")).build();
+        let result = multiple_get(values.into_iter(), &path, Format::Json)?;
+        assert_eq!(
+            RedisValue::Array(vec![
+                RedisValue::BulkString(r#"[1,"AP8"]"#.to_string()),
+                RedisValue::BulkString("[4,6.5]".to_string()),
+                RedisValue::Null
+            ]),
+            result
+        );
+
+        Ok(())
+    }
 }
@@ -1,33 +1,57 @@
-use super::num_operation::{num_operation, Number};
-use crate::util::{apply_changes, CborExt, CborKeyWritable, CborPathExt, NextArgExt};
+use super::{
+    num_operation::{num_operation, NumOptions},
+    number::{add, Number},
+    path_create::{create_path, definite_segments},
+};
+use crate::util::{
+    apply_changes, path_bytes, split_format_arg, value_from_arg, CborError, CborKeyWritable,
+    CborPathExt, NextArgExt,
+};
 use cbor_data::{Cbor, CborOwned};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 
 ///
-/// CBOR.NUMINCRBY key path value
+/// CBOR.NUMINCRBY key path value [SATURATE | PROMOTE] [PRESERVE] [UPSERT] [FORMAT CBOR | JSON | DIAG]
 ///
 /// Increment the number value stored at path by number
-pub fn cbor_num_incr_by(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+pub fn cbor_num_incr_by(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let (args, format) = split_format_arg(&argv, 4)?;
     let mut args = args.iter().skip(1);
 
     let key_name = args.next_arg()?;
     let path = args.next_arg()?;
     let value = args.next_arg()?;
+    let mut upsert = false;
+    let options = NumOptions::from_args(args.filter(|arg| {
+        let is_upsert = !upsert && arg.as_slice().eq_ignore_ascii_case(b"UPSERT");
+        upsert |= is_upsert;
+        !is_upsert
+    }))?;
 
     let key = ctx.open_key_writable(key_name);
-    let cbor_path = CborPath::from_arg(path)?;
-    let value = Cbor::from_arg(value)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
+    let value = value_from_arg(value, format)?;
 
-    let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+    let existing = key.get_cbor_value()?;
+    let (new_value, new_nums) = match existing {
+        Some(existing) if !upsert || !cbor_path.read(existing).is_empty() => {
+            num_incr_by(existing, &cbor_path, &value, options)?
+        }
+        None if !upsert => return Err(CborError::nonexistent_key().into()),
+        existing => {
+            let path = path_bytes(ctx, path)?;
+            let (new_value, counter) =
+                upsert_counter(existing.map(|existing| &**existing), &path, &value)?;
+            (
+                Some(new_value),
+                vec![RedisValue::StringBuffer(counter.into_vec())],
+            )
+        }
     };
 
-    let (new_value, new_nums) = num_incr_by(existing, &cbor_path, value);
-
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.numincrby", key_name)?;
+        apply_changes(ctx, "cbor.numincrby", &argv, &key, Some(new_value))?;
     }
 
     Ok(new_nums.into())
@@ -37,24 +61,42 @@ fn num_incr_by(
     existing: &CborOwned,
     cbor_path: &CborPath,
     value: &Cbor,
-) -> (Option<CborOwned>, Vec<RedisValue>) {
-    num_operation(existing, cbor_path, value, |v1, v2| match (v1, v2) {
-        (Number::Signed(v1), Number::Signed(v2)) => Some(Number::Signed(v1 + v2)),
-        (Number::Signed(v1), Number::Unsigned(v2)) => Some(Number::Signed(v1 + v2 as i64)),
-        (Number::Signed(v1), Number::Float(v2)) => Some(Number::Float(v1 as f64 + v2)),
-        (Number::Unsigned(v1), Number::Signed(v2)) => Some(Number::Signed(v1 as i64 + v2)),
-        (Number::Unsigned(v1), Number::Unsigned(v2)) => Some(Number::Unsigned(v1 + v2)),
-        (Number::Unsigned(v1), Number::Float(v2)) => Some(Number::Float(v1 as f64 + v2)),
-        (Number::Float(v1), Number::Signed(v2)) => Some(Number::Float(v1 + v2 as f64)),
-        (Number::Float(v1), Number::Unsigned(v2)) => Some(Number::Float(v1 + v2 as f64)),
-        (Number::Float(v1), Number::Float(v2)) => Some(Number::Float(v1 + v2)),
+    options: NumOptions,
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
+    num_operation(existing, cbor_path, value, options, |v1, v2| {
+        add(v1, v2).map(Some)
     })
 }
 
+/// Create the counter designated by `path`, starting from 0 and incremented by `value`,
+/// along with its missing parents, as `CBOR.SET ... MKPATH` would.
+///
+/// `existing` is `None` when the key does not exist.
+/// Returns the new document and the new counter.
+fn upsert_counter(
+    existing: Option<&Cbor>,
+    path: &[u8],
+    value: &Cbor,
+) -> Result<(CborOwned, CborOwned), CborError> {
+    let segments = definite_segments(path).ok_or_else(|| {
+        CborError::Err("UPSERT requires a path made of keys and indexes only".into())
+    })?;
+
+    let Ok(value) = Number::try_from(value) else {
+        return Err(CborError::Err("UPSERT requires a number value".into()));
+    };
+    let counter = CborOwned::from(add(Number::Integer(0), value)?);
+
+    Ok((create_path(existing, &segments, &counter)?, counter))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::num_incr_by;
-    use crate::util::{cbor_to_diag, diag_to_bytes, diag_to_cbor};
+    use super::{num_incr_by, upsert_counter};
+    use crate::commands::num_operation::{NumOptions, Overflow};
+    use crate::path_syntax;
+    use crate::util::{cbor_to_diag, diag_to_bytes, diag_to_cbor, CborError};
+    use cbor_data::CborOwned;
     use cborpath::{builder::segment, CborPath};
     use redis_module::RedisValue;
 
@@ -64,7 +106,8 @@ mod tests {
         let cbor_path = CborPath::builder().wildcard().build();
 
         let value = diag_to_cbor("1");
-        let (new_value, nem_nums) = num_incr_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!("[3,-1]", cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![
@@ -75,7 +118,8 @@ mod tests {
         );
 
         let value = diag_to_cbor("-1");
-        let (new_value, nem_nums) = num_incr_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!("[1,-3]", cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![
@@ -92,7 +136,8 @@ mod tests {
         let cbor_path = CborPath::builder().wildcard().build();
 
         let value = diag_to_cbor("2.0");
-        let (new_value, nem_nums) = num_incr_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!(diag_to_cbor("[14.0,14.0]"), new_value.unwrap());
         assert_eq!(
             vec![
@@ -103,7 +148,8 @@ mod tests {
         );
 
         let value = diag_to_cbor("2");
-        let (new_value, nem_nums) = num_incr_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!(diag_to_cbor("[14.0,14]"), new_value.unwrap());
         assert_eq!(
             vec![
@@ -114,7 +160,8 @@ mod tests {
         );
 
         let value = diag_to_cbor("-2");
-        let (new_value, nem_nums) = num_incr_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!(diag_to_cbor("[10.0,10]"), new_value.unwrap());
         assert_eq!(
             vec![
@@ -131,7 +178,8 @@ mod tests {
         let cbor_path = CborPath::builder().descendant(segment().key("a")).build();
         let value = diag_to_cbor("2");
 
-        let (new_value, nem_nums) = num_incr_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!(
             r#"{"a":"b","b":[{"a":4},{"a":7},{"a":"c"}]}"#,
             cbor_to_diag(&new_value.unwrap())
@@ -146,4 +194,253 @@ mod tests {
             nem_nums
         );
     }
+
+    #[test]
+    fn full_integer_range() {
+        let cbor = diag_to_cbor("[18446744073709551615,-18446744073709551616,-1]");
+        let cbor_path = CborPath::builder().wildcard().build();
+
+        let value = diag_to_cbor("-18446744073709551616");
+        let result = num_incr_by(&cbor, &cbor_path, &value, NumOptions::default());
+        assert_eq!(Some(CborError::integer_overflow()), result.err());
+
+        let value = diag_to_cbor("-9223372036854775808");
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, Overflow::Saturate.into()).unwrap();
+        assert_eq!(
+            "[9223372036854775807,-18446744073709551616,-9223372036854775809]",
+            cbor_to_diag(&new_value.unwrap())
+        );
+        assert_eq!(
+            vec![
+                RedisValue::StringBuffer(diag_to_bytes("9223372036854775807")),
+                RedisValue::StringBuffer(diag_to_bytes("-18446744073709551616")),
+                RedisValue::StringBuffer(diag_to_bytes("-9223372036854775809")),
+            ],
+            nem_nums
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let cbor = diag_to_cbor(r#"{"a":1,"b":18446744073709551615}"#);
+        let cbor_path = CborPath::builder().wildcard().build();
+        let value = diag_to_cbor("1");
+
+        let result = num_incr_by(&cbor, &cbor_path, &value, NumOptions::default());
+        assert_eq!(Some(CborError::integer_overflow()), result.err());
+
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, Overflow::Saturate.into()).unwrap();
+        assert_eq!(
+            r#"{"a":2,"b":18446744073709551615}"#,
+            cbor_to_diag(&new_value.unwrap())
+        );
+        assert_eq!(
+            vec![
+                RedisValue::StringBuffer(diag_to_bytes("2")),
+                RedisValue::StringBuffer(diag_to_bytes("18446744073709551615")),
+            ],
+            nem_nums
+        );
+
+        let cbor = diag_to_cbor("[-18446744073709551616]");
+        let value = diag_to_cbor("-1");
+
+        let result = num_incr_by(&cbor, &cbor_path, &value, NumOptions::default());
+        assert_eq!(Some(CborError::integer_overflow()), result.err());
+
+        let (new_value, _) =
+            num_incr_by(&cbor, &cbor_path, &value, Overflow::Saturate.into()).unwrap();
+        assert_eq!("[-18446744073709551616]", cbor_to_diag(&new_value.unwrap()));
+    }
+
+    #[test]
+    fn bignum() {
+        let cbor = diag_to_cbor("[18446744073709551615,2(h'010000000000000000')]");
+        let cbor_path = CborPath::builder().wildcard().build();
+        let value = diag_to_cbor("1");
+
+        let result = num_incr_by(&cbor, &cbor_path, &value, NumOptions::default());
+        assert_eq!(Some(CborError::integer_overflow()), result.err());
+
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, Overflow::Promote.into()).unwrap();
+        assert_eq!(
+            "[2(h'010000000000000000'),2(h'010000000000000001')]",
+            cbor_to_diag(&new_value.unwrap())
+        );
+        assert_eq!(
+            vec![
+                RedisValue::StringBuffer(diag_to_bytes("2(h'010000000000000000')")),
+                RedisValue::StringBuffer(diag_to_bytes("2(h'010000000000000001')")),
+            ],
+            nem_nums
+        );
+
+        // a bignum operand always gives a bignum result, back to a basic integer when it fits
+        let cbor = diag_to_cbor("[2(h'010000000000000000'),1]");
+        let value = diag_to_cbor("3(h'00')");
+        let (new_value, _) = num_incr_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
+        assert_eq!(
+            "[18446744073709551615,0]",
+            cbor_to_diag(&new_value.unwrap())
+        );
+    }
+
+    #[test]
+    fn decimal() {
+        let cbor = diag_to_cbor(r#"{"balance":4([-2,1050]),"count":3}"#);
+        let cbor_path = CborPath::builder().wildcard().build();
+        let value = diag_to_cbor("4([-3,-2125])");
+
+        let (new_value, nem_nums) =
+            num_incr_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
+        assert_eq!(
+            r#"{"balance":4([-3,8375]),"count":4([-3,875])}"#,
+            cbor_to_diag(&new_value.unwrap())
+        );
+        assert_eq!(
+            vec![
+                RedisValue::StringBuffer(diag_to_bytes("4([-3,8375])")),
+                RedisValue::StringBuffer(diag_to_bytes("4([-3,875])")),
+            ],
+            nem_nums
+        );
+    }
+
+    #[test]
+    fn preserve() {
+        let cbor = CborOwned::unchecked(diag_to_bytes("[1.5_1,1.5_2,1.5_3,2,-2]"));
+        let cbor_path = CborPath::builder().wildcard().build();
+        let options = NumOptions {
+            overflow: Overflow::Error,
+            preserve: true,
+        };
+
+        let value = diag_to_cbor("1");
+        let (new_value, _) = num_incr_by(&cbor, &cbor_path, &value, options).unwrap();
+        assert_eq!(
+            diag_to_bytes("[2.5_1,2.5_2,2.5_3,3,-1]"),
+            new_value.unwrap().into_vec()
+        );
+
+        let value = diag_to_cbor("-1.0");
+        let (new_value, _) = num_incr_by(&cbor, &cbor_path, &value, options).unwrap();
+        assert_eq!(
+            diag_to_bytes("[0.5_1,0.5_2,0.5_3,1,-3]"),
+            new_value.unwrap().into_vec()
+        );
+        // without PRESERVE, floats are written in their shortest form
+        let (new_value, _) = num_incr_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
+        assert_eq!(diag_to_cbor("[0.5,0.5,0.5,1.0,-3.0]"), new_value.unwrap());
+
+        let cbor_path = CborPath::builder().index(0).build();
+        let value = diag_to_cbor("0.1");
+        let result = num_incr_by(&cbor, &cbor_path, &value, options);
+        assert_eq!(
+            Some(CborError::Err(
+                "the result cannot be represented as a float16".into()
+            )),
+            result.err()
+        );
+
+        let cbor_path = CborPath::builder().index(3).build();
+        let value = diag_to_cbor("0.5");
+        let result = num_incr_by(&cbor, &cbor_path, &value, options);
+        assert_eq!(
+            Some(CborError::Err(
+                "the result cannot be represented as an unsigned integer".into()
+            )),
+            result.err()
+        );
+
+        let cbor_path = CborPath::builder().index(4).build();
+        let value = diag_to_cbor("2");
+        let result = num_incr_by(&cbor, &cbor_path, &value, options);
+        assert_eq!(
+            Some(CborError::Err(
+                "the result cannot be represented as a negative integer".into()
+            )),
+            result.err()
+        );
+    }
+
+    #[test]
+    fn upsert() {
+        let path = path_syntax::parse(r#"$.pages["/home"].hits"#).unwrap();
+        let value = diag_to_cbor("1");
+
+        let (new_value, counter) = upsert_counter(None, &path, &value).unwrap();
+        assert_eq!(
+            r#"{"pages":{"/home":{"hits":1}}}"#,
+            cbor_to_diag(&new_value)
+        );
+        assert_eq!("1", cbor_to_diag(&counter));
+
+        let cbor = diag_to_cbor(r#"{"pages":{"/about":{"hits":3}},"total":3}"#);
+        let (new_value, _) = upsert_counter(Some(&cbor), &path, &value).unwrap();
+        assert_eq!(
+            r#"{"pages":{"/about":{"hits":3},"/home":{"hits":1}},"total":3}"#,
+            cbor_to_diag(&new_value)
+        );
+
+        let value = diag_to_cbor("2.5");
+        let (new_value, _) = upsert_counter(None, &path, &value).unwrap();
+        assert_eq!(
+            diag_to_cbor(r#"{"pages":{"/home":{"hits":2.5}}}"#),
+            new_value
+        );
+
+        // indexes create arrays, like CBOR.SET ... MKPATH
+        let path = path_syntax::parse("$.pages[0].hits").unwrap();
+        let value = diag_to_cbor("1");
+        let (new_value, _) = upsert_counter(None, &path, &value).unwrap();
+        assert_eq!(r#"{"pages":[{"hits":1}]}"#, cbor_to_diag(&new_value));
+
+        let cbor = diag_to_cbor(r#"{"pages":[{"hits":3}]}"#);
+        let path = path_syntax::parse("$.pages[1].hits").unwrap();
+        let (new_value, _) = upsert_counter(Some(&cbor), &path, &value).unwrap();
+        assert_eq!(
+            r#"{"pages":[{"hits":3},{"hits":1}]}"#,
+            cbor_to_diag(&new_value)
+        );
+    }
+
+    #[test]
+    fn upsert_errors() {
+        let value = diag_to_cbor("1");
+
+        let path = path_syntax::parse("$.pages[*].hits").unwrap();
+        assert_eq!(
+            Some(CborError::Err(
+                "UPSERT requires a path made of keys and indexes only".into()
+            )),
+            upsert_counter(None, &path, &value).err()
+        );
+
+        let path = path_syntax::parse("$.pages[2].hits").unwrap();
+        let cbor = diag_to_cbor(r#"{"pages":[]}"#);
+        assert_eq!(
+            Some(CborError::Err(
+                "cannot create the path - index out of range".into()
+            )),
+            upsert_counter(Some(&cbor), &path, &value).err()
+        );
+
+        let path = path_syntax::parse("$.pages.home").unwrap();
+        let cbor = diag_to_cbor(r#"{"pages":[]}"#);
+        assert_eq!(
+            Some(CborError::WrongType(
+                "cannot create the path - a parent value is not a map".into()
+            )),
+            upsert_counter(Some(&cbor), &path, &value).err()
+        );
+
+        let value = diag_to_cbor(r#""1""#);
+        assert_eq!(
+            Some(CborError::Err("UPSERT requires a number value".into())),
+            upsert_counter(None, &path, &value).err()
+        );
+    }
 }
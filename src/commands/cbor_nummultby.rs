@@ -1,33 +1,40 @@
-use super::num_operation::{num_operation, Number};
-use crate::util::{apply_changes, CborExt, CborKeyWritable, CborPathExt, NextArgExt};
+use super::{
+    num_operation::{num_operation, NumOptions},
+    number::mul,
+};
+use crate::util::{
+    apply_changes, split_format_arg, value_from_arg, CborError, CborKeyWritable, CborPathExt,
+    NextArgExt,
+};
 use cbor_data::{Cbor, CborOwned};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 
 ///
-/// CBOR.NUMMULTBY key path value
+/// CBOR.NUMMULTBY key path value [SATURATE | PROMOTE] [PRESERVE] [FORMAT CBOR | JSON | DIAG]
 ///
 /// Multiply the number value stored at path by number
-pub fn cbor_num_mult_by(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+pub fn cbor_num_mult_by(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let (args, format) = split_format_arg(&argv, 4)?;
     let mut args = args.iter().skip(1);
 
     let key_name = args.next_arg()?;
     let path = args.next_arg()?;
     let value = args.next_arg()?;
+    let options = NumOptions::from_args(args)?;
 
     let key = ctx.open_key_writable(key_name);
-    let cbor_path = CborPath::from_arg(path)?;
-    let value = Cbor::from_arg(value)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
+    let value = value_from_arg(value, format)?;
 
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    let (new_value, new_nums) = num_mult_by(existing, &cbor_path, value);
+    let (new_value, new_nums) = num_mult_by(existing, &cbor_path, &value, options)?;
 
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.nummultby", key_name)?;
+        apply_changes(ctx, "cbor.nummultby", &argv, &key, Some(new_value))?;
     }
 
     Ok(new_nums.into())
@@ -37,24 +44,18 @@ fn num_mult_by(
     existing: &CborOwned,
     cbor_path: &CborPath,
     value: &Cbor,
-) -> (Option<CborOwned>, Vec<RedisValue>) {
-    num_operation(existing, cbor_path, value, |v1, v2| match (v1, v2) {
-        (Number::Signed(v1), Number::Signed(v2)) => Some(Number::Signed(v1 * v2)),
-        (Number::Signed(v1), Number::Unsigned(v2)) => Some(Number::Signed(v1 * v2 as i64)),
-        (Number::Signed(v1), Number::Float(v2)) => Some(Number::Float(v1 as f64 * v2)),
-        (Number::Unsigned(v1), Number::Signed(v2)) => Some(Number::Signed(v1 as i64 * v2)),
-        (Number::Unsigned(v1), Number::Unsigned(v2)) => Some(Number::Unsigned(v1 * v2)),
-        (Number::Unsigned(v1), Number::Float(v2)) => Some(Number::Float(v1 as f64 * v2)),
-        (Number::Float(v1), Number::Signed(v2)) => Some(Number::Float(v1 * v2 as f64)),
-        (Number::Float(v1), Number::Unsigned(v2)) => Some(Number::Float(v1 * v2 as f64)),
-        (Number::Float(v1), Number::Float(v2)) => Some(Number::Float(v1 * v2)),
+    options: NumOptions,
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
+    num_operation(existing, cbor_path, value, options, |v1, v2| {
+        mul(v1, v2).map(Some)
     })
 }
 
 #[cfg(test)]
 mod tests {
     use super::num_mult_by;
-    use crate::util::{cbor_to_diag, diag_to_bytes, diag_to_cbor};
+    use crate::commands::num_operation::{NumOptions, Overflow};
+    use crate::util::{cbor_to_diag, diag_to_bytes, diag_to_cbor, CborError};
     use cborpath::{builder::segment, CborPath};
     use redis_module::RedisValue;
 
@@ -64,7 +65,8 @@ mod tests {
         let cbor_path = CborPath::builder().wildcard().build();
 
         let value = diag_to_cbor("2");
-        let (new_value, nem_nums) = num_mult_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_mult_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!("[4,-4]", cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![
@@ -75,7 +77,8 @@ mod tests {
         );
 
         let value = diag_to_cbor("-2");
-        let (new_value, nem_nums) = num_mult_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_mult_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!("[-4,4]", cbor_to_diag(&new_value.unwrap()));
         assert_eq!(
             vec![
@@ -92,7 +95,8 @@ mod tests {
         let cbor_path = CborPath::builder().wildcard().build();
 
         let value = diag_to_cbor("2.0");
-        let (new_value, nem_nums) = num_mult_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_mult_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!(diag_to_cbor("[24.0,24.0]"), new_value.unwrap());
         assert_eq!(
             vec![
@@ -103,7 +107,8 @@ mod tests {
         );
 
         let value = diag_to_cbor("2");
-        let (new_value, nem_nums) = num_mult_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_mult_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!(diag_to_cbor("[24.0,24]"), new_value.unwrap());
         assert_eq!(
             vec![
@@ -114,7 +119,8 @@ mod tests {
         );
 
         let value = diag_to_cbor("-2");
-        let (new_value, nem_nums) = num_mult_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_mult_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!(diag_to_cbor("[-24.0,-24]"), new_value.unwrap());
         assert_eq!(
             vec![
@@ -131,7 +137,8 @@ mod tests {
         let cbor_path = CborPath::builder().descendant(segment().key("a")).build();
         let value = diag_to_cbor("2");
 
-        let (new_value, nem_nums) = num_mult_by(&cbor, &cbor_path, &value);
+        let (new_value, nem_nums) =
+            num_mult_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
         assert_eq!(
             r#"{"a":"b","b":[{"a":4},{"a":10},{"a":"c"}]}"#,
             cbor_to_diag(&new_value.unwrap())
@@ -146,4 +153,48 @@ mod tests {
             nem_nums
         );
     }
+
+    #[test]
+    fn overflow() {
+        let cbor = diag_to_cbor("[4294967296,-4294967296,3]");
+        let cbor_path = CborPath::builder().wildcard().build();
+
+        let value = diag_to_cbor("4294967296");
+        let result = num_mult_by(&cbor, &cbor_path, &value, NumOptions::default());
+        assert_eq!(Some(CborError::integer_overflow()), result.err());
+
+        let (new_value, nem_nums) =
+            num_mult_by(&cbor, &cbor_path, &value, Overflow::Saturate.into()).unwrap();
+        assert_eq!(
+            "[18446744073709551615,-18446744073709551616,12884901888]",
+            cbor_to_diag(&new_value.unwrap())
+        );
+        assert_eq!(
+            vec![
+                RedisValue::StringBuffer(diag_to_bytes("18446744073709551615")),
+                RedisValue::StringBuffer(diag_to_bytes("-18446744073709551616")),
+                RedisValue::StringBuffer(diag_to_bytes("12884901888")),
+            ],
+            nem_nums
+        );
+
+        let cbor = diag_to_cbor("[-18446744073709551616]");
+        let value = diag_to_cbor("-18446744073709551616");
+        let (new_value, _) =
+            num_mult_by(&cbor, &cbor_path, &value, Overflow::Saturate.into()).unwrap();
+        assert_eq!("[18446744073709551615]", cbor_to_diag(&new_value.unwrap()));
+    }
+
+    #[test]
+    fn full_integer_range() {
+        let cbor = diag_to_cbor("[9223372036854775808,-9223372036854775808]");
+        let cbor_path = CborPath::builder().wildcard().build();
+        let value = diag_to_cbor("-1");
+
+        let (new_value, _) = num_mult_by(&cbor, &cbor_path, &value, NumOptions::default()).unwrap();
+        assert_eq!(
+            "[-9223372036854775808,9223372036854775808]",
+            cbor_to_diag(&new_value.unwrap())
+        );
+    }
 }
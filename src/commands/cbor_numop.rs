@@ -0,0 +1,283 @@
+use super::{
+    num_operation::{num_update, NumOptions},
+    number::{abs, bit_and, bit_or, bit_xor, compare, div, max, min, pow, rem, Number},
+};
+use crate::util::{
+    apply_changes, split_format_arg, value_from_arg, CborError, CborKeyWritable, CborPathExt,
+    Format, NextArgExt,
+};
+use cbor_data::CborOwned;
+use cborpath::CborPath;
+use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use std::cmp::Ordering;
+
+///
+/// CBOR.NUMOP key path op [operand [operand]] [SATURATE | PROMOTE] [PRESERVE] [FORMAT CBOR | JSON | DIAG]
+///
+/// Apply a numeric operation to the number values stored at path
+pub fn cbor_num_op(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let (args, format) = split_format_arg(&argv, 4)?;
+    let mut args = args.iter().skip(1);
+
+    let key_name = args.next_arg()?;
+    let path = args.next_arg()?;
+    let op = args.next_arg()?;
+    let op = NumOp::from_args(op.try_as_str()?, &mut args, format)?;
+    let options = NumOptions::from_args(args)?;
+
+    let key = ctx.open_key_writable(key_name);
+    let cbor_path = CborPath::from_arg(ctx, path)?;
+
+    let Some(existing) = key.get_cbor_value()? else {
+        return Err(CborError::nonexistent_key().into());
+    };
+
+    let (new_value, new_nums) = num_op(existing, &cbor_path, &op, options)?;
+
+    if let Some(new_value) = new_value {
+        apply_changes(ctx, "cbor.numop", &argv, &key, Some(new_value))?;
+    }
+
+    Ok(new_nums.into())
+}
+
+const UNKNOWN_OPERATION: &str =
+    "unknown operation - expected MIN, MAX, MOD, POW, DIV, ABS, AND, OR, XOR or CLAMP";
+
+#[derive(Debug)]
+enum NumOp {
+    Min(Number),
+    Max(Number),
+    Mod(Number),
+    Pow(Number),
+    Div(Number),
+    Abs,
+    And(Number),
+    Or(Number),
+    Xor(Number),
+    Clamp(Number, Number),
+}
+
+impl NumOp {
+    fn from_args<'a>(
+        op: &str,
+        args: &mut impl Iterator<Item = &'a RedisString>,
+        format: Format,
+    ) -> Result<Self, RedisError> {
+        let mut operand = || -> Result<Number, RedisError> {
+            let value = value_from_arg(args.next_arg()?, format)?;
+            Number::try_from(&*value)
+                .map_err(|_| CborError::Err("operands must be numbers".into()).into())
+        };
+
+        let op = match op.to_ascii_uppercase().as_str() {
+            "MIN" => NumOp::Min(operand()?),
+            "MAX" => NumOp::Max(operand()?),
+            "MOD" => NumOp::Mod(operand()?),
+            "POW" => NumOp::Pow(operand()?),
+            "DIV" => NumOp::Div(operand()?),
+            "ABS" => NumOp::Abs,
+            "AND" => NumOp::And(operand()?),
+            "OR" => NumOp::Or(operand()?),
+            "XOR" => NumOp::Xor(operand()?),
+            "CLAMP" => NumOp::Clamp(operand()?, operand()?),
+            _ => return Err(CborError::Err(UNKNOWN_OPERATION.into()).into()),
+        };
+
+        match &op {
+            NumOp::And(n) | NumOp::Or(n) | NumOp::Xor(n)
+                if !matches!(n, Number::Integer(_) | Number::BigNum(_)) =>
+            {
+                Err(CborError::Err("AND, OR and XOR operands must be integers".into()).into())
+            }
+            NumOp::Clamp(lo, hi)
+                if !matches!(compare(lo, hi), Some(Ordering::Less | Ordering::Equal)) =>
+            {
+                Err(CborError::Err("CLAMP lower bound is greater than upper bound".into()).into())
+            }
+            _ => Ok(op),
+        }
+    }
+
+    fn has_bignum_operand(&self) -> bool {
+        match self {
+            NumOp::Min(n)
+            | NumOp::Max(n)
+            | NumOp::Mod(n)
+            | NumOp::Pow(n)
+            | NumOp::Div(n)
+            | NumOp::And(n)
+            | NumOp::Or(n)
+            | NumOp::Xor(n) => n.is_bignum(),
+            NumOp::Abs => false,
+            NumOp::Clamp(lo, hi) => lo.is_bignum() || hi.is_bignum(),
+        }
+    }
+
+    fn apply(&self, number: Number) -> Result<Option<Number>, CborError> {
+        match self {
+            NumOp::Min(n) => Ok(min(number, n.clone())),
+            NumOp::Max(n) => Ok(max(number, n.clone())),
+            NumOp::Mod(n) => rem(number, n.clone()).map(Some),
+            NumOp::Pow(n) => pow(number, n.clone()).map(Some),
+            NumOp::Div(n) => div(number, n.clone()).map(Some),
+            NumOp::Abs => Ok(Some(abs(number))),
+            NumOp::And(n) => Ok(bit_and(number, n.clone())),
+            NumOp::Or(n) => Ok(bit_or(number, n.clone())),
+            NumOp::Xor(n) => Ok(bit_xor(number, n.clone())),
+            NumOp::Clamp(lo, hi) => Ok(max(number, lo.clone()).and_then(|n| min(n, hi.clone()))),
+        }
+    }
+}
+
+fn num_op(
+    existing: &CborOwned,
+    cbor_path: &CborPath,
+    op: &NumOp,
+    options: NumOptions,
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
+    num_update(
+        existing,
+        cbor_path,
+        options,
+        op.has_bignum_operand(),
+        |number| op.apply(number),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{num_op, NumOp};
+    use crate::{
+        commands::{
+            num_operation::{NumOptions, Overflow},
+            number::Number,
+        },
+        util::{cbor_to_diag, diag_to_bytes, diag_to_cbor, CborError},
+    };
+    use cbor_data::CborOwned;
+    use cborpath::CborPath;
+    use redis_module::RedisValue;
+
+    fn number(diag: &str) -> Number {
+        Number::try_from(&*diag_to_cbor(diag)).unwrap()
+    }
+
+    fn apply(cbor: &str, op: NumOp) -> Result<CborOwned, CborError> {
+        let cbor = diag_to_cbor(cbor);
+        let cbor_path = CborPath::builder().wildcard().build();
+        let (new_value, _) = num_op(&cbor, &cbor_path, &op, NumOptions::default())?;
+        Ok(new_value.unwrap())
+    }
+
+    #[test]
+    fn operations() {
+        let cbor = "[-7,2,4([-2,1050]),2.5]";
+
+        assert_eq!(
+            Ok(diag_to_cbor("[-7,2,2,2]")),
+            apply(cbor, NumOp::Min(number("2")))
+        );
+        assert_eq!(
+            Ok(diag_to_cbor("[2,2,4([-2,1050]),2.5]")),
+            apply(cbor, NumOp::Max(number("2")))
+        );
+        assert_eq!(
+            Ok(diag_to_cbor("[-1,0,4([-2,50]),0.5]")),
+            apply(cbor, NumOp::Mod(number("2")))
+        );
+        assert_eq!(
+            Ok(diag_to_cbor("[-3,1,4([-2,525]),1.25]")),
+            apply(cbor, NumOp::Div(number("2")))
+        );
+        assert_eq!(
+            Ok(diag_to_cbor("[49,4,4([-4,1102500]),6.25]")),
+            apply(cbor, NumOp::Pow(number("2")))
+        );
+        assert_eq!(
+            Ok(diag_to_cbor("[7,2,4([-2,1050]),2.5]")),
+            apply(cbor, NumOp::Abs)
+        );
+    }
+
+    #[test]
+    fn bitwise() {
+        let cbor = diag_to_cbor(r#"[12,-1,1.0,"a"]"#);
+        let cbor_path = CborPath::builder().wildcard().build();
+
+        let (new_value, new_nums) = num_op(
+            &cbor,
+            &cbor_path,
+            &NumOp::And(number("6")),
+            NumOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(diag_to_cbor(r#"[4,6,1.0,"a"]"#), new_value.unwrap());
+        assert_eq!(
+            vec![
+                RedisValue::StringBuffer(diag_to_bytes("4")),
+                RedisValue::StringBuffer(diag_to_bytes("6")),
+                RedisValue::Null,
+                RedisValue::Null,
+            ],
+            new_nums
+        );
+
+        assert_eq!(
+            Ok(diag_to_cbor("[14,-1]")),
+            apply("[12,-1]", NumOp::Or(number("6")))
+        );
+        assert_eq!(
+            Ok(diag_to_cbor("[10,-7]")),
+            apply("[12,-1]", NumOp::Xor(number("6")))
+        );
+    }
+
+    #[test]
+    fn clamp() {
+        let cbor = diag_to_cbor(r#"{"stock":{"apples":3,"pears":-2,"plums":120,"kiwis":"n/a"}}"#);
+        let cbor_path = CborPath::builder().key("stock").wildcard().build();
+
+        let (new_value, new_nums) = num_op(
+            &cbor,
+            &cbor_path,
+            &NumOp::Clamp(number("0"), number("100")),
+            NumOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            r#"{"stock":{"apples":3,"pears":0,"plums":100,"kiwis":"n/a"}}"#,
+            cbor_to_diag(&new_value.unwrap())
+        );
+        assert_eq!(
+            vec![
+                RedisValue::StringBuffer(diag_to_bytes("3")),
+                RedisValue::StringBuffer(diag_to_bytes("0")),
+                RedisValue::StringBuffer(diag_to_bytes("100")),
+                RedisValue::Null,
+            ],
+            new_nums
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(CborError::Err("division by zero".into())),
+            apply("[1,2]", NumOp::Div(number("0")))
+        );
+        assert_eq!(
+            Err(CborError::integer_overflow()),
+            apply("[-18446744073709551616]", NumOp::Abs)
+        );
+
+        let cbor = diag_to_cbor("[-18446744073709551616]");
+        let cbor_path = CborPath::builder().wildcard().build();
+        let (new_value, _) =
+            num_op(&cbor, &cbor_path, &NumOp::Abs, Overflow::Promote.into()).unwrap();
+        assert_eq!(
+            "[2(h'010000000000000000')]",
+            cbor_to_diag(&new_value.unwrap())
+        );
+    }
+}
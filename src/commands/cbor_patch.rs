@@ -0,0 +1,583 @@
+use crate::{
+    digest::canonical,
+    path_syntax,
+    util::{
+        apply_changes, split_format_arg, value_from_arg, CborError, CborKeyWritable, NextArgExt,
+    },
+};
+use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
+use cborpath::CborPath;
+use redis_module::{Context, RedisResult, RedisString, REDIS_OK};
+use std::borrow::Cow;
+
+///
+/// CBOR.PATCH key patch [FORMAT CBOR | JSON | DIAG]
+///
+/// Apply an ordered list of operations to a CBOR document, atomically
+pub fn cbor_patch(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let (args, format) = split_format_arg(&argv, 3)?;
+    let mut args = args.iter().skip(1);
+
+    let key_name = args.next_arg()?;
+    let patch = args.next_arg()?;
+
+    if args.next().is_some() {
+        return Err(CborError::syntax().into());
+    }
+
+    let patch = value_from_arg(patch, format)?;
+    let operations = parse_patch(&patch)?;
+
+    let key = ctx.open_key_writable(key_name);
+    let Some(existing) = key.get_cbor_value()? else {
+        return Err(CborError::nonexistent_key().into());
+    };
+
+    let new_value = apply_patch(existing, &operations)?;
+
+    if let Some(new_value) = new_value {
+        apply_changes(ctx, "cbor.patch", &argv, &key, Some(new_value))?;
+    }
+
+    REDIS_OK
+}
+
+/// Target of an operation
+pub(crate) struct Pointer {
+    path: CborPath,
+    /// Path of the parent values and member to add to the parents, `None` for the root
+    parent: Option<(CborPath, Member)>,
+}
+
+/// Last segment of a pointer
+pub(crate) enum Member {
+    Key(CborOwned),
+    Index(i64),
+}
+
+pub(crate) enum Operation<'a> {
+    Add(Pointer, &'a Cbor),
+    Remove(Pointer),
+    Replace(Pointer, &'a Cbor),
+    Move { from: Pointer, path: Pointer },
+    Copy { from: Pointer, path: Pointer },
+    Test(Pointer, &'a Cbor),
+}
+
+impl Operation<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Add(..) => "add",
+            Operation::Remove(_) => "remove",
+            Operation::Replace(..) => "replace",
+            Operation::Move { .. } => "move",
+            Operation::Copy { .. } => "copy",
+            Operation::Test(..) => "test",
+        }
+    }
+}
+
+pub(crate) fn parse_patch(patch: &Cbor) -> Result<Vec<Operation>, CborError> {
+    let ItemKind::Array(operations) = patch.kind() else {
+        return Err(CborError::Err(
+            "invalid patch - expected an array of operations".into(),
+        ));
+    };
+
+    operations
+        .enumerate()
+        .map(|(i, operation)| {
+            parse_operation(operation)
+                .map_err(|e| CborError::Err(format!("invalid patch operation #{i} - {e}").into()))
+        })
+        .collect()
+}
+
+fn parse_operation(operation: &Cbor) -> Result<Operation, String> {
+    let ItemKind::Dict(entries) = operation.kind() else {
+        return Err("expected a map".to_owned());
+    };
+
+    let mut op = None;
+    let mut path = None;
+    let mut from = None;
+    let mut value = None;
+
+    for (k, v) in entries {
+        match k.kind() {
+            ItemKind::Str(s) if s.as_cow() == "op" => match v.kind() {
+                ItemKind::Str(s) => op = Some(s.as_cow().into_owned()),
+                _ => return Err("op must be a text string".to_owned()),
+            },
+            ItemKind::Str(s) if s.as_cow() == "path" => path = Some(parse_pointer(v)?),
+            ItemKind::Str(s) if s.as_cow() == "from" => from = Some(parse_pointer(v)?),
+            ItemKind::Str(s) if s.as_cow() == "value" => value = Some(v),
+            _ => (),
+        }
+    }
+
+    let op = op.ok_or("missing op")?;
+    let path = path.ok_or("missing path")?;
+
+    match op.as_str() {
+        "add" => Ok(Operation::Add(path, value.ok_or("missing value")?)),
+        "remove" => Ok(Operation::Remove(path)),
+        "replace" => Ok(Operation::Replace(path, value.ok_or("missing value")?)),
+        "move" => Ok(Operation::Move {
+            from: from.ok_or("missing from")?,
+            path,
+        }),
+        "copy" => Ok(Operation::Copy {
+            from: from.ok_or("missing from")?,
+            path,
+        }),
+        "test" => Ok(Operation::Test(path, value.ok_or("missing value")?)),
+        _ => Err(format!("unknown op {op}")),
+    }
+}
+
+/// Read a CBORPath given as a CBOR array or as a textual path
+fn parse_pointer(path: &Cbor) -> Result<Pointer, String> {
+    let bytes = match path.kind() {
+        ItemKind::Str(s) => Cow::Owned(
+            path_syntax::parse(&s.as_cow()).map_err(|e| format!("invalid CBORPath: {e}"))?,
+        ),
+        ItemKind::Array(_) => Cow::Borrowed(path.as_slice()),
+        _ => return Err("invalid CBORPath".to_owned()),
+    };
+
+    let path = CborPath::from_bytes(&bytes).map_err(|_| "invalid CBORPath")?;
+    if path.is_root() {
+        return Ok(Pointer { path, parent: None });
+    }
+
+    let ItemKind::Array(segments) = Cbor::unchecked(&bytes).kind() else {
+        return Err("invalid CBORPath".to_owned());
+    };
+    let segments = segments.collect::<Vec<_>>();
+    let (last, parent_segments) = segments.split_last().ok_or("invalid CBORPath")?;
+
+    let member = match last.kind() {
+        ItemKind::Dict(mut selector) => match selector.next() {
+            Some((k, v)) if is_text(k, "#") => match v.kind() {
+                ItemKind::Pos(index) => Member::Index(
+                    i64::try_from(index).map_err(|_| "index out of range".to_owned())?,
+                ),
+                ItemKind::Neg(index) => Member::Index(
+                    -1 - i64::try_from(index).map_err(|_| "index out of range".to_owned())?,
+                ),
+                _ => return Err("invalid CBORPath".to_owned()),
+            },
+            // wildcards, slices, filters and descendants do not designate a single member
+            _ => return Err("the last segment of the path must be a key or an index".to_owned()),
+        },
+        ItemKind::Array(_) => {
+            return Err("the last segment of the path must be a key or an index".to_owned())
+        }
+        _ => Member::Key(last.to_owned()),
+    };
+
+    let parent = CborBuilder::new().write_array(None, |builder| {
+        for segment in parent_segments {
+            builder.write_item(segment);
+        }
+    });
+    let parent = CborPath::from_bytes(parent.as_slice()).map_err(|_| "invalid CBORPath")?;
+
+    Ok(Pointer {
+        path,
+        parent: Some((parent, member)),
+    })
+}
+
+/// Apply all the operations on a copy of `existing`,
+/// the first failing operation aborts the whole patch
+pub(crate) fn apply_patch(
+    existing: &CborOwned,
+    operations: &[Operation],
+) -> Result<Option<CborOwned>, CborError> {
+    let mut document = Cow::Borrowed(existing);
+
+    for (i, operation) in operations.iter().enumerate() {
+        let result = match operation {
+            Operation::Add(path, value) => add(&document, path, value).map(Some),
+            Operation::Remove(path) => remove(&document, path).map(Some),
+            Operation::Replace(path, value) => replace(&document, path, value).map(Some),
+            Operation::Move { from, path } => read_one(&document, from)
+                .and_then(|value| add(&remove(&document, from)?, path, &value))
+                .map(Some),
+            Operation::Copy { from, path } => read_one(&document, from)
+                .and_then(|value| add(&document, path, &value))
+                .map(Some),
+            Operation::Test(path, value) => test(&document, path, value).map(|_| None),
+        }
+        .map_err(|e| {
+            e.context(format_args!(
+                "patch operation #{i} ({}) failed",
+                operation.name()
+            ))
+        })?;
+
+        if let Some(new_document) = result {
+            document = Cow::Owned(new_document);
+        }
+    }
+
+    match document {
+        Cow::Borrowed(_) => Ok(None),
+        Cow::Owned(document) => Ok(Some(document)),
+    }
+}
+
+fn read_one(document: &Cbor, pointer: &Pointer) -> Result<CborOwned, CborError> {
+    match pointer.path.read(document).as_slice() {
+        [value] => Ok((*value).to_owned()),
+        [] => Err(CborError::NonExistent("from path not found".into())),
+        _ => Err(CborError::Err(
+            "from path matches more than one value".into(),
+        )),
+    }
+}
+
+fn add(document: &Cbor, pointer: &Pointer, value: &Cbor) -> Result<CborOwned, CborError> {
+    let Some((parent, member)) = &pointer.parent else {
+        return Ok(value.to_owned());
+    };
+
+    let mut num_matches = 0;
+    let mut error = None;
+
+    let new_document = parent
+        .write(document, |old_value| {
+            num_matches += 1;
+            let new_value = match (old_value.kind(), member) {
+                (ItemKind::Dict(dict), Member::Key(key)) => Some(insert_entry(dict, key, value)),
+                (ItemKind::Array(array), Member::Index(index)) => {
+                    insert_item(array, Some(*index), value)
+                }
+                (ItemKind::Array(array), Member::Key(key)) if is_end_of_array(key) => {
+                    insert_item(array, None, value)
+                }
+                _ => None,
+            };
+            match new_value {
+                Some(new_value) => Ok(Some(Cow::Owned(new_value))),
+                None => {
+                    error.get_or_insert_with(|| match (old_value.kind(), member) {
+                        (ItemKind::Array(_), Member::Index(_)) => {
+                            CborError::Err("index out of range".into())
+                        }
+                        _ => CborError::WrongType(
+                            "parent value is not a map or an array matching the path".into(),
+                        ),
+                    });
+                    Ok(Some(Cow::Borrowed(old_value)))
+                }
+            }
+        })
+        .map_err(CborError::path_write)?;
+
+    match (error, num_matches, new_document) {
+        (Some(e), _, _) => Err(e),
+        (None, 0, _) | (None, _, None) => {
+            Err(CborError::NonExistent("parent path not found".into()))
+        }
+        (None, _, Some(new_document)) => Ok(new_document),
+    }
+}
+
+fn remove(document: &Cbor, pointer: &Pointer) -> Result<CborOwned, CborError> {
+    if pointer.parent.is_none() {
+        return Err(CborError::Err("cannot remove the root".into()));
+    }
+
+    let mut num_matches = 0;
+    let new_document = pointer
+        .path
+        .write(document, |_| {
+            num_matches += 1;
+            Ok(None)
+        })
+        .map_err(CborError::path_write)?;
+
+    match new_document {
+        Some(new_document) if num_matches > 0 => Ok(new_document),
+        _ => Err(path_not_found()),
+    }
+}
+
+fn replace(document: &Cbor, pointer: &Pointer, value: &Cbor) -> Result<CborOwned, CborError> {
+    if pointer.parent.is_none() {
+        return Ok(value.to_owned());
+    }
+
+    let mut num_matches = 0;
+    let new_document = pointer
+        .path
+        .write(document, |_| {
+            num_matches += 1;
+            Ok(Some(Cow::Owned(value.to_owned())))
+        })
+        .map_err(CborError::path_write)?;
+
+    match new_document {
+        Some(new_document) if num_matches > 0 => Ok(new_document),
+        _ => Err(path_not_found()),
+    }
+}
+
+/// Every value matching the path must be semantically equal to `value`
+fn test(document: &Cbor, pointer: &Pointer, value: &Cbor) -> Result<(), CborError> {
+    let results = pointer.path.read(document);
+    if results.is_empty() {
+        return Err(path_not_found());
+    }
+
+    let expected = canonical(value);
+    if results.iter().all(|result| canonical(result) == expected) {
+        Ok(())
+    } else {
+        Err(CborError::Err("test failed".into()))
+    }
+}
+
+#[inline]
+fn path_not_found() -> CborError {
+    CborError::NonExistent("path not found".into())
+}
+
+fn is_text(cbor: &Cbor, text: &str) -> bool {
+    matches!(cbor.kind(), ItemKind::Str(s) if s.as_cow() == text)
+}
+
+/// `"-"` designates the end of an array, as in RFC 6902
+fn is_end_of_array(key: &Cbor) -> bool {
+    is_text(key, "-")
+}
+
+fn insert_entry<'a>(
+    dict: impl Iterator<Item = (&'a Cbor, &'a Cbor)>,
+    key: &Cbor,
+    value: &Cbor,
+) -> CborOwned {
+    CborBuilder::new().write_dict(None, |builder| {
+        let mut replaced = false;
+        for (k, v) in dict {
+            if k == key {
+                replaced = true;
+                builder.with_cbor_key(|b| b.write_item(k), |b| b.write_item(value));
+            } else {
+                builder.with_cbor_key(|b| b.write_item(k), |b| b.write_item(v));
+            }
+        }
+        if !replaced {
+            builder.with_cbor_key(|b| b.write_item(key), |b| b.write_item(value));
+        }
+    })
+}
+
+/// Insert `value` before `index`, at the end of the array for `None`.
+/// Negative indexes start from the end of the array.
+fn insert_item<'a>(
+    array: impl Iterator<Item = &'a Cbor>,
+    index: Option<i64>,
+    value: &Cbor,
+) -> Option<CborOwned> {
+    let items = array.collect::<Vec<_>>();
+    let len = items.len() as i64;
+    let index = match index {
+        Some(index) if index < 0 => len + index,
+        Some(index) => index,
+        None => len,
+    };
+
+    if !(0..=len).contains(&index) {
+        return None;
+    }
+
+    Some(CborBuilder::new().write_array(None, |builder| {
+        for (i, item) in items.iter().enumerate() {
+            if i as i64 == index {
+                builder.write_item(value);
+            }
+            builder.write_item(item);
+        }
+        if index == len {
+            builder.write_item(value);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_patch, parse_patch};
+    use crate::util::{cbor_to_diag, diag_to_cbor, CborError};
+
+    fn patch(document: &str, patch: &str) -> Result<Option<String>, String> {
+        let patch = diag_to_cbor(patch);
+        let operations = parse_patch(&patch).map_err(|e| e.to_string())?;
+        apply_patch(&diag_to_cbor(document), &operations)
+            .map(|new_value| new_value.map(|new_value| cbor_to_diag(&new_value)))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn add() {
+        assert_eq!(
+            Ok(Some(r#"{"a":1,"b":2}"#.to_owned())),
+            patch(r#"{"a":1}"#, r#"[{"op":"add","path":["$","b"],"value":2}]"#)
+        );
+        assert_eq!(
+            Ok(Some(r#"{"a":3}"#.to_owned())),
+            patch(r#"{"a":1}"#, r#"[{"op":"add","path":"$.a","value":3}]"#)
+        );
+        assert_eq!(
+            Ok(Some(r#"{"a":[1,4,2,3]}"#.to_owned())),
+            patch(
+                r#"{"a":[1,2,3]}"#,
+                r#"[{"op":"add","path":"$.a[1]","value":4}]"#
+            )
+        );
+        assert_eq!(
+            Ok(Some(r#"{"a":[1,2,3,4]}"#.to_owned())),
+            patch(
+                r#"{"a":[1,2,3]}"#,
+                r#"[{"op":"add","path":["$","a","-"],"value":4}]"#
+            )
+        );
+        assert_eq!(
+            Ok(Some(r#"{"a":[1,2,3,4]}"#.to_owned())),
+            patch(
+                r#"{"a":[1,2,3]}"#,
+                r#"[{"op":"add","path":"$.a[3]","value":4}]"#
+            )
+        );
+        assert_eq!(
+            Ok(Some(r#"{1:h'00'}"#.to_owned())),
+            patch(r#"{}"#, r#"[{"op":"add","path":["$",1],"value":h'00'}]"#)
+        );
+        assert_eq!(
+            Ok(Some("[1]".to_owned())),
+            patch(r#"{"a":1}"#, r#"[{"op":"add","path":"$","value":[1]}]"#)
+        );
+        assert!(patch(
+            r#"{"a":[1]}"#,
+            r#"[{"op":"add","path":"$.a[2]","value":4}]"#
+        )
+        .is_err());
+        assert!(patch(r#"{"a":1}"#, r#"[{"op":"add","path":"$.b.c","value":4}]"#).is_err());
+        assert!(patch(r#"{"a":1}"#, r#"[{"op":"add","path":"$.a.c","value":4}]"#).is_err());
+        assert!(patch(r#"{"a":1}"#, r#"[{"op":"add","path":"$.*","value":4}]"#).is_err());
+    }
+
+    #[test]
+    fn remove_and_replace() {
+        assert_eq!(
+            Ok(Some(r#"{"b":[2]}"#.to_owned())),
+            patch(
+                r#"{"a":1,"b":[1,2]}"#,
+                r#"[{"op":"remove","path":"$.a"},{"op":"remove","path":"$.b[0]"}]"#
+            )
+        );
+        assert_eq!(
+            Ok(Some(r#"{"a":[0,0]}"#.to_owned())),
+            patch(
+                r#"{"a":[1,2]}"#,
+                r#"[{"op":"replace","path":"$.a[*]","value":0}]"#
+            )
+        );
+        assert!(patch(r#"{"a":1}"#, r#"[{"op":"remove","path":"$.b"}]"#).is_err());
+        assert!(patch(r#"{"a":1}"#, r#"[{"op":"remove","path":"$"}]"#).is_err());
+        assert!(patch(r#"{"a":1}"#, r#"[{"op":"replace","path":"$.b","value":0}]"#).is_err());
+    }
+
+    #[test]
+    fn move_and_copy() {
+        assert_eq!(
+            Ok(Some(r#"{"b":{"c":1}}"#.to_owned())),
+            patch(
+                r#"{"a":1,"b":{}}"#,
+                r#"[{"op":"move","from":"$.a","path":"$.b.c"}]"#
+            )
+        );
+        assert_eq!(
+            Ok(Some(r#"{"a":[1,2],"b":[2]}"#.to_owned())),
+            patch(
+                r#"{"a":[1,2],"b":[]}"#,
+                r#"[{"op":"copy","from":"$.a[1]","path":["$","b","-"]}]"#
+            )
+        );
+        assert!(patch(
+            r#"{"a":[1,2],"b":[]}"#,
+            r#"[{"op":"copy","from":"$.a[*]","path":"$.c"}]"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_aborts() {
+        assert_eq!(
+            Ok(None),
+            patch(
+                r#"{"a":1.0}"#,
+                r#"[{"op":"test","path":"$.a","value":1.0_3}]"#
+            )
+        );
+        assert_eq!(
+            Ok(Some(r#"{"a":1.0,"b":2}"#.to_owned())),
+            patch(
+                r#"{"a":1.0}"#,
+                r#"[{"op":"test","path":"$.a","value":1.0},{"op":"add","path":"$.b","value":2}]"#
+            )
+        );
+        assert_eq!(
+            Err("ERR patch operation #1 (test) failed - test failed".to_owned()),
+            patch(
+                r#"{"a":1}"#,
+                r#"[{"op":"add","path":"$.b","value":2},{"op":"test","path":"$.a","value":2}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn error_prefixes() {
+        assert!(matches!(
+            patch(r#"{"a":1}"#, r#"[{"op":"remove","path":"$.b"}]"#),
+            Err(e) if e.starts_with("NONEXISTENT ")
+        ));
+        assert!(matches!(
+            patch(r#"{"a":1}"#, r#"[{"op":"add","path":"$.a.b","value":1}]"#),
+            Err(e) if e.starts_with("WRONGTYPE ")
+        ));
+        assert!(matches!(
+            patch(r#"{"a":[]}"#, r#"[{"op":"add","path":"$.a[1]","value":1}]"#),
+            Err(e) if e.starts_with("ERR ")
+        ));
+    }
+
+    #[test]
+    fn invalid_patch() {
+        assert!(patch("{}", r#"{"op":"remove","path":"$"}"#).is_err());
+        assert!(patch("{}", r#"[{"op":"unknown","path":"$"}]"#).is_err());
+        assert!(patch("{}", r#"[{"op":"add","path":"$.a"}]"#).is_err());
+        assert!(patch("{}", r#"[{"op":"move","path":"$.a"}]"#).is_err());
+        assert!(patch("{}", r#"[{"path":"$.a"}]"#).is_err());
+        assert!(patch("{}", r#"[{"op":"remove","path":1}]"#).is_err());
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        for index in [
+            "18446744073709551615",
+            "9223372036854775808",
+            "-9223372036854775809",
+        ] {
+            let operations =
+                format!(r#"[{{"op":"add","path":["$","a",{{"#":{index}}}],"value":0}}]"#);
+            assert!(
+                matches!(patch(r#"{"a":[1]}"#, &operations), Err(e) if e.contains("operation #0")),
+                "{index}"
+            );
+        }
+    }
+}
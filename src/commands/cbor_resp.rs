@@ -1,7 +1,8 @@
-use crate::util::{CborKey, CborPathExt, NextArgExt};
+use super::number::{Fraction, Number};
+use crate::util::{CborError, CborKey, CborPathExt, NextArgExt};
 use cbor_data::{Cbor, CborOwned, ItemKind};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 
 ///
 /// CBOR.RESP key [path]
@@ -12,13 +13,13 @@ pub fn cbor_resp(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
 
     let key = args.next_arg()?;
     let cbor_path = match args.next_arg() {
-        Ok(cbor_path) => CborPath::from_arg(cbor_path)?,
+        Ok(cbor_path) => CborPath::from_arg(ctx, cbor_path)?,
         Err(_) => CborPath::root(),
     };
 
     let key = ctx.open_key(key);
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
     Ok(resp(existing, &cbor_path).into())
@@ -30,9 +31,21 @@ fn resp(existing: &CborOwned, cbor_path: &CborPath) -> Vec<RedisValue> {
 }
 
 fn resp_from_cbor(value: &Cbor) -> RedisValue {
+    match Number::try_from(value) {
+        Ok(Number::BigNum(v)) => return RedisValue::BigNumber(v.to_string()),
+        Ok(Number::Fraction(v)) => return resp_from_fraction(&v),
+        _ => (),
+    }
+
     match value.kind() {
-        ItemKind::Pos(v) => RedisValue::Integer(v as i64),
-        ItemKind::Neg(v) => RedisValue::Integer(-1 - (v as i64)),
+        ItemKind::Pos(v) => match i64::try_from(v) {
+            Ok(v) => RedisValue::Integer(v),
+            Err(_) => RedisValue::BigNumber(v.to_string()),
+        },
+        ItemKind::Neg(v) => match i64::try_from(v) {
+            Ok(v) => RedisValue::Integer(-1 - v),
+            Err(_) => RedisValue::BigNumber((-1 - v as i128).to_string()),
+        },
         ItemKind::Float(v) => RedisValue::Float(v),
         ItemKind::Str(v) => RedisValue::BulkString(v.as_cow().into_owned()),
         ItemKind::Bytes(v) => RedisValue::StringBuffer(v.as_cow().into_owned()),
@@ -48,6 +61,16 @@ fn resp_from_cbor(value: &Cbor) -> RedisValue {
     }
 }
 
+/// Integral decimal fractions map to big numbers and other decimal fractions to their
+/// exact decimal representation, as RESP has no decimal type. Bigfloats map to doubles.
+fn resp_from_fraction(value: &Fraction) -> RedisValue {
+    match value.to_decimal_string() {
+        Some(v) if value.exponent >= 0 => RedisValue::BigNumber(v),
+        Some(v) => RedisValue::BulkString(v),
+        None => RedisValue::Float(value.to_f64()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::resp;
@@ -92,4 +115,26 @@ mod tests {
             ])]
         );
     }
+
+    #[test]
+    fn big_numbers() {
+        let cbor = diag_to_cbor(
+            "[18446744073709551615,-18446744073709551616,2(h'010000000000000000'),3(h'010000000000000000'),4([-2,-1250]),4([2,12]),5([-1,3])]",
+        );
+        let cbor_path = CborPath::root();
+
+        let values = resp(&cbor, &cbor_path);
+        assert_eq!(
+            values,
+            vec![RedisValue::Array(vec![
+                RedisValue::BigNumber("18446744073709551615".to_string()),
+                RedisValue::BigNumber("-18446744073709551616".to_string()),
+                RedisValue::BigNumber("18446744073709551616".to_string()),
+                RedisValue::BigNumber("-18446744073709551617".to_string()),
+                RedisValue::BulkString("-12.50".to_string()),
+                RedisValue::BigNumber("1200".to_string()),
+                RedisValue::Float(1.5),
+            ])]
+        );
+    }
 }
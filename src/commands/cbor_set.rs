@@ -1,7 +1,18 @@
-use crate::util::{apply_changes, CborExt, CborKeyWritable, CborPathExt, NextArgExt};
+use super::{
+    cbor_get::get,
+    path_create::{create_path, definite_segments, Segment},
+};
+use crate::{
+    config::output_format,
+    util::{
+        apply_changes, apply_changes_with_expire, now_millis, path_bytes, value_from_arg,
+        value_to_reply, CborError, CborKeyWritable, CborPathExt, Format, KeyExpire, NextArgExt,
+    },
+};
 use cbor_data::{Cbor, CborOwned};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue, REDIS_OK};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
+use std::time::Duration;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum SetOptions {
@@ -10,22 +21,39 @@ pub enum SetOptions {
     None,
 }
 
+/// Expiration of the key once the value is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expiry {
+    /// Discard the time to live of the key, like `SET`
+    None,
+    /// `KEEPTTL`
+    Keep,
+    /// `EX` or `PX`
+    In(Duration),
+    /// `EXAT` or `PXAT`, in milliseconds since the Unix epoch
+    At(u64),
+}
+
 ///
-/// CBOR.SET key path value [NX | XX]
+/// CBOR.SET key path value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL] [MKPATH] [STRICT] [FORMAT CBOR | JSON | DIAG]
 ///
-pub fn cbor_set(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    let mut args = args.iter().skip(1);
+pub fn cbor_set(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let mut args = argv.iter().skip(1);
 
     let key_name = args.next_arg()?;
     let path = args.next_arg()?;
     let value = args.next_arg()?;
 
-    let cbor_path = CborPath::from_arg(path)?;
-    let value = Cbor::from_arg(value)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
 
     let mut options = SetOptions::None;
+    let mut get_old_value = false;
+    let mut expiry = Expiry::None;
+    let mut mkpath = false;
+    let mut strict = false;
+    let mut format = None;
 
-    for s in args {
+    while let Some(s) = args.next() {
         match s.try_as_str()? {
             arg if arg.eq_ignore_ascii_case("NX") && options == SetOptions::None => {
                 options = SetOptions::NotExists
@@ -33,36 +61,119 @@ pub fn cbor_set(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
             arg if arg.eq_ignore_ascii_case("XX") && options == SetOptions::None => {
                 options = SetOptions::AlreadyExists
             }
-            _ => return Err(RedisError::Str("ERR syntax error")),
+            arg if arg.eq_ignore_ascii_case("GET") && !get_old_value => get_old_value = true,
+            arg if arg.eq_ignore_ascii_case("EX") && expiry == Expiry::None => {
+                expiry = Expiry::In(Duration::from_millis(parse_expire(
+                    args.next_arg()?.try_as_str()?,
+                    1000,
+                )?))
+            }
+            arg if arg.eq_ignore_ascii_case("PX") && expiry == Expiry::None => {
+                expiry = Expiry::In(Duration::from_millis(parse_expire(
+                    args.next_arg()?.try_as_str()?,
+                    1,
+                )?))
+            }
+            arg if arg.eq_ignore_ascii_case("EXAT") && expiry == Expiry::None => {
+                expiry = Expiry::At(parse_expire(args.next_arg()?.try_as_str()?, 1000)?)
+            }
+            arg if arg.eq_ignore_ascii_case("PXAT") && expiry == Expiry::None => {
+                expiry = Expiry::At(parse_expire(args.next_arg()?.try_as_str()?, 1)?)
+            }
+            arg if arg.eq_ignore_ascii_case("KEEPTTL") && expiry == Expiry::None => {
+                expiry = Expiry::Keep
+            }
+            arg if arg.eq_ignore_ascii_case("MKPATH") && !mkpath => mkpath = true,
+            arg if arg.eq_ignore_ascii_case("STRICT") && !strict => strict = true,
+            arg if arg.eq_ignore_ascii_case("FORMAT") => {
+                format = Some(Format::from_arg(args.next_arg()?)?)
+            }
+            _ => return Err(CborError::syntax().into()),
         };
     }
 
+    let value = value_from_arg(value, format.unwrap_or(Format::Cbor))?;
+    let path = if mkpath {
+        Some(path_bytes(ctx, path)?)
+    } else {
+        None
+    };
+    let segments = match &path {
+        Some(path) => Some(definite_segments(path).ok_or_else(|| {
+            CborError::Err("MKPATH requires a path made of keys and indexes only".into())
+        })?),
+        None => None,
+    };
+
     let key = ctx.open_key_writable(key_name);
     let existing = key.get_cbor_value()?;
 
-    match set(existing, &cbor_path, value, options) {
+    let reply = if get_old_value {
+        match get(existing, &cbor_path) {
+            Some(old_value) => value_to_reply(old_value, format.unwrap_or_else(output_format)),
+            None => RedisValue::Null,
+        }
+    } else {
+        RedisValue::SimpleStringStatic("OK")
+    };
+
+    let result = match &segments {
+        Some(segments) => set_mkpath(existing, &cbor_path, segments, &value, options)?,
+        None => set(existing, &cbor_path, &value, options),
+    };
+
+    match result {
+        SetResult::ErrConditionNotMet if get_old_value => Ok(reply),
         SetResult::ErrConditionNotMet => Ok(RedisValue::Null),
-        SetResult::ErrExpectedRoot => Err(RedisError::Str(
-            "ERR new CBOR documents must be created with a root path",
-        )),
+        SetResult::ErrExpectedRoot => Err(CborError::expected_root().into()),
         SetResult::Updated(new_value) => {
-            key.set_cbor_value(new_value)?;
-            apply_changes(ctx, "cbor.set", key_name)?;
-            REDIS_OK
+            let now = now_millis();
+            if matches!(expiry, Expiry::At(at) if at <= now) {
+                // like SET, an expire time in the past deletes the key
+                if existing.is_some() {
+                    let del = [
+                        ctx.create_string("DEL"),
+                        ctx.create_string(key_name.as_slice()),
+                    ];
+                    apply_changes(ctx, "cbor.set", &del, &key, None)?;
+                }
+                return Ok(reply);
+            }
+
+            let expire = match expiry {
+                Expiry::None => KeyExpire::Discard,
+                Expiry::Keep => KeyExpire::Keep,
+                Expiry::In(ttl) => KeyExpire::At(now.saturating_add(ttl.as_millis() as u64)),
+                Expiry::At(at) => KeyExpire::At(at),
+            };
+            apply_changes_with_expire(ctx, "cbor.set", &argv, &key, Some(new_value), expire)?;
+            Ok(reply)
         }
-        SetResult::NoMatch => REDIS_OK,
+        SetResult::NoMatch if strict => Err(CborError::no_match().into()),
+        SetResult::NoMatch => Ok(reply),
     }
 }
 
+/// Parse a positive expire time, converted to milliseconds with `unit`
+fn parse_expire(time: &str, unit: u64) -> Result<u64, CborError> {
+    time.parse::<u64>()
+        .ok()
+        .filter(|&time| time > 0)
+        .and_then(|time| time.checked_mul(unit))
+        // the time to live must fit in the signed milliseconds of the module API
+        .filter(|&time| i64::try_from(time).is_ok())
+        .ok_or_else(|| CborError::Err("invalid expire time in 'cbor.set' command".into()))
+}
+
 #[derive(Debug, PartialEq, Eq)]
-enum SetResult {
+pub(crate) enum SetResult {
     ErrConditionNotMet,
     ErrExpectedRoot,
     Updated(CborOwned),
     NoMatch,
 }
 
-fn set(
+pub(crate) fn set(
     existing: Option<&CborOwned>,
     cbor_path: &CborPath,
     value: &Cbor,
@@ -89,10 +200,33 @@ fn set(
     }
 }
 
+/// Like [`set`], but create the value designated by the definite path `segments`,
+/// with its missing parent maps and arrays, when the path matches nothing
+pub(crate) fn set_mkpath(
+    existing: Option<&CborOwned>,
+    cbor_path: &CborPath,
+    segments: &[Segment],
+    value: &Cbor,
+    options: SetOptions,
+) -> Result<SetResult, CborError> {
+    match set(existing, cbor_path, value, options) {
+        SetResult::ErrExpectedRoot | SetResult::NoMatch => Ok(SetResult::Updated(create_path(
+            existing.map(|existing| &**existing),
+            segments,
+            value,
+        )?)),
+        result => Ok(result),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{set, SetOptions, SetResult};
-    use crate::util::{cbor_to_diag, diag_to_cbor};
+    use super::{parse_expire, set, set_mkpath, SetOptions, SetResult};
+    use crate::{
+        commands::path_create::definite_segments,
+        path_syntax,
+        util::{cbor_to_diag, diag_to_cbor, CborError},
+    };
     use cborpath::CborPath;
 
     #[test]
@@ -145,4 +279,74 @@ mod tests {
         );
         assert!(matches!(result, SetResult::Updated(cbor) if cbor_to_diag(&cbor) == "[1,2,4]"));
     }
+
+    #[test]
+    fn mkpath() {
+        let path = path_syntax::parse("$.a.b[0]").unwrap();
+        let cbor_path = CborPath::from_bytes(&path).unwrap();
+        let segments = definite_segments(&path).unwrap();
+        let value = diag_to_cbor("1");
+
+        let result = set(None, &cbor_path, &value, SetOptions::None);
+        assert_eq!(SetResult::ErrExpectedRoot, result);
+        let result = set_mkpath(None, &cbor_path, &segments, &value, SetOptions::None);
+        assert!(
+            matches!(result, Ok(SetResult::Updated(cbor)) if cbor_to_diag(&cbor) == r#"{"a":{"b":[1]}}"#)
+        );
+
+        let existing = diag_to_cbor(r#"{"c":2}"#);
+        let result = set(Some(&existing), &cbor_path, &value, SetOptions::None);
+        assert_eq!(SetResult::NoMatch, result);
+        let result = set_mkpath(
+            Some(&existing),
+            &cbor_path,
+            &segments,
+            &value,
+            SetOptions::None,
+        );
+        assert!(
+            matches!(result, Ok(SetResult::Updated(cbor)) if cbor_to_diag(&cbor) == r#"{"c":2,"a":{"b":[1]}}"#)
+        );
+
+        let existing = diag_to_cbor(r#"{"a":{"b":[0]}}"#);
+        let result = set_mkpath(
+            Some(&existing),
+            &cbor_path,
+            &segments,
+            &value,
+            SetOptions::None,
+        );
+        assert!(
+            matches!(result, Ok(SetResult::Updated(cbor)) if cbor_to_diag(&cbor) == r#"{"a":{"b":[1]}}"#)
+        );
+
+        let existing = diag_to_cbor(r#"{"a":[]}"#);
+        let result = set_mkpath(
+            Some(&existing),
+            &cbor_path,
+            &segments,
+            &value,
+            SetOptions::None,
+        );
+        assert_eq!(
+            Err(CborError::WrongType(
+                "cannot create the path - a parent value is not a map".into()
+            )),
+            result
+        );
+    }
+
+    #[test]
+    fn expire() {
+        assert_eq!(Ok(10_000), parse_expire("10", 1000));
+        assert_eq!(Ok(10), parse_expire("10", 1));
+
+        let invalid = Err(CborError::Err(
+            "invalid expire time in 'cbor.set' command".into(),
+        ));
+        assert_eq!(invalid, parse_expire("0", 1));
+        assert_eq!(invalid, parse_expire("-10", 1));
+        assert_eq!(invalid, parse_expire("1.5", 1));
+        assert_eq!(invalid, parse_expire("9223372036854775807", 1000));
+    }
 }
@@ -1,33 +1,32 @@
-use crate::util::{apply_changes, CborKeyWritable, CborPathExt, NextArgExt};
+use crate::util::{apply_changes, CborError, CborKeyWritable, CborPathExt, NextArgExt};
 use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 use std::borrow::Cow;
 
 ///
 /// CBOR.STRAPPEND key path value
 ///
 /// Append the CBOR-string value to the string at path
-pub fn cbor_str_append(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    let mut args = args.iter().skip(1).peekable();
+pub fn cbor_str_append(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let mut args = argv.iter().skip(1).peekable();
 
     let key_name = args.next_arg()?;
     let path = args.next_arg()?;
     let value = args.next_arg()?;
 
     let key = ctx.open_key_writable(key_name);
-    let cbor_path = CborPath::from_arg(path)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
     let value = value.try_as_str()?;
 
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    let (new_value, str_lengths) = str_append(existing, &cbor_path, value);
+    let (new_value, str_lengths) = str_append(existing, &cbor_path, value)?;
 
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.strappend", key_name)?;
+        apply_changes(ctx, "cbor.strappend", &argv, &key, Some(new_value))?;
     }
 
     Ok(str_lengths.into())
@@ -37,7 +36,7 @@ fn str_append<'a>(
     existing: &'a Cbor,
     cbor_path: &CborPath,
     value: &'a str,
-) -> (Option<CborOwned>, Vec<RedisValue>) {
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
     let mut str_lengths = Vec::<RedisValue>::new();
 
     let new_value = cbor_path
@@ -53,9 +52,9 @@ fn str_append<'a>(
                 Ok(Some(Cow::Borrowed(old_value)))
             }
         })
-        .unwrap();
+        .map_err(CborError::path_write)?;
 
-    (new_value, str_lengths)
+    Ok((new_value, str_lengths))
 }
 
 #[cfg(test)]
@@ -71,7 +70,7 @@ mod tests {
         let cbor_path = CborPath::builder().descendant(segment().key("a")).build();
         let value = "baz";
 
-        let (new_value, str_lengths) = str_append(&cbor, &cbor_path, value);
+        let (new_value, str_lengths) = str_append(&cbor, &cbor_path, value).unwrap();
         assert_eq!(
             r#"{"a":"foobaz","nested":{"a":"hellobaz"},"nested2":{"a":12}}"#,
             cbor_to_diag(&new_value.unwrap())
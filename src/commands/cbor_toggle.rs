@@ -1,37 +1,39 @@
-use crate::util::{apply_changes, CborKeyWritable, CborPathExt, NextArgExt};
+use crate::util::{apply_changes, CborError, CborKeyWritable, CborPathExt, NextArgExt};
 use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 use std::borrow::Cow;
 
 ///
 /// CBOR.TOGGLE key [path]
 ///
 /// Toggle a Boolean value stored at path
-pub fn cbor_toggle(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    let mut args = args.iter().skip(1).peekable();
+pub fn cbor_toggle(ctx: &Context, argv: Vec<RedisString>) -> RedisResult {
+    let mut args = argv.iter().skip(1).peekable();
 
     let key_name = args.next_arg()?;
     let path = args.next_arg()?;
 
     let key = ctx.open_key_writable(key_name);
-    let cbor_path = CborPath::from_arg(path)?;
+    let cbor_path = CborPath::from_arg(ctx, path)?;
 
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
-    let (new_value, new_booleans) = toggle(existing, &cbor_path);
+    let (new_value, new_booleans) = toggle(existing, &cbor_path)?;
 
     if let Some(new_value) = new_value {
-        key.set_cbor_value(new_value)?;
-        apply_changes(ctx, "cbor.toggle", key_name)?;
+        apply_changes(ctx, "cbor.toggle", &argv, &key, Some(new_value))?;
     }
 
     Ok(new_booleans.into())
 }
 
-fn toggle(existing: &Cbor, cbor_path: &CborPath) -> (Option<CborOwned>, Vec<RedisValue>) {
+fn toggle(
+    existing: &Cbor,
+    cbor_path: &CborPath,
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError> {
     let mut new_booleans = Vec::<RedisValue>::new();
 
     let new_value = cbor_path
@@ -44,9 +46,9 @@ fn toggle(existing: &Cbor, cbor_path: &CborPath) -> (Option<CborOwned>, Vec<Redi
                 Ok(Some(Cow::Borrowed(old_value)))
             }
         })
-        .unwrap();
+        .map_err(CborError::path_write)?;
 
-    (new_value, new_booleans)
+    Ok((new_value, new_booleans))
 }
 
 
@@ -62,7 +64,7 @@ mod tests {
         let cbor = diag_to_cbor(r#"{"a": true, "nested": {"a": "hello"}}"#);
         let cbor_path = CborPath::builder().descendant(segment().key("a")).build();
 
-        let (new_value, new_booleans) = toggle(&cbor, &cbor_path);
+        let (new_value, new_booleans) = toggle(&cbor, &cbor_path).unwrap();
         let new_value = new_value.unwrap();
         assert_eq!(
             r#"{"a":false,"nested":{"a":"hello"}}"#,
@@ -76,7 +78,7 @@ mod tests {
             new_booleans
         );
 
-        let (new_value, new_booleans) = toggle(&new_value, &cbor_path);
+        let (new_value, new_booleans) = toggle(&new_value, &cbor_path).unwrap();
         assert_eq!(
             r#"{"a":true,"nested":{"a":"hello"}}"#,
             cbor_to_diag(&new_value.unwrap())
@@ -1,7 +1,8 @@
-use crate::util::{CborKey, CborPathExt, NextArgExt};
+use super::number::Number;
+use crate::util::{CborError, CborKey, CborPathExt, NextArgExt};
 use cbor_data::{CborOwned, ItemKind};
 use cborpath::CborPath;
-use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use redis_module::{Context, RedisResult, RedisString, RedisValue};
 
 ///
 /// CBOR.TYPE key [path]
@@ -12,13 +13,13 @@ pub fn cbor_type(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
 
     let key = args.next_arg()?;
     let cbor_path = match args.next_arg() {
-        Ok(cbor_path) => CborPath::from_arg(cbor_path)?,
+        Ok(cbor_path) => CborPath::from_arg(ctx, cbor_path)?,
         Err(_) => CborPath::root(),
     };
 
     let key = ctx.open_key(key);
     let Some(existing) = key.get_cbor_value()? else {
-        return Err(RedisError::nonexistent_key());
+        return Err(CborError::nonexistent_key().into());
     };
 
     Ok(_type(existing, &cbor_path).into())
@@ -30,18 +31,20 @@ pub fn _type(existing: &CborOwned, cbor_path: &CborPath) -> Vec<RedisValue> {
         .into_iter()
         .map(|v| {
             RedisValue::BulkString(
-                match v.kind() {
-                    ItemKind::Pos(_) => "unsigned",
-                    ItemKind::Neg(_) => "negative",
-                    ItemKind::Float(_) => "float",
-                    ItemKind::Str(_) => "string",
-                    ItemKind::Bytes(_) => "bytestring",
-                    ItemKind::Bool(_) => "boolean",
-                    ItemKind::Null => "null",
-                    ItemKind::Undefined => "undefined",
-                    ItemKind::Simple(_) => "simple",
-                    ItemKind::Array(_) => "array",
-                    ItemKind::Dict(_) => "map",
+                match (Number::try_from(v), v.kind()) {
+                    (Ok(Number::BigNum(_)), _) => "bignum",
+                    (Ok(Number::Fraction(_)), _) => "decimal",
+                    (_, ItemKind::Pos(_)) => "unsigned",
+                    (_, ItemKind::Neg(_)) => "negative",
+                    (_, ItemKind::Float(_)) => "float",
+                    (_, ItemKind::Str(_)) => "string",
+                    (_, ItemKind::Bytes(_)) => "bytestring",
+                    (_, ItemKind::Bool(_)) => "boolean",
+                    (_, ItemKind::Null) => "null",
+                    (_, ItemKind::Undefined) => "undefined",
+                    (_, ItemKind::Simple(_)) => "simple",
+                    (_, ItemKind::Array(_)) => "array",
+                    (_, ItemKind::Dict(_)) => "map",
                 }
                 .to_string(),
             )
@@ -59,7 +62,7 @@ mod tests {
     #[test]
     fn test() {
         let cbor = diag_to_cbor(
-            r#"[12,-12,12.12,"foo",h'0123456789ABCDEF',true,null,undefined,simple(32),[1,2,3],{"a":1,"b":2}]"#,
+            r#"[12,-12,12.12,"foo",h'0123456789ABCDEF',true,null,undefined,simple(32),[1,2,3],{"a":1,"b":2},2(h'010000000000000000'),3(h'010000000000000000'),4([-2,1050]),5([-1,3]),2(h'01')]"#,
         );
         let cbor_path = CborPath::builder().wildcard().build();
 
@@ -77,6 +80,11 @@ mod tests {
                 RedisValue::BulkString("simple".to_string()),
                 RedisValue::BulkString("array".to_string()),
                 RedisValue::BulkString("map".to_string()),
+                RedisValue::BulkString("bignum".to_string()),
+                RedisValue::BulkString("bignum".to_string()),
+                RedisValue::BulkString("decimal".to_string()),
+                RedisValue::BulkString("decimal".to_string()),
+                RedisValue::BulkString("bignum".to_string()),
             ],
             _types
         );
@@ -4,22 +4,29 @@ mod cbor_arrinsert;
 mod cbor_arrlen;
 mod cbor_arrpop;
 mod cbor_arrtrim;
+mod cbor_cdc;
 mod cbor_clear;
 mod cbor_debug;
 mod cbor_del;
+mod cbor_diff;
 mod cbor_get;
 mod cbor_mapappend;
 mod cbor_mapkeys;
 mod cbor_maplen;
+mod cbor_merge;
 mod cbor_mget;
 mod cbor_numincrby;
 mod cbor_nummultby;
+mod cbor_numop;
+mod cbor_patch;
 mod cbor_resp;
 mod cbor_set;
 mod cbor_strappend;
 mod cbor_strlen;
 mod cbor_toggle;
 mod num_operation;
+mod number;
+mod path_create;
 mod cbor_type;
 
 pub use cbor_arrappend::*;
@@ -28,16 +35,21 @@ pub use cbor_arrinsert::*;
 pub use cbor_arrlen::*;
 pub use cbor_arrpop::*;
 pub use cbor_arrtrim::*;
+pub use cbor_cdc::*;
 pub use cbor_clear::*;
 pub use cbor_debug::*;
 pub use cbor_del::*;
+pub use cbor_diff::*;
 pub use cbor_get::*;
 pub use cbor_mapappend::*;
 pub use cbor_mapkeys::*;
 pub use cbor_maplen::*;
+pub use cbor_merge::*;
 pub use cbor_mget::*;
 pub use cbor_numincrby::*;
 pub use cbor_nummultby::*;
+pub use cbor_numop::*;
+pub use cbor_patch::*;
 pub use cbor_resp::*;
 pub use cbor_set::*;
 pub use cbor_strappend::*;
@@ -1,64 +1,183 @@
-use cbor_data::{Cbor, CborOwned, ItemKind};
+use super::number::{Encoding, Number, INTEGER_MAX, INTEGER_MIN};
+use crate::util::{CborError, NextArgExt};
+use cbor_data::{Cbor, CborOwned};
 use cborpath::CborPath;
-use redis_module::RedisValue;
+use num_bigint::BigInt;
+use num_traits::Signed;
+use redis_module::{RedisError, RedisString, RedisValue};
 use std::borrow::Cow;
 
-#[derive(Clone, Copy)]
-pub enum Number {
-    Signed(i64),
-    Unsigned(u64),
-    Float(f64),
+/// What to do when an integer result does not fit in the CBOR integer range
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Fail the whole command with an error reply
+    #[default]
+    Error,
+    /// Clamp the result to `-2^64` or `2^64-1`
+    Saturate,
+    /// Write the result as a bignum
+    Promote,
 }
 
-impl TryFrom<&Cbor> for Number {
-    type Error = ();
+/// Options shared by the numeric commands
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NumOptions {
+    pub overflow: Overflow,
+    /// Keep the major type of integers and the width of floats
+    pub preserve: bool,
+}
 
+impl From<Overflow> for NumOptions {
     #[inline]
-    fn try_from(value: &Cbor) -> Result<Self, Self::Error> {
-        match value.kind() {
-            ItemKind::Pos(value) => Ok(Number::Unsigned(value)),
-            ItemKind::Neg(value) => Ok(Number::Signed(-1 - (value as i64))),
-            ItemKind::Float(value) => Ok(Number::Float(value)),
-            _ => Err(()),
+    fn from(overflow: Overflow) -> Self {
+        Self {
+            overflow,
+            preserve: false,
         }
     }
 }
 
-impl From<Number> for CborOwned {
-    #[inline]
-    fn from(num: Number) -> Self {
-        match num {
-            Number::Signed(value) => cborpath::builder::IntoCborOwned::into(value),
-            Number::Unsigned(value) => cborpath::builder::IntoCborOwned::into(value),
-            Number::Float(value) => cborpath::builder::IntoCborOwned::into(value),
+impl NumOptions {
+    /// Parse the optional `SATURATE` or `PROMOTE` and `PRESERVE` arguments,
+    /// which must be the last ones
+    pub fn from_args<'a>(args: impl Iterator<Item = &'a RedisString>) -> Result<Self, RedisError> {
+        let mut options = NumOptions::default();
+
+        for arg in args {
+            match arg.try_as_str()? {
+                arg if arg.eq_ignore_ascii_case("SATURATE")
+                    && options.overflow == Overflow::Error =>
+                {
+                    options.overflow = Overflow::Saturate
+                }
+                arg if arg.eq_ignore_ascii_case("PROMOTE")
+                    && options.overflow == Overflow::Error =>
+                {
+                    options.overflow = Overflow::Promote
+                }
+                arg if arg.eq_ignore_ascii_case("PRESERVE") && !options.preserve => {
+                    options.preserve = true
+                }
+                _ => return Err(CborError::syntax().into()),
+            }
         }
+
+        Ok(options)
     }
 }
 
+/// Apply `operation` to each number matching `cbor_path` and `value`.
+///
+/// Matches are left unchanged, with a `null` result, when `value` is not a number.
 pub fn num_operation<F>(
     existing: &CborOwned,
     cbor_path: &CborPath,
     value: &Cbor,
+    options: NumOptions,
     mut operation: F,
-) -> (Option<CborOwned>, Vec<RedisValue>)
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError>
 where
-    F: FnMut(Number, Number) -> Option<Number>,
+    F: FnMut(Number, Number) -> Result<Option<Number>, CborError>,
+{
+    let operand = Number::try_from(value).ok();
+    let bignum = operand.as_ref().is_some_and(Number::is_bignum);
+
+    num_update(
+        existing,
+        cbor_path,
+        options,
+        bignum,
+        |number| match &operand {
+            Some(operand) => operation(number, operand.clone()),
+            None => Ok(None),
+        },
+    )
+}
+
+/// Apply `operation` to each number matching `cbor_path`,
+/// returning one result per match: the new number, or `null` if the match is not a number
+/// or if `operation` returns `None`.
+///
+/// Integer results out of the CBOR integer range are handled according to `overflow`,
+/// except when the number or one of the operands (`bignum_operand`) is a bignum:
+/// the result is then a bignum too.
+/// Integer results in the CBOR integer range are always written as basic integers,
+/// unless `preserve` is set: results then keep the major type of integers and the width of floats,
+/// see [`Encoding`].
+///
+/// The first error returned by `operation` fails the whole update.
+pub fn num_update<F>(
+    existing: &CborOwned,
+    cbor_path: &CborPath,
+    options: NumOptions,
+    bignum_operand: bool,
+    mut operation: F,
+) -> Result<(Option<CborOwned>, Vec<RedisValue>), CborError>
+where
+    F: FnMut(Number) -> Result<Option<Number>, CborError>,
 {
     let mut new_nums = Vec::<RedisValue>::new();
+    let mut error = None;
 
     let new_value = cbor_path
         .write(existing, |old_value| {
-            if let (Ok(v1), Ok(v2)) = (old_value.try_into(), value.try_into()) {
-                if let Some(result) = operation(v1, v2) {
-                    let result: CborOwned = result.into();
-                    new_nums.push(RedisValue::StringBuffer(result.clone().into_vec()));
-                    return Ok(Some(Cow::Owned(result)));
+            if error.is_some() {
+                return Ok(Some(Cow::Borrowed(old_value)));
+            }
+            if let Ok(number) = Number::try_from(old_value) {
+                let bignum = bignum_operand || number.is_bignum();
+                let encoding = if options.preserve {
+                    Encoding::of(old_value)
+                } else {
+                    Encoding::Any
+                };
+                let result = match operation(number) {
+                    Ok(Some(result)) => check_range(result, bignum, options.overflow)
+                        .and_then(|result| encoding.write(result))
+                        .map(Some),
+                    Ok(None) => Ok(None),
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(Some(result)) => {
+                        new_nums.push(RedisValue::StringBuffer(result.clone().into_vec()));
+                        return Ok(Some(Cow::Owned(result)));
+                    }
+                    Ok(None) => (),
+                    Err(e) => {
+                        error = Some(e);
+                        return Ok(Some(Cow::Borrowed(old_value)));
+                    }
                 }
             }
             new_nums.push(RedisValue::Null);
             Ok(Some(Cow::Borrowed(old_value)))
         })
-        .unwrap();
+        .map_err(CborError::path_write)?;
 
-    (new_value, new_nums)
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    Ok((new_value, new_nums))
+}
+
+/// Handle integer results out of the CBOR integer range
+fn check_range(result: Number, bignum: bool, overflow: Overflow) -> Result<Number, CborError> {
+    let value = match result {
+        Number::Integer(i) if (INTEGER_MIN..=INTEGER_MAX).contains(&i) => return Ok(result),
+        Number::Integer(i) => BigInt::from(i),
+        Number::BigNum(b) => b,
+        Number::Fraction(_) | Number::Float(_) => return Ok(result),
+    };
+
+    match i128::try_from(&value) {
+        Ok(i) if (INTEGER_MIN..=INTEGER_MAX).contains(&i) => Ok(Number::Integer(i)),
+        _ if bignum || overflow == Overflow::Promote => Ok(Number::BigNum(value)),
+        _ => match overflow {
+            Overflow::Saturate if value.is_negative() => Ok(Number::Integer(INTEGER_MIN)),
+            Overflow::Saturate => Ok(Number::Integer(INTEGER_MAX)),
+            _ => Err(CborError::integer_overflow()),
+        },
+    }
 }
@@ -0,0 +1,862 @@
+//! CBOR numbers and their arithmetic.
+//!
+//! Besides basic integers and floats, numbers include the tagged numbers of
+//! [RFC 8949 §3.4.3-3.4.4](https://datatracker.ietf.org/doc/html/rfc8949#section-3.4.3):
+//! - bignums (tags 2 and 3), arbitrary-precision integers
+//! - decimal fractions (tag 4) and bigfloats (tag 5), `[exponent, mantissa]` arrays
+//!   meaning `mantissa * 10^exponent` and `mantissa * 2^exponent`
+//!
+//! Arithmetic is exact, except when a float is involved, or when a decimal fraction
+//! is combined with a bigfloat, in which case the result is a float.
+
+use crate::{encoding::f16_bits, util::CborError};
+use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+
+const TAG_POSITIVE_BIGNUM: u64 = 2;
+const TAG_NEGATIVE_BIGNUM: u64 = 3;
+const TAG_DECIMAL_FRACTION: u64 = 4;
+const TAG_BIGFLOAT: u64 = 5;
+
+/// Smallest integer representable by CBOR major type 1: -2^64
+pub const INTEGER_MIN: i128 = -1 - u64::MAX as i128;
+/// Largest integer representable by CBOR major type 0: 2^64-1
+pub const INTEGER_MAX: i128 = u64::MAX as i128;
+
+/// Largest difference between the exponents of two fractions added together,
+/// to bound the size of the rescaled mantissas
+const MAX_EXPONENT_SHIFT: u32 = 1024;
+
+/// Largest size of a bignum computed by [`pow`]
+const MAX_POW_BITS: u64 = 64 * 1024;
+
+/// A CBOR number.
+///
+/// Basic integers are held in an `i128` so that arithmetic covers the full CBOR integer range
+/// (`-2^64..2^64-1`). Integer results out of this range are detected by
+/// [`num_operation`](super::num_operation::num_operation).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Number {
+    Integer(i128),
+    BigNum(BigInt),
+    Fraction(Fraction),
+    Float(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Base {
+    /// Decimal fraction
+    Ten,
+    /// Bigfloat
+    Two,
+}
+
+/// `mantissa * base^exponent`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fraction {
+    pub mantissa: BigInt,
+    pub exponent: i64,
+    pub base: Base,
+}
+
+impl Number {
+    #[inline]
+    pub fn is_bignum(&self) -> bool {
+        matches!(self, Number::BigNum(_))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Integer(i) => *i == 0,
+            Number::BigNum(b) => b.is_zero(),
+            Number::Fraction(f) => f.mantissa.is_zero(),
+            Number::Float(f) => *f == 0.0,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Integer(i) => *i as f64,
+            Number::BigNum(b) => b.to_f64().unwrap_or(f64::NAN),
+            Number::Fraction(f) => f.to_f64(),
+            Number::Float(f) => *f,
+        }
+    }
+}
+
+impl Base {
+    /// `base^shift`, for a shift between 0 and [`MAX_EXPONENT_SHIFT`]
+    fn pow(self, shift: i128) -> Result<BigInt, CborError> {
+        let shift = u32::try_from(shift)
+            .ok()
+            .filter(|shift| *shift <= MAX_EXPONENT_SHIFT)
+            .ok_or_else(|| {
+                CborError::Err("fraction exponents are too far apart to be combined".into())
+            })?;
+
+        let base = match self {
+            Base::Ten => BigInt::from(10),
+            Base::Two => BigInt::from(2),
+        };
+        Ok(base.pow(shift))
+    }
+}
+
+impl Fraction {
+    #[inline]
+    fn integer(mantissa: BigInt, base: Base) -> Self {
+        Self {
+            mantissa,
+            exponent: 0,
+            base,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let base = match self.base {
+            Base::Ten => 10f64,
+            Base::Two => 2f64,
+        };
+        self.mantissa.to_f64().unwrap_or(f64::NAN) * base.powf(self.exponent as f64)
+    }
+
+    /// Mantissa of this fraction expressed with a smaller or equal `exponent`
+    fn rescale(&self, exponent: i64) -> Result<BigInt, CborError> {
+        Ok(&self.mantissa * self.base.pow(self.exponent as i128 - exponent as i128)?)
+    }
+
+    /// Exact decimal representation, e.g. `"-12.50"` for `[-2, -1250]`
+    pub fn to_decimal_string(&self) -> Option<String> {
+        if self.base != Base::Ten {
+            return None;
+        }
+
+        let digits = self.mantissa.abs().to_string();
+        let sign = if self.mantissa.is_negative() { "-" } else { "" };
+
+        match self.exponent.cmp(&0) {
+            Ordering::Equal => Some(format!("{sign}{digits}")),
+            Ordering::Greater => {
+                let zeros = usize::try_from(self.exponent)
+                    .ok()
+                    .filter(|zeros| *zeros <= MAX_EXPONENT_SHIFT as usize)?;
+                Some(format!("{sign}{digits}{}", "0".repeat(zeros)))
+            }
+            Ordering::Less => {
+                let scale = usize::try_from(self.exponent.unsigned_abs())
+                    .ok()
+                    .filter(|scale| *scale <= MAX_EXPONENT_SHIFT as usize)?;
+                let digits = format!("{digits:0>width$}", width = scale + 1);
+                let (int, frac) = digits.split_at(digits.len() - scale);
+                Some(format!("{sign}{int}.{frac}"))
+            }
+        }
+    }
+}
+
+impl TryFrom<&Cbor> for Number {
+    type Error = ();
+
+    fn try_from(value: &Cbor) -> Result<Self, Self::Error> {
+        match (value.tags().last(), value.kind()) {
+            (Some(TAG_POSITIVE_BIGNUM), ItemKind::Bytes(bytes)) => Ok(Number::BigNum(
+                BigInt::from_bytes_be(num_bigint::Sign::Plus, &bytes.as_cow()),
+            )),
+            (Some(TAG_NEGATIVE_BIGNUM), ItemKind::Bytes(bytes)) => Ok(Number::BigNum(
+                BigInt::from(-1) - BigInt::from_bytes_be(num_bigint::Sign::Plus, &bytes.as_cow()),
+            )),
+            (Some(tag @ (TAG_DECIMAL_FRACTION | TAG_BIGFLOAT)), ItemKind::Array(mut array)) => {
+                let (Some(exponent), Some(mantissa), None) =
+                    (array.next(), array.next(), array.next())
+                else {
+                    return Err(());
+                };
+                let exponent = match exponent.kind() {
+                    ItemKind::Pos(e) => i64::try_from(e).map_err(|_| ())?,
+                    ItemKind::Neg(e) => -1 - i64::try_from(e).map_err(|_| ())?,
+                    _ => return Err(()),
+                };
+                let mantissa = match Number::try_from(mantissa)? {
+                    Number::Integer(i) => BigInt::from(i),
+                    Number::BigNum(b) => b,
+                    _ => return Err(()),
+                };
+                let base = if tag == TAG_DECIMAL_FRACTION {
+                    Base::Ten
+                } else {
+                    Base::Two
+                };
+                Ok(Number::Fraction(Fraction {
+                    mantissa,
+                    exponent,
+                    base,
+                }))
+            }
+            (_, ItemKind::Pos(value)) => Ok(Number::Integer(value as i128)),
+            (_, ItemKind::Neg(value)) => Ok(Number::Integer(-1 - value as i128)),
+            (_, ItemKind::Float(value)) => Ok(Number::Float(value)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<Number> for CborOwned {
+    #[inline]
+    fn from(num: Number) -> Self {
+        match num {
+            Number::Integer(value) if (0..=INTEGER_MAX).contains(&value) => {
+                CborBuilder::new().write_pos(value as u64, None)
+            }
+            Number::Integer(value) if (INTEGER_MIN..0).contains(&value) => {
+                CborBuilder::new().write_neg((-1 - value) as u64, None)
+            }
+            Number::Integer(value) => write_integer(CborBuilder::new(), &BigInt::from(value)),
+            Number::BigNum(value) => write_integer(CborBuilder::new(), &value),
+            Number::Fraction(fraction) => {
+                let tag = match fraction.base {
+                    Base::Ten => TAG_DECIMAL_FRACTION,
+                    Base::Two => TAG_BIGFLOAT,
+                };
+                CborBuilder::new().write_array(Some(tag), |builder| {
+                    write_integer(&mut *builder, &BigInt::from(fraction.exponent));
+                    write_integer(&mut *builder, &fraction.mantissa);
+                })
+            }
+            Number::Float(value) => cborpath::builder::IntoCborOwned::into(value),
+        }
+    }
+}
+
+/// Major type of a basic integer or width of a float, kept when writing a result
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Unsigned,
+    Negative,
+    Float16,
+    Float32,
+    Float64,
+    /// Tagged numbers, or encoding not preserved
+    Any,
+}
+
+impl Encoding {
+    pub fn of(value: &Cbor) -> Self {
+        match head(value) {
+            Some(head) if head >> 5 == 0 => Encoding::Unsigned,
+            Some(head) if head >> 5 == 1 => Encoding::Negative,
+            Some(0xf9) => Encoding::Float16,
+            Some(0xfa) => Encoding::Float32,
+            Some(0xfb) => Encoding::Float64,
+            _ => Encoding::Any,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Unsigned => "an unsigned integer",
+            Encoding::Negative => "a negative integer",
+            Encoding::Float16 => "a float16",
+            Encoding::Float32 => "a float32",
+            Encoding::Float64 => "a float64",
+            Encoding::Any => "a number",
+        }
+    }
+
+    /// Write `result` with this encoding,
+    /// failing if `result` cannot be represented exactly with it
+    pub fn write(self, result: Number) -> Result<CborOwned, CborError> {
+        let not_representable = || {
+            CborError::Err(format!("the result cannot be represented as {}", self.name()).into())
+        };
+
+        match self {
+            Encoding::Any => Ok(result.into()),
+            Encoding::Unsigned | Encoding::Negative => {
+                let integer = match result {
+                    Number::Integer(i) => Some(i),
+                    Number::Float(f) if f.fract() == 0.0 => Some(f as i128),
+                    _ => None,
+                };
+                match integer {
+                    Some(i) if compare(&Number::Integer(i), &result) != Some(Ordering::Equal) => {
+                        Err(not_representable())
+                    }
+                    Some(i) if self == Encoding::Unsigned && (0..=INTEGER_MAX).contains(&i) => {
+                        Ok(Number::Integer(i).into())
+                    }
+                    Some(i) if self == Encoding::Negative && (INTEGER_MIN..0).contains(&i) => {
+                        Ok(Number::Integer(i).into())
+                    }
+                    _ => Err(not_representable()),
+                }
+            }
+            Encoding::Float16 | Encoding::Float32 | Encoding::Float64 => {
+                let float = result.to_f64();
+                if !float.is_nan()
+                    && compare(&Number::Float(float), &result) != Some(Ordering::Equal)
+                {
+                    return Err(not_representable());
+                }
+
+                let mut buf = Vec::with_capacity(9);
+                match self {
+                    Encoding::Float16 => {
+                        let half = f16_bits(float).ok_or_else(not_representable)?;
+                        buf.push(0xf9);
+                        buf.extend_from_slice(&half.to_be_bytes());
+                    }
+                    Encoding::Float32 => {
+                        let single = float as f32;
+                        if !float.is_nan() && single as f64 != float {
+                            return Err(not_representable());
+                        }
+                        buf.push(0xfa);
+                        buf.extend_from_slice(&single.to_bits().to_be_bytes());
+                    }
+                    _ => {
+                        buf.push(0xfb);
+                        buf.extend_from_slice(&float.to_bits().to_be_bytes());
+                    }
+                }
+                Ok(CborOwned::unchecked(buf))
+            }
+        }
+    }
+}
+
+/// Initial byte of the data item of `value`, after its tags
+fn head(value: &Cbor) -> Option<u8> {
+    let bytes = value.as_slice();
+    let mut pos = 0;
+    loop {
+        let head = *bytes.get(pos)?;
+        if head >> 5 != 6 {
+            return Some(head);
+        }
+        pos += match head & 0x1f {
+            0..=23 => 1,
+            24 => 2,
+            25 => 3,
+            26 => 5,
+            27 => 9,
+            _ => return None,
+        };
+    }
+}
+
+/// Write `value` as a basic integer, or as a bignum when out of the basic integer range
+fn write_integer<W: Writer>(writer: W, value: &BigInt) -> W::Output {
+    if let Ok(value) = u64::try_from(value) {
+        writer.write_pos(value, None)
+    } else if value.is_negative() {
+        let value = BigInt::from(-1) - value;
+        match u64::try_from(&value) {
+            Ok(value) => writer.write_neg(value, None),
+            Err(_) => writer.write_bytes(&value.to_bytes_be().1, Some(TAG_NEGATIVE_BIGNUM)),
+        }
+    } else {
+        writer.write_bytes(&value.to_bytes_be().1, Some(TAG_POSITIVE_BIGNUM))
+    }
+}
+
+/// Operands converted to a common representation
+enum Operands {
+    Integers(i128, i128),
+    BigNums(BigInt, BigInt),
+    Fractions(Fraction, Fraction),
+    Floats(f64, f64),
+}
+
+fn coerce(n1: Number, n2: Number) -> Operands {
+    match (n1, n2) {
+        (Number::Integer(i1), Number::Integer(i2)) => Operands::Integers(i1, i2),
+        (n1 @ Number::Float(_), n2) | (n1, n2 @ Number::Float(_)) => {
+            Operands::Floats(n1.to_f64(), n2.to_f64())
+        }
+        (Number::Fraction(f1), Number::Fraction(f2)) if f1.base != f2.base => {
+            Operands::Floats(f1.to_f64(), f2.to_f64())
+        }
+        (Number::Fraction(f1), Number::Fraction(f2)) => Operands::Fractions(f1, f2),
+        (Number::Fraction(f), Number::Integer(i)) => {
+            let base = f.base;
+            Operands::Fractions(f, Fraction::integer(i.into(), base))
+        }
+        (Number::Fraction(f), Number::BigNum(b)) => {
+            let base = f.base;
+            Operands::Fractions(f, Fraction::integer(b, base))
+        }
+        (Number::Integer(i), Number::Fraction(f)) => {
+            Operands::Fractions(Fraction::integer(i.into(), f.base), f)
+        }
+        (Number::BigNum(b), Number::Fraction(f)) => {
+            Operands::Fractions(Fraction::integer(b, f.base), f)
+        }
+        (Number::Integer(i1), Number::BigNum(b2)) => Operands::BigNums(i1.into(), b2),
+        (Number::BigNum(b1), Number::Integer(i2)) => Operands::BigNums(b1, i2.into()),
+        (Number::BigNum(b1), Number::BigNum(b2)) => Operands::BigNums(b1, b2),
+    }
+}
+
+pub fn add(n1: Number, n2: Number) -> Result<Number, CborError> {
+    let result = match coerce(n1, n2) {
+        Operands::Integers(i1, i2) => match i1.checked_add(i2) {
+            Some(i) => Number::Integer(i),
+            None => Number::BigNum(BigInt::from(i1) + BigInt::from(i2)),
+        },
+        Operands::BigNums(b1, b2) => Number::BigNum(b1 + b2),
+        Operands::Fractions(f1, f2) => {
+            let exponent = f1.exponent.min(f2.exponent);
+            Number::Fraction(Fraction {
+                mantissa: f1.rescale(exponent)? + f2.rescale(exponent)?,
+                exponent,
+                base: f1.base,
+            })
+        }
+        Operands::Floats(f1, f2) => Number::Float(f1 + f2),
+    };
+
+    Ok(result)
+}
+
+pub fn mul(n1: Number, n2: Number) -> Result<Number, CborError> {
+    let result = match coerce(n1, n2) {
+        Operands::Integers(i1, i2) => match i1.checked_mul(i2) {
+            Some(i) => Number::Integer(i),
+            None => Number::BigNum(BigInt::from(i1) * BigInt::from(i2)),
+        },
+        Operands::BigNums(b1, b2) => Number::BigNum(b1 * b2),
+        Operands::Fractions(f1, f2) => Number::Fraction(Fraction {
+            mantissa: f1.mantissa * f2.mantissa,
+            exponent: f1.exponent.checked_add(f2.exponent).ok_or_else(|| {
+                CborError::Err("fraction exponent overflow - the result is too large".into())
+            })?,
+            base: f1.base,
+        }),
+        Operands::Floats(f1, f2) => Number::Float(f1 * f2),
+    };
+
+    Ok(result)
+}
+
+fn division_by_zero() -> CborError {
+    CborError::Err("division by zero".into())
+}
+
+/// Ordering of two numbers, `None` if one of them is NaN
+pub fn compare(n1: &Number, n2: &Number) -> Option<Ordering> {
+    match coerce(n1.clone(), n2.clone()) {
+        Operands::Integers(i1, i2) => Some(i1.cmp(&i2)),
+        Operands::BigNums(b1, b2) => Some(b1.cmp(&b2)),
+        Operands::Fractions(f1, f2) => {
+            let exponent = f1.exponent.min(f2.exponent);
+            match (f1.rescale(exponent), f2.rescale(exponent)) {
+                (Ok(m1), Ok(m2)) => Some(m1.cmp(&m2)),
+                _ => f1.to_f64().partial_cmp(&f2.to_f64()),
+            }
+        }
+        Operands::Floats(f1, f2) => f1.partial_cmp(&f2),
+    }
+}
+
+/// Smallest of two numbers, `None` if one of them is NaN
+pub fn min(n1: Number, n2: Number) -> Option<Number> {
+    match compare(&n1, &n2)? {
+        Ordering::Greater => Some(n2),
+        _ => Some(n1),
+    }
+}
+
+/// Largest of two numbers, `None` if one of them is NaN
+pub fn max(n1: Number, n2: Number) -> Option<Number> {
+    match compare(&n1, &n2)? {
+        Ordering::Less => Some(n2),
+        _ => Some(n1),
+    }
+}
+
+/// Division, truncated toward zero for integers,
+/// and to the smaller exponent of `n1` and `n2` for decimal fractions and bigfloats
+pub fn div(n1: Number, n2: Number) -> Result<Number, CborError> {
+    if n2.is_zero() {
+        return Err(division_by_zero());
+    }
+
+    let result = match coerce(n1, n2) {
+        Operands::Integers(i1, i2) => Number::Integer(i1 / i2),
+        Operands::BigNums(b1, b2) => Number::BigNum(b1 / b2),
+        Operands::Fractions(f1, f2) => {
+            // f1 / f2 = (m1 * base^(e1 - e2 - e) / m2) * base^e
+            let exponent = f1.exponent.min(f2.exponent);
+            let shift = f1.exponent as i128 - f2.exponent as i128 - exponent as i128;
+            let mantissa = if shift >= 0 {
+                f1.mantissa * f2.base.pow(shift)? / f2.mantissa
+            } else {
+                f1.mantissa / (f2.mantissa * f2.base.pow(-shift)?)
+            };
+            Number::Fraction(Fraction {
+                mantissa,
+                exponent,
+                base: f1.base,
+            })
+        }
+        Operands::Floats(f1, f2) => Number::Float(f1 / f2),
+    };
+
+    Ok(result)
+}
+
+/// Remainder of the division truncated toward zero, of the same sign as `n1`
+pub fn rem(n1: Number, n2: Number) -> Result<Number, CborError> {
+    if n2.is_zero() {
+        return Err(division_by_zero());
+    }
+
+    let result = match coerce(n1, n2) {
+        Operands::Integers(i1, i2) => Number::Integer(i1 % i2),
+        Operands::BigNums(b1, b2) => Number::BigNum(b1 % b2),
+        Operands::Fractions(f1, f2) => {
+            let exponent = f1.exponent.min(f2.exponent);
+            Number::Fraction(Fraction {
+                mantissa: f1.rescale(exponent)? % f2.rescale(exponent)?,
+                exponent,
+                base: f1.base,
+            })
+        }
+        Operands::Floats(f1, f2) => Number::Float(f1 % f2),
+    };
+
+    Ok(result)
+}
+
+/// `n1` raised to the power `n2`, exact when `n2` is a non-negative integer
+/// and `n1` is not a float
+pub fn pow(n1: Number, n2: Number) -> Result<Number, CborError> {
+    let exponent = match n2 {
+        Number::Integer(i) => u32::try_from(i).ok(),
+        _ => None,
+    };
+    let Some(exponent) = exponent else {
+        return Ok(Number::Float(n1.to_f64().powf(n2.to_f64())));
+    };
+
+    let big_pow = |b: &BigInt| {
+        // 0, 1 and -1 raised to any power stay as small
+        if b.bits() > 1 && b.bits().saturating_mul(exponent as u64) > MAX_POW_BITS {
+            Err(CborError::Err(
+                "the result of the power is too large".into(),
+            ))
+        } else {
+            Ok(b.pow(exponent))
+        }
+    };
+
+    let result = match n1 {
+        Number::Integer(i) => match i.checked_pow(exponent) {
+            Some(i) => Number::Integer(i),
+            None => Number::BigNum(big_pow(&BigInt::from(i))?),
+        },
+        Number::BigNum(b) => Number::BigNum(big_pow(&b)?),
+        Number::Fraction(f) => Number::Fraction(Fraction {
+            mantissa: big_pow(&f.mantissa)?,
+            exponent: f.exponent.checked_mul(exponent as i64).ok_or_else(|| {
+                CborError::Err("fraction exponent overflow - the result is too large".into())
+            })?,
+            base: f.base,
+        }),
+        Number::Float(f) => Number::Float(f.powf(exponent as f64)),
+    };
+
+    Ok(result)
+}
+
+pub fn abs(n: Number) -> Number {
+    match n {
+        Number::Integer(i) => Number::Integer(i.abs()),
+        Number::BigNum(b) => Number::BigNum(b.abs()),
+        Number::Fraction(f) => Number::Fraction(Fraction {
+            mantissa: f.mantissa.abs(),
+            ..f
+        }),
+        Number::Float(f) => Number::Float(f.abs()),
+    }
+}
+
+/// Bitwise operation on two integers, in two's complement,
+/// `None` if one of the numbers is not an integer
+fn bitwise(
+    n1: Number,
+    n2: Number,
+    op: fn(i128, i128) -> i128,
+    big_op: fn(BigInt, BigInt) -> BigInt,
+) -> Option<Number> {
+    match (n1, n2) {
+        (Number::Integer(i1), Number::Integer(i2)) => Some(Number::Integer(op(i1, i2))),
+        (Number::Integer(i1), Number::BigNum(b2)) => Some(Number::BigNum(big_op(i1.into(), b2))),
+        (Number::BigNum(b1), Number::Integer(i2)) => Some(Number::BigNum(big_op(b1, i2.into()))),
+        (Number::BigNum(b1), Number::BigNum(b2)) => Some(Number::BigNum(big_op(b1, b2))),
+        _ => None,
+    }
+}
+
+pub fn bit_and(n1: Number, n2: Number) -> Option<Number> {
+    bitwise(n1, n2, |i1, i2| i1 & i2, |b1, b2| b1 & b2)
+}
+
+pub fn bit_or(n1: Number, n2: Number) -> Option<Number> {
+    bitwise(n1, n2, |i1, i2| i1 | i2, |b1, b2| b1 | b2)
+}
+
+pub fn bit_xor(n1: Number, n2: Number) -> Option<Number> {
+    bitwise(n1, n2, |i1, i2| i1 ^ i2, |b1, b2| b1 ^ b2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        abs, add, bit_and, bit_or, bit_xor, div, max, min, mul, pow, rem, Base, Fraction, Number,
+    };
+    use crate::util::{cbor_to_diag, diag_to_cbor};
+    use cbor_data::CborOwned;
+    use num_bigint::BigInt;
+
+    fn number(diag: &str) -> Number {
+        Number::try_from(&*diag_to_cbor(diag)).unwrap()
+    }
+
+    fn diag(number: Number) -> String {
+        cbor_to_diag(&CborOwned::from(number))
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(
+            Number::Integer(-18446744073709551616),
+            number("-18446744073709551616")
+        );
+        assert_eq!(
+            Number::BigNum(BigInt::from(18446744073709551616u128)),
+            number("2(h'010000000000000000')")
+        );
+        assert_eq!(
+            Number::BigNum(BigInt::from(-18446744073709551617i128)),
+            number("3(h'010000000000000000')")
+        );
+        assert_eq!(
+            Number::Fraction(Fraction {
+                mantissa: BigInt::from(27315),
+                exponent: -2,
+                base: Base::Ten
+            }),
+            number("4([-2, 27315])")
+        );
+        assert_eq!(
+            Number::Fraction(Fraction {
+                mantissa: BigInt::from(3),
+                exponent: -1,
+                base: Base::Two
+            }),
+            number("5([-1, 3])")
+        );
+        assert!(Number::try_from(&*diag_to_cbor(r#"4(["a", 1])"#)).is_err());
+        assert!(Number::try_from(&*diag_to_cbor("4([1, 2, 3])")).is_err());
+        assert!(Number::try_from(&*diag_to_cbor(r#"2("a")"#)).is_err());
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(
+            "18446744073709551615",
+            diag(number("2(h'ffffffffffffffff')"))
+        );
+        assert_eq!(
+            "2(h'010000000000000000')",
+            diag(number("2(h'010000000000000000')"))
+        );
+        assert_eq!(
+            "3(h'010000000000000000')",
+            diag(number("3(h'010000000000000000')"))
+        );
+        assert_eq!(
+            "4([-2,2(h'010000000000000000')])",
+            diag(number("4([-2, 2(h'010000000000000000')])"))
+        );
+    }
+
+    #[test]
+    fn bignum_arithmetic() {
+        assert_eq!(
+            "2(h'010000000000000000')",
+            diag(add(number("18446744073709551615"), number("1")).unwrap())
+        );
+        assert_eq!(
+            "18446744073709551615",
+            diag(add(number("2(h'010000000000000000')"), number("-1")).unwrap())
+        );
+        assert_eq!(
+            "2(h'0100000000000000000000000000000000')",
+            diag(
+                mul(
+                    number("2(h'010000000000000000')"),
+                    number("2(h'010000000000000000')")
+                )
+                .unwrap()
+            )
+        );
+        assert_eq!(
+            "3(h'01ffffffffffffffff')",
+            diag(mul(number("-18446744073709551616"), number("2")).unwrap())
+        );
+    }
+
+    #[test]
+    fn fraction_arithmetic() {
+        assert_eq!(
+            "4([-2,1370])",
+            diag(add(number("4([-2, 1250])"), number("4([-1, 12])")).unwrap())
+        );
+        assert_eq!(
+            "4([-2,1450])",
+            diag(add(number("4([-2, 1250])"), number("2")).unwrap())
+        );
+        assert_eq!(
+            "4([-3,156250])",
+            diag(mul(number("4([-2, 1250])"), number("4([-1, 125])")).unwrap())
+        );
+        assert_eq!(
+            "5([-1,7])",
+            diag(add(number("5([-1, 3])"), number("2")).unwrap())
+        );
+        assert_eq!(
+            diag_to_cbor("2.0"),
+            CborOwned::from(add(number("4([-1, 5])"), number("5([-1, 3])")).unwrap())
+        );
+        assert_eq!(
+            diag_to_cbor("2.5"),
+            CborOwned::from(add(number("4([-1, 5])"), number("2.0")).unwrap())
+        );
+        assert!(add(number("4([-2000, 1])"), number("1")).is_err());
+    }
+
+    #[test]
+    fn decimal_string() {
+        let decimal = |diag: &str| match number(diag) {
+            Number::Fraction(f) => f.to_decimal_string(),
+            _ => None,
+        };
+
+        assert_eq!(Some("-12.50".to_owned()), decimal("4([-2, -1250])"));
+        assert_eq!(Some("0.005".to_owned()), decimal("4([-3, 5])"));
+        assert_eq!(Some("1200".to_owned()), decimal("4([2, 12])"));
+        assert_eq!(Some("12".to_owned()), decimal("4([0, 12])"));
+        assert_eq!(None, decimal("5([-1, 3])"));
+    }
+
+    #[test]
+    fn min_max() {
+        assert_eq!("2", diag(min(number("2"), number("3")).unwrap()));
+        assert_eq!("2.5", diag(max(number("2"), number("2.5")).unwrap()));
+        assert_eq!(
+            "4([-2,150])",
+            diag(min(number("4([-2,150])"), number("2")).unwrap())
+        );
+        assert_eq!(
+            "2(h'010000000000000000')",
+            diag(max(number("2(h'010000000000000000')"), number("-1")).unwrap())
+        );
+        assert!(min(number("1"), number("NaN")).is_none());
+    }
+
+    #[test]
+    fn div_rem() {
+        assert_eq!("-3", diag(div(number("-7"), number("2")).unwrap()));
+        assert_eq!("-1", diag(rem(number("-7"), number("2")).unwrap()));
+        assert_eq!(
+            "4([-2,333])",
+            diag(div(number("4([-2,1000])"), number("3")).unwrap())
+        );
+        assert_eq!(
+            "4([-2,400])",
+            diag(div(number("4([-2,1000])"), number("4([-1,25])")).unwrap())
+        );
+        // the quotient keeps the precision of the divisor
+        assert_eq!(
+            "4([-2,3333])",
+            diag(div(number("1"), number("4([-2,3])")).unwrap())
+        );
+        assert_eq!(
+            "4([0,0])",
+            diag(div(number("4([0,1])"), number("4([2,3])")).unwrap())
+        );
+        assert_eq!(
+            "4([0,333])",
+            diag(div(number("4([3,1])"), number("4([0,3])")).unwrap())
+        );
+        assert_eq!(
+            "4([-1,5])",
+            diag(rem(number("4([-1,35])"), number("1")).unwrap())
+        );
+        assert_eq!(
+            diag_to_cbor("3.5"),
+            CborOwned::from(div(number("7.0"), number("2")).unwrap())
+        );
+        assert!(div(number("1"), number("0")).is_err());
+        assert!(rem(number("1.0"), number("4([-2,0])")).is_err());
+    }
+
+    #[test]
+    fn pow_abs() {
+        assert_eq!("1024", diag(pow(number("2"), number("10")).unwrap()));
+        assert_eq!(
+            "2(h'0100000000000000000000000000000000')",
+            diag(pow(number("2"), number("128")).unwrap())
+        );
+        assert_eq!(
+            "4([-4,15625])",
+            diag(pow(number("4([-2,125])"), number("2")).unwrap())
+        );
+        assert_eq!(
+            diag_to_cbor("0.5"),
+            CborOwned::from(pow(number("2"), number("-1")).unwrap())
+        );
+        assert!(pow(number("2"), number("100000")).is_err());
+        assert_eq!(
+            "1",
+            diag(pow(number("2(h'01')"), number("100000")).unwrap())
+        );
+        assert_eq!(
+            "-1",
+            diag(pow(number("3(h'00')"), number("100001")).unwrap())
+        );
+        assert_eq!("0", diag(pow(number("2(h'')"), number("100000")).unwrap()));
+        assert_eq!(
+            "4([-200000,1])",
+            diag(pow(number("4([-2,1])"), number("100000")).unwrap())
+        );
+        assert_eq!(
+            "4([0,-1])",
+            diag(pow(number("4([0,-1])"), number("100001")).unwrap())
+        );
+
+        assert_eq!(
+            "2(h'010000000000000000')",
+            diag(abs(number("-18446744073709551616")))
+        );
+        assert_eq!("4([-2,1250])", diag(abs(number("4([-2,-1250])"))));
+        assert_eq!("2", diag(abs(number("-2"))));
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!("4", diag(bit_and(number("12"), number("6")).unwrap()));
+        assert_eq!("14", diag(bit_or(number("12"), number("6")).unwrap()));
+        assert_eq!("10", diag(bit_xor(number("12"), number("6")).unwrap()));
+        assert_eq!("-8", diag(bit_and(number("-1"), number("-8")).unwrap()));
+        assert_eq!(
+            "2(h'010000000000000001')",
+            diag(bit_or(number("2(h'010000000000000000')"), number("1")).unwrap())
+        );
+        assert!(bit_and(number("1.0"), number("1")).is_none());
+    }
+}
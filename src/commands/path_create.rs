@@ -0,0 +1,195 @@
+use crate::util::CborError;
+use cbor_data::{Cbor, CborBuilder, CborOwned, ItemKind, Writer};
+
+/// A segment of a definite path, designating a single value
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    Key(&'a Cbor),
+    Index(i64),
+}
+
+/// Split the CBOR encoding of a path into its segments,
+/// or `None` if the path is not made of keys and indexes only
+pub fn definite_segments(path: &[u8]) -> Option<Vec<Segment<'_>>> {
+    let ItemKind::Array(segments) = Cbor::unchecked(path).kind() else {
+        return None;
+    };
+
+    // the first segment is the root identifier `$`
+    segments
+        .skip(1)
+        .map(|segment| match segment.kind() {
+            ItemKind::Dict(mut selector) => match (selector.next(), selector.next()) {
+                (Some((k, v)), None) if matches!(k.kind(), ItemKind::Str(s) if s.as_cow() == "#") => {
+                    match v.kind() {
+                        ItemKind::Pos(index) => i64::try_from(index).ok().map(Segment::Index),
+                        ItemKind::Neg(index) => {
+                            i64::try_from(index).ok().map(|index| Segment::Index(-1 - index))
+                        }
+                        _ => None,
+                    }
+                }
+                // wildcards, slices, filters and descendants
+                _ => None,
+            },
+            // unions
+            ItemKind::Array(_) => None,
+            _ => Some(Segment::Key(segment)),
+        })
+        .collect()
+}
+
+/// Write `value` at the location designated by `segments` in `existing`,
+/// creating the missing maps, for key segments, and arrays, for index segments, along the way.
+///
+/// `existing` is `None` when the document does not exist.
+/// A missing array element can only be created at the end of its array.
+pub fn create_path(
+    existing: Option<&Cbor>,
+    segments: &[Segment],
+    value: &Cbor,
+) -> Result<CborOwned, CborError> {
+    let Some((segment, segments)) = segments.split_first() else {
+        return Ok(value.to_owned());
+    };
+
+    match segment {
+        Segment::Key(key) => {
+            let entries = match existing.map(Cbor::kind) {
+                None => Vec::new(),
+                Some(ItemKind::Dict(dict)) => dict.collect::<Vec<_>>(),
+                Some(_) => {
+                    return Err(CborError::WrongType(
+                        "cannot create the path - a parent value is not a map".into(),
+                    ))
+                }
+            };
+
+            let child = entries.iter().find(|(k, _)| k == key).map(|(_, v)| *v);
+            let child = create_path(child, segments, value)?;
+
+            Ok(CborBuilder::new().write_dict(None, |builder| {
+                for (k, v) in &entries {
+                    let v = if k == key { &*child } else { *v };
+                    builder.with_cbor_key(|b| b.write_item(k), |b| b.write_item(v));
+                }
+                if !entries.iter().any(|(k, _)| k == key) {
+                    builder.with_cbor_key(|b| b.write_item(key), |b| b.write_item(&child));
+                }
+            }))
+        }
+        Segment::Index(index) => {
+            let items = match existing.map(Cbor::kind) {
+                None => Vec::new(),
+                Some(ItemKind::Array(array)) => array.collect::<Vec<_>>(),
+                Some(_) => {
+                    return Err(CborError::WrongType(
+                        "cannot create the path - a parent value is not an array".into(),
+                    ))
+                }
+            };
+
+            let len = items.len() as i64;
+            let position = if *index < 0 { len + index } else { *index };
+            if !(0..=len).contains(&position) {
+                return Err(CborError::Err(
+                    "cannot create the path - index out of range".into(),
+                ));
+            }
+            let position = position as usize;
+
+            let child = create_path(items.get(position).copied(), segments, value)?;
+
+            Ok(CborBuilder::new().write_array(None, |builder| {
+                for (i, item) in items.iter().enumerate() {
+                    let item: &Cbor = if i == position { &child } else { item };
+                    builder.write_item(item);
+                }
+                if position == items.len() {
+                    builder.write_item(&child);
+                }
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_path, definite_segments, Segment};
+    use crate::{
+        path_syntax,
+        util::{cbor_to_diag, diag_to_cbor, CborError},
+    };
+
+    fn create(existing: Option<&str>, path: &str, value: &str) -> Result<String, CborError> {
+        let existing = existing.map(diag_to_cbor);
+        let path = path_syntax::parse(path).unwrap();
+        let segments = definite_segments(&path).unwrap();
+        let value = diag_to_cbor(value);
+        create_path(existing.as_deref(), &segments, &value).map(|cbor| cbor_to_diag(&cbor))
+    }
+
+    #[test]
+    fn segments() {
+        let path = path_syntax::parse(r#"$.a[1]["b"][-1]"#).unwrap();
+        let a = diag_to_cbor(r#""a""#);
+        let b = diag_to_cbor(r#""b""#);
+        assert_eq!(
+            Some(vec![
+                Segment::Key(&a),
+                Segment::Index(1),
+                Segment::Key(&b),
+                Segment::Index(-1)
+            ]),
+            definite_segments(&path)
+        );
+
+        for path in ["$.a.*", "$..a", "$.a[1:2]", "$.a[?@.b]", r#"$["a","b"]"#] {
+            let path = path_syntax::parse(path).unwrap();
+            assert_eq!(None, definite_segments(&path));
+        }
+    }
+
+    #[test]
+    fn create_missing() {
+        assert_eq!(
+            Ok(r#"{"a":[{"b":1}]}"#.to_owned()),
+            create(None, "$.a[0].b", "1")
+        );
+        assert_eq!(
+            Ok(r#"{"a":[{"b":1}],"c":{"d":2}}"#.to_owned()),
+            create(Some(r#"{"a":[{"b":1}]}"#), "$.c.d", "2")
+        );
+        assert_eq!(
+            Ok(r#"{"a":[{"b":1,"c":2}]}"#.to_owned()),
+            create(Some(r#"{"a":[{"b":1}]}"#), "$.a[-1].c", "2")
+        );
+        assert_eq!(
+            Ok(r#"{"a":[{"b":1},{"c":2}]}"#.to_owned()),
+            create(Some(r#"{"a":[{"b":1}]}"#), "$.a[1].c", "2")
+        );
+        assert_eq!(Ok("3".to_owned()), create(Some("[1,2]"), "$", "3"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(CborError::Err(
+                "cannot create the path - index out of range".into()
+            )),
+            create(None, "$.a[1]", "1")
+        );
+        assert_eq!(
+            Err(CborError::WrongType(
+                "cannot create the path - a parent value is not a map".into()
+            )),
+            create(Some(r#"{"a":[]}"#), "$.a.b", "1")
+        );
+        assert_eq!(
+            Err(CborError::WrongType(
+                "cannot create the path - a parent value is not an array".into()
+            )),
+            create(Some(r#"{"a":{}}"#), "$.a[0]", "1")
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Module configuration.
+//!
+//! Settings can be given as `loadmodule` arguments, e.g. `loadmodule librecbor.so max-depth 64`,
+//! and read or changed at runtime with `CONFIG GET recbor.<name>` and `CONFIG SET recbor.<name> <value>`:
+//! * `max-document-size`: maximum size in bytes of a written document, `0` for no limit
+//! * `max-depth`: maximum nesting depth of arrays and maps in a written document, up to [`MAX_DEPTH`]
+//! * `path-syntax`: which path arguments are accepted, see [`PathSyntax`]
+//! * `output-format`: format of the value replies when no `FORMAT` option is given
+//! * `replication`: see [`ReplicationMode`]
+//! * `replication-threshold`: see [`full_document_threshold`]
+//! * `digest-mode`: see [`DigestMode`]
+//! * `detailed-events`: whether changes are also published on [`EVENTS_CHANNEL`]
+
+use crate::{
+    digest::{digest_mode, set_digest_mode, DigestMode},
+    events::{detailed_events, set_detailed_events, EVENTS_CHANNEL},
+    replication::{
+        full_document_threshold, replication_mode, set_full_document_threshold,
+        set_replication_mode, ReplicationMode,
+    },
+    util::{CborError, Format, MAX_DEPTH},
+};
+use redis_module::{
+    configuration::{ConfigurationContext, ConfigurationValue, EnumConfigurationValue},
+    RedisError,
+};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// Syntax of the path arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PathSyntax {
+    /// Textual paths, starting with `$`, and CBOR encoded paths
+    Auto = 0,
+    /// CBOR encoded paths only
+    Cbor = 1,
+    /// Textual paths only
+    Text = 2,
+}
+
+static MAX_DOCUMENT_SIZE: AtomicUsize = AtomicUsize::new(0);
+static MAX_WRITE_DEPTH: AtomicUsize = AtomicUsize::new(MAX_DEPTH);
+static PATH_SYNTAX: AtomicU8 = AtomicU8::new(PathSyntax::Auto as u8);
+static OUTPUT_FORMAT: AtomicU8 = AtomicU8::new(Format::Cbor as u8);
+
+/// Maximum size in bytes of a written document, if any
+#[inline]
+pub fn max_document_size() -> Option<usize> {
+    match MAX_DOCUMENT_SIZE.load(Ordering::Relaxed) {
+        0 => None,
+        size => Some(size),
+    }
+}
+
+/// Maximum nesting depth of arrays and maps in a written document
+#[inline]
+pub fn max_depth() -> usize {
+    MAX_WRITE_DEPTH.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub fn path_syntax() -> PathSyntax {
+    match PATH_SYNTAX.load(Ordering::Relaxed) {
+        1 => PathSyntax::Cbor,
+        2 => PathSyntax::Text,
+        _ => PathSyntax::Auto,
+    }
+}
+
+/// Format of the value replies when no `FORMAT` option is given
+#[inline]
+pub fn output_format() -> Format {
+    match OUTPUT_FORMAT.load(Ordering::Relaxed) {
+        1 => Format::Json,
+        2 => Format::Diag,
+        _ => Format::Cbor,
+    }
+}
+
+/// `max-document-size`
+pub struct MaxDocumentSize;
+
+impl ConfigurationValue<i64> for MaxDocumentSize {
+    fn get(&self, _ctx: &ConfigurationContext) -> i64 {
+        MAX_DOCUMENT_SIZE.load(Ordering::Relaxed) as i64
+    }
+
+    fn set(&self, _ctx: &ConfigurationContext, val: i64) -> Result<(), RedisError> {
+        let size = usize::try_from(val)
+            .map_err(|_| CborError::Err("max-document-size must not be negative".into()))?;
+        MAX_DOCUMENT_SIZE.store(size, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// `max-depth`
+pub struct MaxDepth;
+
+impl ConfigurationValue<i64> for MaxDepth {
+    fn get(&self, _ctx: &ConfigurationContext) -> i64 {
+        max_depth() as i64
+    }
+
+    fn set(&self, _ctx: &ConfigurationContext, val: i64) -> Result<(), RedisError> {
+        let depth = usize::try_from(val)
+            .ok()
+            .filter(|depth| (1..=MAX_DEPTH).contains(depth))
+            .ok_or_else(|| {
+                CborError::Err(format!("max-depth must be between 1 and {MAX_DEPTH}").into())
+            })?;
+        MAX_WRITE_DEPTH.store(depth, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// `path-syntax`
+pub struct PathSyntaxConfig;
+
+impl ConfigurationValue<PathSyntax> for PathSyntaxConfig {
+    fn get(&self, _ctx: &ConfigurationContext) -> PathSyntax {
+        path_syntax()
+    }
+
+    fn set(&self, _ctx: &ConfigurationContext, val: PathSyntax) -> Result<(), RedisError> {
+        PATH_SYNTAX.store(val as u8, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// `output-format`
+pub struct OutputFormat;
+
+impl ConfigurationValue<Format> for OutputFormat {
+    fn get(&self, _ctx: &ConfigurationContext) -> Format {
+        output_format()
+    }
+
+    fn set(&self, _ctx: &ConfigurationContext, val: Format) -> Result<(), RedisError> {
+        OUTPUT_FORMAT.store(val as u8, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// `replication`
+pub struct Replication;
+
+impl ConfigurationValue<ReplicationMode> for Replication {
+    fn get(&self, _ctx: &ConfigurationContext) -> ReplicationMode {
+        replication_mode()
+    }
+
+    fn set(&self, _ctx: &ConfigurationContext, val: ReplicationMode) -> Result<(), RedisError> {
+        set_replication_mode(val);
+        Ok(())
+    }
+}
+
+/// `replication-threshold`
+pub struct ReplicationThreshold;
+
+impl ConfigurationValue<i64> for ReplicationThreshold {
+    fn get(&self, _ctx: &ConfigurationContext) -> i64 {
+        full_document_threshold() as i64
+    }
+
+    fn set(&self, _ctx: &ConfigurationContext, val: i64) -> Result<(), RedisError> {
+        let threshold = usize::try_from(val)
+            .map_err(|_| CborError::Err("replication-threshold must not be negative".into()))?;
+        set_full_document_threshold(threshold);
+        Ok(())
+    }
+}
+
+/// `digest-mode`
+pub struct DigestModeConfig;
+
+impl ConfigurationValue<DigestMode> for DigestModeConfig {
+    fn get(&self, _ctx: &ConfigurationContext) -> DigestMode {
+        digest_mode()
+    }
+
+    fn set(&self, _ctx: &ConfigurationContext, val: DigestMode) -> Result<(), RedisError> {
+        set_digest_mode(val);
+        Ok(())
+    }
+}
+
+/// `detailed-events`
+pub struct DetailedEvents;
+
+impl ConfigurationValue<bool> for DetailedEvents {
+    fn get(&self, _ctx: &ConfigurationContext) -> bool {
+        detailed_events()
+    }
+
+    fn set(&self, _ctx: &ConfigurationContext, val: bool) -> Result<(), RedisError> {
+        set_detailed_events(val);
+        Ok(())
+    }
+}
+
+/// Implement the enum configuration conversions of a fieldless enum,
+/// its configuration values being the lower case names of its variants
+macro_rules! enum_configuration {
+    ($type:ty { $($variant:path => $name:literal),+ $(,)? }) => {
+        impl TryFrom<i32> for $type {
+            type Error = RedisError;
+
+            fn try_from(value: i32) -> Result<Self, Self::Error> {
+                $(if value == $variant as i32 {
+                    return Ok($variant);
+                })+
+                Err(RedisError::Str("unknown configuration value"))
+            }
+        }
+
+        impl From<$type> for i32 {
+            fn from(value: $type) -> Self {
+                value as i32
+            }
+        }
+
+        impl EnumConfigurationValue for $type {
+            fn get_options(&self) -> (Vec<String>, Vec<i32>) {
+                (
+                    vec![$($name.to_owned()),+],
+                    vec![$($variant as i32),+],
+                )
+            }
+        }
+    };
+}
+
+enum_configuration!(PathSyntax {
+    PathSyntax::Auto => "auto",
+    PathSyntax::Cbor => "cbor",
+    PathSyntax::Text => "text",
+});
+
+enum_configuration!(Format {
+    Format::Cbor => "cbor",
+    Format::Json => "json",
+    Format::Diag => "diag",
+});
+
+enum_configuration!(ReplicationMode {
+    ReplicationMode::Verbatim => "verbatim",
+    ReplicationMode::Effects => "effects",
+});
+
+enum_configuration!(DigestMode {
+    DigestMode::Raw => "raw",
+    DigestMode::Semantic => "semantic",
+});
+
+#[cfg(test)]
+mod tests {
+    use super::PathSyntax;
+    use crate::{digest::DigestMode, replication::ReplicationMode, util::Format};
+    use redis_module::configuration::EnumConfigurationValue;
+
+    fn round_trip<T>(value: T) -> (T, String)
+    where
+        T: EnumConfigurationValue + Copy,
+    {
+        let (names, values) = value.get_options();
+        let index = values
+            .iter()
+            .position(|v| *v == value.into())
+            .expect("value not in the options");
+        (T::try_from(values[index]).unwrap(), names[index].clone())
+    }
+
+    #[test]
+    fn enum_values() {
+        assert_eq!(
+            (PathSyntax::Text, "text".to_owned()),
+            round_trip(PathSyntax::Text)
+        );
+        assert_eq!((Format::Json, "json".to_owned()), round_trip(Format::Json));
+        assert_eq!(
+            (ReplicationMode::Effects, "effects".to_owned()),
+            round_trip(ReplicationMode::Effects)
+        );
+        assert_eq!(
+            (DigestMode::Semantic, "semantic".to_owned()),
+            round_trip(DigestMode::Semantic)
+        );
+        assert!(Format::try_from(3).is_err());
+    }
+}
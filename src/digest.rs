@@ -0,0 +1,165 @@
+//! Digest of CBOR documents for `DEBUG DIGEST` and `DEBUG DIGEST-VALUE`.
+//!
+//! The mode is set by the `digest-mode` configuration.
+//! In [`DigestMode::Raw`] mode, the stored bytes are digested as is.
+//! In [`DigestMode::Semantic`] mode, documents are first converted to the
+//! [core deterministic encoding](https://datatracker.ietf.org/doc/html/rfc8949#section-4.2.1),
+//! so that documents which only differ by their encoding (integer, length or float widths,
+//! indefinite lengths, map key order) get the same digest.
+
+use crate::encoding::{write_float, write_header};
+use cbor_data::{Cbor, ItemKind};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DigestMode {
+    Raw = 0,
+    Semantic = 1,
+}
+
+static DIGEST_MODE: AtomicU8 = AtomicU8::new(DigestMode::Raw as u8);
+
+#[inline]
+pub fn digest_mode() -> DigestMode {
+    match DIGEST_MODE.load(Ordering::Relaxed) {
+        1 => DigestMode::Semantic,
+        _ => DigestMode::Raw,
+    }
+}
+
+#[inline]
+pub fn set_digest_mode(mode: DigestMode) {
+    DIGEST_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Core deterministic encoding of `cbor`
+pub fn canonical(cbor: &Cbor) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(cbor.as_slice().len());
+    write_canonical(cbor, &mut buf);
+    buf
+}
+
+fn write_canonical(cbor: &Cbor, buf: &mut Vec<u8>) {
+    for tag in cbor.tags() {
+        write_header(6, tag, buf);
+    }
+
+    match cbor.kind() {
+        ItemKind::Pos(v) => write_header(0, v, buf),
+        ItemKind::Neg(v) => write_header(1, v, buf),
+        ItemKind::Float(v) => write_float(v, buf),
+        ItemKind::Bytes(b) => {
+            let b = b.as_cow();
+            write_header(2, b.len() as u64, buf);
+            buf.extend_from_slice(&b);
+        }
+        ItemKind::Str(s) => {
+            let s = s.as_cow();
+            write_header(3, s.len() as u64, buf);
+            buf.extend_from_slice(s.as_bytes());
+        }
+        ItemKind::Bool(false) => buf.push(0xf4),
+        ItemKind::Bool(true) => buf.push(0xf5),
+        ItemKind::Null => buf.push(0xf6),
+        ItemKind::Undefined => buf.push(0xf7),
+        ItemKind::Simple(v) => write_header(7, v as u64, buf),
+        ItemKind::Array(array) => {
+            let items = array.collect::<Vec<_>>();
+            write_header(4, items.len() as u64, buf);
+            for item in items {
+                write_canonical(item, buf);
+            }
+        }
+        ItemKind::Dict(dict) => {
+            let mut entries = dict
+                .map(|(k, v)| (canonical(k), canonical(v)))
+                .collect::<Vec<_>>();
+            entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+            write_header(5, entries.len() as u64, buf);
+            for (k, v) in entries {
+                buf.extend_from_slice(&k);
+                buf.extend_from_slice(&v);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::canonical;
+    use crate::util::{bytes_to_diag, diag_to_bytes};
+    use cbor_data::Cbor;
+
+    fn canonical_diag(diag: &str) -> String {
+        let bytes = diag_to_bytes(diag);
+        bytes_to_diag(&canonical(Cbor::unchecked(&bytes)))
+    }
+
+    fn assert_same_canonical(diag1: &str, diag2: &str) {
+        let bytes1 = diag_to_bytes(diag1);
+        let bytes2 = diag_to_bytes(diag2);
+        assert_ne!(bytes1, bytes2);
+        assert_eq!(
+            canonical(Cbor::unchecked(&bytes1)),
+            canonical(Cbor::unchecked(&bytes2))
+        );
+    }
+
+    #[test]
+    fn integer_widths() {
+        assert_same_canonical("1", "1_3");
+        assert_same_canonical("-500", "-500_2");
+        assert_same_canonical("[1,2]", "[1_0,2_1]");
+        assert_eq!("1", canonical_diag("1_3"));
+    }
+
+    #[test]
+    fn float_widths() {
+        assert_same_canonical("1.5_1", "1.5_3");
+        assert_same_canonical("100000.0_2", "100000.0_3");
+        assert_eq!("1.5_1", canonical_diag("1.5_3"));
+        assert_eq!("100000.0_2", canonical_diag("100000.0_3"));
+        assert_eq!("3.14", canonical_diag("3.14"));
+    }
+
+    #[test]
+    fn indefinite_lengths() {
+        assert_same_canonical("[1,[2,3]]", "[_ 1,[_ 2,3]]");
+        assert_same_canonical(r#""foobar""#, r#"(_ "foo", "bar")"#);
+        assert_same_canonical("h'0001'", "(_ h'00', h'01')");
+    }
+
+    #[test]
+    fn map_key_order() {
+        assert_same_canonical(
+            r#"{"a":1,"b":{"c":2,"d":3}}"#,
+            r#"{"b":{"d":3,"c":2},"a":1}"#,
+        );
+        assert_eq!(
+            r#"{10:1,"a":2,[1]:3}"#,
+            canonical_diag(r#"{[1]:3,"a":2,10:1}"#)
+        );
+    }
+
+    #[test]
+    fn tags() {
+        assert_same_canonical("1(1_2)", "1(1)");
+        assert_eq!(r#"32("foo")"#, canonical_diag(r#"32((_ "f", "oo"))"#));
+    }
+
+    #[test]
+    fn semantic_differences() {
+        let distinct = ["1", "1.0", "-1", r#""a""#, "h'61'", "[1]", "{1:1}", "1(1)"];
+        for (i, diag1) in distinct.iter().enumerate() {
+            for diag2 in &distinct[i + 1..] {
+                let bytes1 = diag_to_bytes(diag1);
+                let bytes2 = diag_to_bytes(diag2);
+                assert_ne!(
+                    canonical(Cbor::unchecked(&bytes1)),
+                    canonical(Cbor::unchecked(&bytes2))
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Encoding of CBOR item headers and floats with the shortest widths preserving their values,
+//! i.e. the [preferred serialization](https://datatracker.ietf.org/doc/html/rfc8949#section-4.1).
+
+/// Write the header of an item of type `major` with the shortest encoding of `value`
+pub(crate) fn write_header(major: u8, value: u64, buf: &mut Vec<u8>) {
+    let major = major << 5;
+    match value {
+        0..=23 => buf.push(major | value as u8),
+        24..=0xff => buf.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xffff => {
+            buf.push(major | 25);
+            buf.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buf.push(major | 26);
+            buf.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            buf.push(major | 27);
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+/// Write the shortest float encoding preserving `value`
+pub(crate) fn write_float(value: f64, buf: &mut Vec<u8>) {
+    if value.is_nan() {
+        buf.extend_from_slice(&[0xf9, 0x7e, 0x00]);
+    } else if let Some(half) = f16_bits(value) {
+        buf.push(0xf9);
+        buf.extend_from_slice(&half.to_be_bytes());
+    } else if (value as f32) as f64 == value {
+        buf.push(0xfa);
+        buf.extend_from_slice(&(value as f32).to_bits().to_be_bytes());
+    } else {
+        buf.push(0xfb);
+        buf.extend_from_slice(&value.to_bits().to_be_bytes());
+    }
+}
+
+/// Half precision bits of `value` if it can be represented exactly
+pub fn f16_bits(value: f64) -> Option<u16> {
+    let single = value as f32;
+    if single as f64 != value {
+        return None;
+    }
+
+    let bits = single.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    match exponent {
+        // infinity, NaN
+        0xff => Some(sign | 0x7c00 | (mantissa >> 13) as u16),
+        // zero, single precision subnormals are too small for half precision
+        0 if mantissa == 0 => Some(sign),
+        0 => None,
+        _ => {
+            let exponent = exponent - 127;
+            if (-14..=15).contains(&exponent) {
+                // normal
+                (mantissa & 0x1fff == 0)
+                    .then(|| sign | (((exponent + 15) as u16) << 10) | (mantissa >> 13) as u16)
+            } else if (-24..-14).contains(&exponent) {
+                // subnormal
+                let mantissa = mantissa | 0x80_0000;
+                let shift = -(exponent + 1);
+                (mantissa & ((1 << shift) - 1) == 0).then(|| sign | (mantissa >> shift) as u16)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::f16_bits;
+
+    #[test]
+    fn half_precision() {
+        assert_eq!(Some(0x0000), f16_bits(0.0));
+        assert_eq!(Some(0x8000), f16_bits(-0.0));
+        assert_eq!(Some(0x3e00), f16_bits(1.5));
+        assert_eq!(Some(0x7bff), f16_bits(65504.0));
+        assert_eq!(Some(0x0400), f16_bits(0.00006103515625));
+        assert_eq!(Some(0x0001), f16_bits(5.960464477539063e-8));
+        assert_eq!(Some(0x7c00), f16_bits(f64::INFINITY));
+        assert_eq!(Some(0xfc00), f16_bits(f64::NEG_INFINITY));
+        assert_eq!(None, f16_bits(65536.0));
+        assert_eq!(None, f16_bits(3.14));
+        assert_eq!(None, f16_bits(1.0e-10));
+    }
+}
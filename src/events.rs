@@ -0,0 +1,76 @@
+//! Detailed change events.
+//!
+//! Keyspace notifications only carry the event name, e.g. `cbor.set`, and the key name.
+//! When the `detailed-events` setting is enabled, every change is also published on [`EVENTS_CHANNEL`]
+//! as a CBOR map with the event name, the key name and the normalized paths of the changed values,
+//! in the old document for removed and replaced values and in the new document for added values:
+//!
+//! ```text
+//! {"event": "cbor.set", "key": h'6b6579', "paths": [["$", "a", {"#": 0}]]}
+//! ```
+
+use crate::commands::changed_paths;
+use cbor_data::{Cbor, CborBuilder, CborOwned, Writer};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Pub/Sub channel of the detailed events
+pub const EVENTS_CHANNEL: &str = "__cbor__:events";
+
+static DETAILED_EVENTS: AtomicBool = AtomicBool::new(false);
+
+#[inline]
+pub fn detailed_events() -> bool {
+    DETAILED_EVENTS.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub fn set_detailed_events(enabled: bool) {
+    DETAILED_EVENTS.store(enabled, Ordering::Relaxed);
+}
+
+/// The detailed event of `event` changing the value of `key_name` from `old` to `new`.
+///
+/// `old` is `None` for a new key and `new` is `None` for a deleted key:
+/// the root path is then the only changed path.
+pub fn detailed_event(
+    event: &str,
+    key_name: &[u8],
+    old: Option<&Cbor>,
+    new: Option<&Cbor>,
+) -> CborOwned {
+    let paths = match (old, new) {
+        (Some(old), Some(new)) => changed_paths(old, new),
+        _ => CborBuilder::new().write_array(None, |builder| {
+            builder.write_array(None, |builder| {
+                builder.write_str("$", None);
+            });
+        }),
+    };
+
+    CborBuilder::new().write_dict(None, |builder| {
+        builder.with_key("event", |b| b.write_str(event, None));
+        builder.with_key("key", |b| b.write_bytes(key_name, None));
+        builder.with_key("paths", |b| b.write_item(&paths));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::detailed_event;
+    use crate::util::{cbor_to_diag, diag_to_cbor};
+
+    #[test]
+    fn event() {
+        let old = diag_to_cbor(r#"{"a":[1,2],"b":true}"#);
+        let new = diag_to_cbor(r#"{"a":[1,3],"c":null}"#);
+
+        assert_eq!(
+            r##"{"event":"cbor.set","key":h'6b6579',"paths":[["$","a",{"#":1}],["$","b"],["$","c"]]}"##,
+            cbor_to_diag(&detailed_event("cbor.set", b"key", Some(&old), Some(&new)))
+        );
+        assert_eq!(
+            r#"{"event":"cbor.set","key":h'6b6579',"paths":[["$"]]}"#,
+            cbor_to_diag(&detailed_event("cbor.set", b"key", None, Some(&new)))
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Conversions between JSON text and CBOR.
+//!
+//! JSON to CBOR:
+//! - `null`, booleans, strings, arrays and objects map to their CBOR counterparts, object key order is preserved
+//! - integers map to unsigned or negative integers, in the smallest width
+//! - other numbers map to floats, in the shortest width preserving their value
+//!
+//! CBOR to JSON, following [RFC 8949 §6.1](https://datatracker.ietf.org/doc/html/rfc8949#section-6.1):
+//! - integers and finite floats map to JSON numbers,
+//!   negative integers below `-2^63` are converted to floats
+//! - NaN and infinite floats, `undefined` and simple values map to `null`
+//! - byte strings map to base64url strings without padding,
+//!   or to base64 strings with padding when tagged 22, or to base16 strings when tagged 23
+//! - other tags are dropped, only their tagged item is converted
+//! - text map keys are kept as is, byte string keys are converted as byte strings,
+//!   other keys are replaced by their JSON text (e.g. `1` becomes `"1"`);
+//!   keys converted to the same string collide, the last value replacing the previous ones
+//!   at the position of the first key (e.g. `{1:"a","1":"b"}` becomes `{"1":"b"}`)
+
+use crate::encoding::{write_float, write_header};
+use cbor_data::{Cbor, CborOwned, ItemKind};
+use serde_json::{Map, Value};
+
+const TAG_EXPECTED_BASE64: u64 = 22;
+const TAG_EXPECTED_BASE16: u64 = 23;
+
+/// Parse a JSON text into a CBOR document
+pub fn json_to_cbor(json: &str) -> Result<CborOwned, serde_json::Error> {
+    let value = serde_json::from_str::<Value>(json)?;
+    let mut buf = Vec::with_capacity(json.len());
+    write_value(&value, &mut buf);
+    Ok(CborOwned::unchecked(buf))
+}
+
+fn write_value(value: &Value, buf: &mut Vec<u8>) {
+    match value {
+        Value::Null => buf.push(0xf6),
+        Value::Bool(false) => buf.push(0xf4),
+        Value::Bool(true) => buf.push(0xf5),
+        Value::Number(n) => {
+            if let Some(n) = n.as_u64() {
+                write_header(0, n, buf);
+            } else if let Some(n) = n.as_i64() {
+                write_header(1, (-1 - n) as u64, buf);
+            } else {
+                write_float(n.as_f64().unwrap_or(f64::NAN), buf);
+            }
+        }
+        Value::String(s) => {
+            write_header(3, s.len() as u64, buf);
+            buf.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(items) => {
+            write_header(4, items.len() as u64, buf);
+            for item in items {
+                write_value(item, buf);
+            }
+        }
+        Value::Object(entries) => {
+            write_header(5, entries.len() as u64, buf);
+            for (k, v) in entries {
+                write_header(3, k.len() as u64, buf);
+                buf.extend_from_slice(k.as_bytes());
+                write_value(v, buf);
+            }
+        }
+    }
+}
+
+/// Convert a CBOR document into a JSON text
+pub fn cbor_to_json(cbor: &Cbor) -> String {
+    cbor_to_value(cbor).to_string()
+}
+
+fn cbor_to_value(cbor: &Cbor) -> Value {
+    match cbor.kind() {
+        ItemKind::Pos(v) => Value::from(v),
+        ItemKind::Neg(v) => match i64::try_from(v) {
+            Ok(v) => Value::from(-1 - v),
+            Err(_) => Value::from(-1.0 - v as f64),
+        },
+        ItemKind::Float(v) => Value::from(v),
+        ItemKind::Str(s) => Value::String(s.as_cow().into_owned()),
+        ItemKind::Bytes(b) => {
+            let b = b.as_cow();
+            Value::String(match cbor.tags().last() {
+                Some(TAG_EXPECTED_BASE64) => base64(&b, BASE64_ALPHABET, true),
+                Some(TAG_EXPECTED_BASE16) => base16(&b),
+                _ => base64(&b, BASE64URL_ALPHABET, false),
+            })
+        }
+        ItemKind::Bool(v) => Value::Bool(v),
+        ItemKind::Null | ItemKind::Undefined | ItemKind::Simple(_) => Value::Null,
+        ItemKind::Array(array) => Value::Array(array.map(cbor_to_value).collect()),
+        ItemKind::Dict(dict) => Value::Object(
+            dict.map(|(k, v)| {
+                let k = match cbor_to_value(k) {
+                    Value::String(k) => k,
+                    k => k.to_string(),
+                };
+                (k, cbor_to_value(v))
+            })
+            .collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64URL_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn base64(bytes: &[u8], alphabet: &[u8; 64], padding: bool) -> String {
+    let mut result = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        let num_chars = chunk.len() + 1;
+        for i in 0..4 {
+            if i < num_chars {
+                result.push(alphabet[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else if padding {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+fn base16(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, cbor_to_json, json_to_cbor, BASE64URL_ALPHABET, BASE64_ALPHABET};
+    use crate::util::{cbor_to_diag, diag_to_cbor};
+
+    #[test]
+    fn from_json() {
+        let cbor = json_to_cbor(
+            r#"{"b": [1, -2, 1.5, 100000.5, "foo"], "a": {"t": true, "f": false, "n": null}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            r#"{"b":[1,-2,1.5_1,100000.5_2,"foo"],"a":{"t":true,"f":false,"n":null}}"#,
+            cbor_to_diag(&cbor)
+        );
+
+        let cbor = json_to_cbor("[18446744073709551615, -9223372036854775808]").unwrap();
+        assert_eq!(
+            "[18446744073709551615,-9223372036854775808]",
+            cbor_to_diag(&cbor)
+        );
+
+        assert!(json_to_cbor("{").is_err());
+        assert!(json_to_cbor("[1,]").is_err());
+        assert!(json_to_cbor("1 2").is_err());
+    }
+
+    #[test]
+    fn to_json() {
+        let cbor = diag_to_cbor(
+            r#"{"b":[1,-2,1.5,"foo"],"a":{"t":true,"f":false,"n":null,"u":undefined,"s":simple(32)}}"#,
+        );
+        assert_eq!(
+            r#"{"b":[1,-2,1.5,"foo"],"a":{"t":true,"f":false,"n":null,"u":null,"s":null}}"#,
+            cbor_to_json(&cbor)
+        );
+
+        let cbor = diag_to_cbor("[NaN,Infinity,-18446744073709551616]");
+        assert_eq!("[null,null,-1.8446744073709552e19]", cbor_to_json(&cbor));
+    }
+
+    #[test]
+    fn bytes_to_json() {
+        let cbor = diag_to_cbor("[h'fbff01',22(h'fbff01'),23(h'fbff01'),h'',h'00']");
+        assert_eq!(r#"["-_8B","+/8B","fbff01","","AA"]"#, cbor_to_json(&cbor));
+    }
+
+    #[test]
+    fn tags_to_json() {
+        let cbor =
+            diag_to_cbor(r#"[1(1363896240),0("2013-03-21T20:04:00Z"),2(h'010000000000000000')]"#);
+        assert_eq!(
+            r#"[1363896240,"2013-03-21T20:04:00Z","AQAAAAAAAAAA"]"#,
+            cbor_to_json(&cbor)
+        );
+    }
+
+    #[test]
+    fn map_keys_to_json() {
+        let cbor = diag_to_cbor(r#"{1:"a",-1:"b",h'00':"c",true:"d",null:"e",[1,2]:"f","g":"g"}"#);
+        assert_eq!(
+            r#"{"1":"a","-1":"b","AA":"c","true":"d","null":"e","[1,2]":"f","g":"g"}"#,
+            cbor_to_json(&cbor)
+        );
+    }
+
+    #[test]
+    fn colliding_map_keys_to_json() {
+        let cbor = diag_to_cbor(r#"{1:"a","1":"b"}"#);
+        assert_eq!(r#"{"1":"b"}"#, cbor_to_json(&cbor));
+
+        let cbor = diag_to_cbor(r#"{"AA":"a","b":"b",h'00':"c"}"#);
+        assert_eq!(r#"{"AA":"c","b":"b"}"#, cbor_to_json(&cbor));
+    }
+
+    #[test]
+    fn base64_encodings() {
+        assert_eq!("", base64(b"", BASE64_ALPHABET, true));
+        assert_eq!("Zg==", base64(b"f", BASE64_ALPHABET, true));
+        assert_eq!("Zm8=", base64(b"fo", BASE64_ALPHABET, true));
+        assert_eq!("Zm9v", base64(b"foo", BASE64_ALPHABET, true));
+        assert_eq!("Zm9vYmFy", base64(b"foobar", BASE64_ALPHABET, true));
+        assert_eq!("Zg", base64(b"f", BASE64URL_ALPHABET, false));
+        assert_eq!("Zm8", base64(b"fo", BASE64URL_ALPHABET, false));
+    }
+}
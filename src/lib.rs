@@ -1,7 +1,28 @@
-use crate::redis_cbor_type::REDIS_CBOR_TYPE;
+use crate::{
+    config::{
+        DetailedEvents, DigestModeConfig, MaxDepth, MaxDocumentSize, OutputFormat, PathSyntax,
+        PathSyntaxConfig, Replication, ReplicationThreshold,
+    },
+    digest::DigestMode,
+    redis_cbor_type::REDIS_CBOR_TYPE,
+    replication::{ReplicationMode, DEFAULT_FULL_DOCUMENT_THRESHOLD},
+    stats::instrumented,
+    util::{Format, MAX_DEPTH},
+};
+use redis_module::configuration::ConfigurationFlags;
 
 mod redis_cbor_type;
+mod cdc;
 mod commands;
+mod config;
+mod digest;
+mod encoding;
+mod events;
+mod json;
+mod path_syntax;
+mod rdb;
+mod replication;
+mod stats;
 mod util;
 
 pub const MODULE_NAME: &str = "ReCBOR";
@@ -13,28 +34,51 @@ redis_module! {
     name: MODULE_NAME,
     version: 1,
     data_types: [REDIS_CBOR_TYPE],
+    info: stats::add_info,
     commands: [
-        ["cbor.arrappend", commands::cbor_arr_append, "write deny-oom", 1, 1, 1],
-        ["cbor.arrindex", commands::cbor_arr_index, "readonly", 1, 1, 1],
-        ["cbor.arrinsert", commands::cbor_arr_insert, "write deny-oom", 1, 1, 1],
-        ["cbor.arrlen", commands::cbor_arr_len, "readonly", 1, 1, 1],
-        ["cbor.arrpop", commands::cbor_arr_pop, "write deny-oom", 1, 1, 1],
-        ["cbor.arrtrim", commands::cbor_arr_trim, "write deny-oom", 1, 1, 1],
-        ["cbor.clear", commands::cbor_clear, "write deny-oom", 1, 1, 1],
-        ["cbor.debug", commands::cbor_debug, "readonly", 2, 2, 1],
-        ["cbor.del", commands::cbor_del, "write deny-oom", 1, 1, 1],
-        ["cbor.get", commands::cbor_get, "readonly", 1, 1, 1],
-        ["cbor.mapappend", commands::cbor_map_append, "write deny-oom", 1, 1, 1],
-        ["cbor.mapkeys", commands::cbor_mapkeys, "readonly", 1, 1, 1],
-        ["cbor.maplen", commands::cbor_map_len, "readonly", 1, 1, 1],
-        ["cbor.mget", commands::cbor_mget, "readonly", 1,1,1],
-        ["cbor.numincrby", commands::cbor_num_incr_by, "write deny-oom", 1,1,1],
-        ["cbor.nummultby", commands::cbor_num_mult_by, "write deny-oom", 1,1,1],
-        ["cbor.resp", commands::cbor_resp, "readonly", 1,1,1],
-        ["cbor.set", commands::cbor_set, "write deny-oom", 1, 1, 1],
-        ["cbor.strappend", commands::cbor_str_append, "write deny-oom", 1, 1, 1],
-        ["cbor.strlen", commands::cbor_str_len, "readonly", 1,1,1],
-        ["cbor.toggle", commands::cbor_toggle, "write deny-oom", 1, 1, 1],
-        ["cbor.type", commands::cbor_type, "readonly", 1,1,1],
+        ["cbor.arrappend", instrumented!("cbor.arrappend", commands::cbor_arr_append), "write deny-oom", 1, 1, 1],
+        ["cbor.arrindex", instrumented!("cbor.arrindex", commands::cbor_arr_index), "readonly", 1, 1, 1],
+        ["cbor.arrinsert", instrumented!("cbor.arrinsert", commands::cbor_arr_insert), "write deny-oom", 1, 1, 1],
+        ["cbor.arrlen", instrumented!("cbor.arrlen", commands::cbor_arr_len), "readonly", 1, 1, 1],
+        ["cbor.arrpop", instrumented!("cbor.arrpop", commands::cbor_arr_pop), "write deny-oom", 1, 1, 1],
+        ["cbor.arrtrim", instrumented!("cbor.arrtrim", commands::cbor_arr_trim), "write deny-oom", 1, 1, 1],
+        ["cbor.cdc", instrumented!("cbor.cdc", commands::cbor_cdc), "write admin", 0, 0, 0],
+        ["cbor.clear", instrumented!("cbor.clear", commands::cbor_clear), "write deny-oom", 1, 1, 1],
+        ["cbor.debug", instrumented!("cbor.debug", commands::cbor_debug), "readonly", 2, 2, 1],
+        ["cbor.del", instrumented!("cbor.del", commands::cbor_del), "write deny-oom", 1, 1, 1],
+        ["cbor.diff", instrumented!("cbor.diff", commands::cbor_diff), "readonly", 1, 1, 1],
+        ["cbor.get", instrumented!("cbor.get", commands::cbor_get), "readonly", 1, 1, 1],
+        ["cbor.mapappend", instrumented!("cbor.mapappend", commands::cbor_map_append), "write deny-oom", 1, 1, 1],
+        ["cbor.mapkeys", instrumented!("cbor.mapkeys", commands::cbor_mapkeys), "readonly", 1, 1, 1],
+        ["cbor.maplen", instrumented!("cbor.maplen", commands::cbor_map_len), "readonly", 1, 1, 1],
+        ["cbor.merge", instrumented!("cbor.merge", commands::cbor_merge), "write deny-oom", 1, 1, 1],
+        ["cbor.mget", instrumented!("cbor.mget", commands::cbor_mget), "readonly", 1,1,1],
+        ["cbor.numincrby", instrumented!("cbor.numincrby", commands::cbor_num_incr_by), "write deny-oom", 1,1,1],
+        ["cbor.nummultby", instrumented!("cbor.nummultby", commands::cbor_num_mult_by), "write deny-oom", 1,1,1],
+        ["cbor.numop", instrumented!("cbor.numop", commands::cbor_num_op), "write deny-oom", 1, 1, 1],
+        ["cbor.patch", instrumented!("cbor.patch", commands::cbor_patch), "write deny-oom", 1, 1, 1],
+        ["cbor.resp", instrumented!("cbor.resp", commands::cbor_resp), "readonly", 1,1,1],
+        ["cbor.set", instrumented!("cbor.set", commands::cbor_set), "write deny-oom", 1, 1, 1],
+        ["cbor.strappend", instrumented!("cbor.strappend", commands::cbor_str_append), "write deny-oom", 1, 1, 1],
+        ["cbor.strlen", instrumented!("cbor.strlen", commands::cbor_str_len), "readonly", 1,1,1],
+        ["cbor.toggle", instrumented!("cbor.toggle", commands::cbor_toggle), "write deny-oom", 1, 1, 1],
+        ["cbor.type", instrumented!("cbor.type", commands::cbor_type), "readonly", 1,1,1],
     ],
+    configurations: [
+        i64: [
+            ["max-document-size", &MaxDocumentSize, 0, 0, i64::MAX, ConfigurationFlags::MEMORY, None],
+            ["max-depth", &MaxDepth, MAX_DEPTH as i64, 1, MAX_DEPTH as i64, ConfigurationFlags::DEFAULT, None],
+            ["replication-threshold", &ReplicationThreshold, DEFAULT_FULL_DOCUMENT_THRESHOLD as i64, 0, i64::MAX, ConfigurationFlags::MEMORY, None],
+        ],
+        bool: [
+            ["detailed-events", &DetailedEvents, false, ConfigurationFlags::DEFAULT, None],
+        ],
+        enum: [
+            ["path-syntax", &PathSyntaxConfig, PathSyntax::Auto, ConfigurationFlags::DEFAULT, None],
+            ["output-format", &OutputFormat, Format::Cbor, ConfigurationFlags::DEFAULT, None],
+            ["replication", &Replication, ReplicationMode::Verbatim, ConfigurationFlags::DEFAULT, None],
+            ["digest-mode", &DigestModeConfig, DigestMode::Raw, ConfigurationFlags::DEFAULT, None],
+        ],
+        module_args_as_configuration: true,
+    ]
 }
@@ -0,0 +1,810 @@
+//! Textual CBORPath syntax.
+//!
+//! A JSONPath-like syntax that is parsed into the CBOR encoding of a CBORPath,
+//! each construct mapping to exactly one CBOR construct.
+//! The grammar and its mapping are documented in [path.md](../docs/docs/path.md).
+
+use crate::encoding::{write_float, write_header};
+use std::fmt;
+
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+/// Maximum nesting of filters, parenthesized expressions and function calls
+const MAX_NESTING: usize = 64;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+/// Textual paths start with the root identifier `$`,
+/// whereas CBOR encoded paths start with an array header
+#[inline]
+pub fn is_text_path(bytes: &[u8]) -> bool {
+    bytes.first() == Some(&b'$')
+}
+
+/// Parse a textual path into the CBOR encoding of the same CBORPath
+pub fn parse(text: &str) -> Result<Vec<u8>, ParseError> {
+    let mut parser = Parser {
+        input: text,
+        pos: 0,
+        depth: 0,
+    };
+    let path = parser.path()?;
+    parser.skip_whitespaces();
+    if parser.pos < text.len() {
+        return Err(parser.error("unexpected character"));
+    }
+    Ok(path)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Comparable {
+    Literal,
+    Query,
+    Function,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    #[inline]
+    fn error(&self, message: &'static str) -> ParseError {
+        ParseError {
+            position: self.pos,
+            message,
+        }
+    }
+
+    #[inline]
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    #[inline]
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    fn expect(&mut self, token: &str, message: &'static str) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    /// Parse a nested expression, bounding the recursion of the parser
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_NESTING {
+            return Err(self.error("too many nested expressions"));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn skip_whitespaces(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    /// path = "$" *segment
+    fn path(&mut self) -> Result<Vec<u8>, ParseError> {
+        self.expect("$", "expected root identifier `$`")?;
+        self.query(text("$"))
+    }
+
+    /// Segments following a root or current node identifier
+    fn query(&mut self, identifier: Vec<u8>) -> Result<Vec<u8>, ParseError> {
+        let mut items = vec![identifier];
+        loop {
+            let start = self.pos;
+            self.skip_whitespaces();
+            match self.segment()? {
+                Some(segment) => items.push(segment),
+                None => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        Ok(array(items))
+    }
+
+    fn segment(&mut self) -> Result<Option<Vec<u8>>, ParseError> {
+        if self.eat("..") {
+            let selection = if self.peek() == Some('[') {
+                self.bracketed_selection()?
+            } else if self.eat("*") {
+                wildcard()
+            } else {
+                self.member_name()?
+            };
+            Ok(Some(map("..", selection)))
+        } else if self.eat(".") {
+            if self.eat("*") {
+                Ok(Some(wildcard()))
+            } else {
+                Ok(Some(self.member_name()?))
+            }
+        } else if self.peek() == Some('[') {
+            Ok(Some(self.bracketed_selection()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// "[" selector *("," selector) "]"
+    fn bracketed_selection(&mut self) -> Result<Vec<u8>, ParseError> {
+        self.expect("[", "expected `[`")?;
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespaces();
+            selectors.push(self.selector()?);
+            self.skip_whitespaces();
+            if self.eat("]") {
+                break;
+            }
+            self.expect(",", "expected `,` or `]`")?;
+        }
+
+        if selectors.len() == 1 {
+            Ok(selectors.pop().unwrap())
+        } else {
+            Ok(array(selectors))
+        }
+    }
+
+    fn selector(&mut self) -> Result<Vec<u8>, ParseError> {
+        match self.peek() {
+            Some('"') | Some('\'') => Ok(text(&self.string()?)),
+            Some('*') => {
+                self.pos += 1;
+                Ok(wildcard())
+            }
+            Some('?') => {
+                self.pos += 1;
+                self.skip_whitespaces();
+                Ok(map("?", self.nested(Self::logical_or)?))
+            }
+            Some(c) if c == '-' || c == ':' || c.is_ascii_digit() => self.index_or_slice(),
+            _ if self.eat("key(") => {
+                self.skip_whitespaces();
+                let key = self.literal()?;
+                self.skip_whitespaces();
+                self.expect(")", "expected `)`")?;
+                Ok(key)
+            }
+            _ => Err(self.error("invalid selector")),
+        }
+    }
+
+    /// index = int
+    ///
+    /// slice = [start] ":" [end] [":" [step]]
+    fn index_or_slice(&mut self) -> Result<Vec<u8>, ParseError> {
+        let start = self.optional_int()?;
+        self.skip_whitespaces();
+        if !self.eat(":") {
+            return match start {
+                Some(index) => Ok(map("#", int(index))),
+                None => Err(self.error("expected index")),
+            };
+        }
+
+        self.skip_whitespaces();
+        let end = self.optional_int()?;
+        self.skip_whitespaces();
+        let step = if self.eat(":") {
+            self.skip_whitespaces();
+            self.optional_int()?
+        } else {
+            None
+        };
+
+        let step = step.unwrap_or(1);
+        let (default_start, default_end) = if step >= 0 {
+            (0, i64::MAX)
+        } else {
+            (i64::MAX, i64::MIN)
+        };
+
+        Ok(map(
+            ":",
+            array(vec![
+                int(start.unwrap_or(default_start)),
+                int(end.unwrap_or(default_end)),
+                int(step),
+            ]),
+        ))
+    }
+
+    fn optional_int(&mut self) -> Result<Option<i64>, ParseError> {
+        let rest = self.rest();
+        let sign = usize::from(rest.starts_with('-'));
+        let digits = rest[sign..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len() - sign);
+        if digits == 0 {
+            return if sign == 1 {
+                Err(self.error("expected integer"))
+            } else {
+                Ok(None)
+            };
+        }
+
+        let value = rest[..sign + digits]
+            .parse::<i64>()
+            .map_err(|_| self.error("integer out of range"))?;
+        self.pos += sign + digits;
+        Ok(Some(value))
+    }
+
+    /// name-first *name-char
+    fn member_name(&mut self) -> Result<Vec<u8>, ParseError> {
+        let rest = self.rest();
+        let mut chars = rest.char_indices();
+        match chars.next() {
+            Some((_, c)) if c == '_' || c.is_ascii_alphabetic() || !c.is_ascii() => (),
+            _ => return Err(self.error("expected member name")),
+        }
+
+        let len = chars
+            .find(|(_, c)| !(*c == '_' || c.is_ascii_alphanumeric() || !c.is_ascii()))
+            .map_or(rest.len(), |(i, _)| i);
+        self.pos += len;
+        Ok(text(&rest[..len]))
+    }
+
+    /// logical-or = logical-and *("||" logical-and)
+    fn logical_or(&mut self) -> Result<Vec<u8>, ParseError> {
+        let mut operands = vec![self.logical_and()?];
+        loop {
+            self.skip_whitespaces();
+            if !self.eat("||") {
+                break;
+            }
+            self.skip_whitespaces();
+            operands.push(self.logical_and()?);
+        }
+
+        if operands.len() == 1 {
+            Ok(operands.pop().unwrap())
+        } else {
+            Ok(map("||", array(operands)))
+        }
+    }
+
+    /// logical-and = basic *("&&" basic)
+    fn logical_and(&mut self) -> Result<Vec<u8>, ParseError> {
+        let mut operands = vec![self.basic()?];
+        loop {
+            self.skip_whitespaces();
+            if !self.eat("&&") {
+                break;
+            }
+            self.skip_whitespaces();
+            operands.push(self.basic()?);
+        }
+
+        if operands.len() == 1 {
+            Ok(operands.pop().unwrap())
+        } else {
+            Ok(map("&&", array(operands)))
+        }
+    }
+
+    /// basic = ["!"] "(" logical-or ")" / ["!"] (filter-query / function) / comparison
+    fn basic(&mut self) -> Result<Vec<u8>, ParseError> {
+        if self.rest().starts_with('!') && !self.rest().starts_with("!=") {
+            self.pos += 1;
+            self.skip_whitespaces();
+            let operand = if self.peek() == Some('(') {
+                self.parenthesized()?
+            } else {
+                match self.comparable()? {
+                    (_, Comparable::Literal) => return Err(self.error("expected test expression")),
+                    (operand, _) => operand,
+                }
+            };
+            return Ok(map("!", operand));
+        }
+
+        if self.peek() == Some('(') {
+            return self.parenthesized();
+        }
+
+        let (left, kind) = self.comparable()?;
+        let start = self.pos;
+        self.skip_whitespaces();
+        match self.comparison_operator() {
+            Some(operator) => {
+                self.skip_whitespaces();
+                let (right, _) = self.comparable()?;
+                Ok(map(operator, array(vec![left, right])))
+            }
+            None if kind == Comparable::Literal => Err(self.error("expected comparison operator")),
+            None => {
+                self.pos = start;
+                Ok(left)
+            }
+        }
+    }
+
+    fn parenthesized(&mut self) -> Result<Vec<u8>, ParseError> {
+        self.expect("(", "expected `(`")?;
+        self.skip_whitespaces();
+        let expression = self.nested(Self::logical_or)?;
+        self.skip_whitespaces();
+        self.expect(")", "expected `)`")?;
+        Ok(expression)
+    }
+
+    fn comparison_operator(&mut self) -> Option<&'static str> {
+        ["==", "!=", "<=", ">=", "<", ">"]
+            .into_iter()
+            .find(|operator| self.eat(operator))
+    }
+
+    /// comparable = literal / filter-query / function
+    fn comparable(&mut self) -> Result<(Vec<u8>, Comparable), ParseError> {
+        if self.eat("@") {
+            return Ok((self.query(text("@"))?, Comparable::Query));
+        }
+        if self.eat("$") {
+            return Ok((self.query(text("$"))?, Comparable::Query));
+        }
+
+        let rest = self.rest();
+        let name_len = rest
+            .find(|c: char| !(c == '_' || c.is_ascii_lowercase() || c.is_ascii_digit()))
+            .unwrap_or(rest.len());
+        if name_len > 0 && rest[name_len..].starts_with('(') {
+            let name = &rest[..name_len];
+            self.pos += name_len;
+            return Ok((self.function(name)?, Comparable::Function));
+        }
+
+        Ok((self.literal()?, Comparable::Literal))
+    }
+
+    /// function = name "(" arg *("," arg) ")"
+    fn function(&mut self, name: &str) -> Result<Vec<u8>, ParseError> {
+        self.expect("(", "expected `(`")?;
+        let mut args = Vec::new();
+        loop {
+            self.skip_whitespaces();
+            let (arg, _) = self.nested(Self::comparable)?;
+            args.push(arg);
+            self.skip_whitespaces();
+            if self.eat(")") {
+                break;
+            }
+            self.expect(",", "expected `,` or `)`")?;
+        }
+
+        if args.len() == 1 {
+            Ok(map(name, args.pop().unwrap()))
+        } else {
+            Ok(map(name, array(args)))
+        }
+    }
+
+    /// literal = number / string / bytes / "true" / "false" / "null" / "undefined"
+    fn literal(&mut self) -> Result<Vec<u8>, ParseError> {
+        match self.peek() {
+            Some('"') | Some('\'') => Ok(text(&self.string()?)),
+            Some('h') if self.rest().starts_with("h'") => self.bytes(),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => {
+                for (keyword, byte) in [
+                    ("true", 0xf5),
+                    ("false", 0xf4),
+                    ("null", 0xf6),
+                    ("undefined", 0xf7),
+                ] {
+                    let rest = self.rest();
+                    if rest.starts_with(keyword)
+                        && !rest[keyword.len()..]
+                            .starts_with(|c: char| c == '_' || c.is_ascii_alphanumeric())
+                    {
+                        self.pos += keyword.len();
+                        return Ok(vec![byte]);
+                    }
+                }
+                Err(self.error("expected literal"))
+            }
+        }
+    }
+
+    /// number = ["-"] digits ["." digits] [("e" / "E") ["+" / "-"] digits]
+    fn number(&mut self) -> Result<Vec<u8>, ParseError> {
+        let rest = self.rest();
+        let bytes = rest.as_bytes();
+        let mut len = usize::from(bytes[0] == b'-');
+        let digits = |from: usize| {
+            bytes[from..]
+                .iter()
+                .position(|b| !b.is_ascii_digit())
+                .unwrap_or(bytes.len() - from)
+        };
+
+        let integer_digits = digits(len);
+        if integer_digits == 0 {
+            return Err(self.error("expected number"));
+        }
+        len += integer_digits;
+
+        let mut is_float = false;
+        if bytes.get(len) == Some(&b'.') {
+            let fraction_digits = digits(len + 1);
+            if fraction_digits == 0 {
+                return Err(self.error("expected fraction digits"));
+            }
+            len += 1 + fraction_digits;
+            is_float = true;
+        }
+        if matches!(bytes.get(len), Some(b'e') | Some(b'E')) {
+            let sign = usize::from(matches!(bytes.get(len + 1), Some(b'+') | Some(b'-')));
+            let exponent_digits = digits(len + 1 + sign);
+            if exponent_digits == 0 {
+                return Err(self.error("expected exponent digits"));
+            }
+            len += 1 + sign + exponent_digits;
+            is_float = true;
+        }
+
+        let number = &rest[..len];
+        let mut buf = Vec::new();
+        if is_float {
+            let value = number
+                .parse::<f64>()
+                .map_err(|_| self.error("invalid number"))?;
+            write_float(value, &mut buf);
+        } else {
+            let value = number
+                .parse::<i128>()
+                .map_err(|_| self.error("integer out of range"))?;
+            if value >= 0 && value <= u64::MAX as i128 {
+                write_header(0, value as u64, &mut buf);
+            } else if value < 0 && -1 - value <= u64::MAX as i128 {
+                write_header(1, (-1 - value) as u64, &mut buf);
+            } else {
+                return Err(self.error("integer out of range"));
+            }
+        }
+
+        self.pos += len;
+        Ok(buf)
+    }
+
+    /// bytes = "h'" *(HEXDIG HEXDIG) "'"
+    fn bytes(&mut self) -> Result<Vec<u8>, ParseError> {
+        self.expect("h'", "expected `h'`")?;
+        let rest = self.rest();
+        let Some(len) = rest.find('\'') else {
+            return Err(self.error("unterminated byte string"));
+        };
+
+        let hex = rest[..len].as_bytes();
+        if hex.len() % 2 != 0 {
+            return Err(self.error("odd number of hexadecimal digits"));
+        }
+        let bytes = hex
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| self.error("invalid hexadecimal digit"))?;
+
+        self.pos += len + 1;
+        let mut buf = Vec::with_capacity(bytes.len() + 9);
+        write_header(MAJOR_BYTES, bytes.len() as u64, &mut buf);
+        buf.extend_from_slice(&bytes);
+        Ok(buf)
+    }
+
+    /// string = DQUOTE *double-quoted DQUOTE / SQUOTE *single-quoted SQUOTE
+    fn string(&mut self) -> Result<String, ParseError> {
+        let quote = self.peek().ok_or_else(|| self.error("expected string"))?;
+        self.pos += 1;
+
+        let mut result = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                c if c == quote => return Ok(result),
+                '\\' => result.push(self.escaped()?),
+                c => result.push(c),
+            }
+        }
+    }
+
+    fn escaped(&mut self) -> Result<char, ParseError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("unterminated string"))?;
+        self.pos += c.len_utf8();
+        match c {
+            '"' | '\'' | '\\' | '/' => Ok(c),
+            'b' => Ok('\u{8}'),
+            'f' => Ok('\u{c}'),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            'u' => {
+                let high = self.hex4()?;
+                if (0xd800..0xdc00).contains(&high) {
+                    self.expect("\\u", "expected low surrogate")?;
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error("invalid low surrogate"));
+                    }
+                    let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                    char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+                } else {
+                    char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"))
+                }
+            }
+            _ => Err(self.error("invalid escape sequence")),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let hex = self
+            .rest()
+            .get(..4)
+            .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("expected 4 hexadecimal digits"))?;
+        let value = u32::from_str_radix(hex, 16).unwrap();
+        self.pos += 4;
+        Ok(value)
+    }
+}
+
+fn text(s: &str) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(s.len() + 9);
+    write_header(MAJOR_TEXT, s.len() as u64, &mut buf);
+    buf.extend_from_slice(s.as_bytes());
+    buf
+}
+
+fn int(value: i64) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(9);
+    if value >= 0 {
+        write_header(0, value as u64, &mut buf);
+    } else {
+        write_header(1, (-1 - value) as u64, &mut buf);
+    }
+    buf
+}
+
+fn array(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_header(MAJOR_ARRAY, items.len() as u64, &mut buf);
+    for item in items {
+        buf.extend_from_slice(&item);
+    }
+    buf
+}
+
+/// Single entry map `{key: value}`
+fn map(key: &str, value: Vec<u8>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_header(MAJOR_MAP, 1, &mut buf);
+    buf.extend_from_slice(&text(key));
+    buf.extend_from_slice(&value);
+    buf
+}
+
+#[inline]
+fn wildcard() -> Vec<u8> {
+    map("*", int(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_text_path, parse, ParseError};
+    use crate::util::{bytes_to_diag, diag_to_bytes, diag_to_cbor};
+    use cbor_data::Cbor;
+    use cborpath::CborPath;
+
+    fn assert_path(expected_diag: &str, text: &str) {
+        let bytes = parse(text).unwrap();
+        assert_eq!(
+            diag_to_bytes(expected_diag),
+            bytes,
+            "{text} => {}",
+            bytes_to_diag(&bytes)
+        );
+    }
+
+    fn assert_error(position: usize, text: &str) {
+        assert!(
+            matches!(parse(text), Err(ParseError { position: p, .. }) if p == position),
+            "{text} => {:?}",
+            parse(text)
+        );
+    }
+
+    #[test]
+    fn detection() {
+        assert!(is_text_path(b"$.a"));
+        assert!(is_text_path(b"$"));
+        assert!(!is_text_path(b"\x81\x61$"));
+        assert!(!is_text_path(b""));
+    }
+
+    #[test]
+    fn root() {
+        assert_path(r#"["$"]"#, "$");
+        assert_path(r#"["$"]"#, "$  ");
+    }
+
+    #[test]
+    fn keys() {
+        assert_path(r#"["$","a"]"#, "$.a");
+        assert_path(r#"["$","store","book"]"#, "$.store.book");
+        assert_path(r#"["$","a b"]"#, r#"$["a b"]"#);
+        assert_path(r#"["$","it's"]"#, r#"$['it\'s']"#);
+        assert_path(r#"["$","é_1"]"#, "$.é_1");
+        assert_path(r#"["$","\né😀"]"#, r#"$["\né😀"]"#);
+        assert_path(r#"["$",1]"#, "$[key(1)]");
+        assert_path(r#"["$",-1]"#, "$[key(-1)]");
+        assert_path(r#"["$",h'00ff']"#, "$[key(h'00ff')]");
+        assert_path(r#"["$",true]"#, "$[key(true)]");
+        assert_path(r#"["$",1.5_1]"#, "$[key(1.5)]");
+    }
+
+    #[test]
+    fn indexes() {
+        assert_path(r##"["$",{"#":0}]"##, "$[0]");
+        assert_path(r##"["$",{"#":-1}]"##, "$[-1]");
+        assert_path(
+            r##"["$","store","book",{"#":0},"title"]"##,
+            "$.store.book[0].title",
+        );
+    }
+
+    #[test]
+    fn wildcards() {
+        assert_path(r#"["$",{"*":1}]"#, "$.*");
+        assert_path(r#"["$",{"*":1}]"#, "$[*]");
+        assert_path(r#"["$",{"..":{"*":1}}]"#, "$..*");
+    }
+
+    #[test]
+    fn slices() {
+        assert_path(r#"["$",{":":[1,3,1]}]"#, "$[1:3]");
+        assert_path(r#"["$",{":":[0,5,2]}]"#, "$[:5:2]");
+        assert_path(r#"["$",{":":[1,9223372036854775807,1]}]"#, "$[1:]");
+        assert_path(
+            r#"["$",{":":[9223372036854775807,-9223372036854775808,-1]}]"#,
+            "$[::-1]",
+        );
+    }
+
+    #[test]
+    fn descendants() {
+        assert_path(r#"["$",{"..":"a"}]"#, "$..a");
+        assert_path(r##"["$",{"..":{"#":0}}]"##, "$..[0]");
+        assert_path(r#"["$",{"..":["a","b"]}]"#, r#"$..["a", "b"]"#);
+    }
+
+    #[test]
+    fn multiple_selectors() {
+        assert_path(r##"["$",["a",{"#":1},{"*":1}]]"##, "$['a', 1, *]");
+    }
+
+    #[test]
+    fn filters() {
+        assert_path(r#"["$",{"?":["@","isbn"]}]"#, "$[?@.isbn]");
+        assert_path(r#"["$",{"?":{"!":["@","isbn"]}}]"#, "$[?!@.isbn]");
+        assert_path(
+            r#"["$",{"?":{"<":[["@","price"],10]}}]"#,
+            "$[?@.price < 10]",
+        );
+        assert_path(
+            r#"["$",{"?":{"!=":[["@","a"],["$","b"]]}}]"#,
+            "$[?@.a!=$.b]",
+        );
+        assert_path(
+            r#"["$",{"?":{"||":[{"&&":[{">=":[["@","a"],1]},{"==":[["@","b"],"x"]}]},{"!":{"==":[["@","c"],null]}}]}}]"#,
+            r#"$[?@.a >= 1 && @.b == "x" || !(@.c == null)]"#,
+        );
+        assert_path(
+            r#"["$",{"?":{">":[{"length":["@","authors"]},2]}}]"#,
+            "$[?length(@.authors) > 2]",
+        );
+        assert_path(
+            r#"["$",{"?":{"match":[["@","date"],"1974-05-.."]}}]"#,
+            r#"$[?match(@.date, "1974-05-..")]"#,
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_error(0, "");
+        assert_error(0, "a");
+        assert_error(2, "$.");
+        assert_error(2, "$.1");
+        assert_error(2, "$[a]");
+        assert_error(5, "$['a'");
+        assert_error(4, "$[1 2]");
+        assert_error(2, "$ a");
+        assert_error(8, "$[key(h'0g')]");
+        assert_error(4, "$[?1]");
+        assert_error(6, r#"$["a\x"]"#);
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = |depth: usize, open: &str, close: &str| {
+            format!("$[?{}@.a{}]", open.repeat(depth), close.repeat(depth))
+        };
+
+        // the filter is the first nesting level
+        assert!(parse(&nested(63, "(", ")")).is_ok());
+        assert!(parse(&nested(63, "length(", ")")).is_ok());
+        assert_error(3 + 64, &nested(64, "(", ")"));
+        assert_error(3 + 64 * 7, &nested(64, "length(", ")"));
+        assert_error(3 + 64 * 3, &nested(64, "@[?", "]"));
+
+        // deeper than the stack would allow without the limit
+        let nested = nested(100_000, "(", ")");
+        assert!(parse(&nested).is_err());
+    }
+
+    #[test]
+    fn read() {
+        let cbor = diag_to_cbor(r#"{"store":{"book":[{"title":"a"},{"title":"b"}]}}"#);
+
+        let bytes = parse("$.store.book[1].title").unwrap();
+        let cbor_path = CborPath::from_bytes(&bytes).unwrap();
+        let results = cbor_path.read(&cbor);
+        assert_eq!(
+            vec![Cbor::checked(&diag_to_bytes(r#""b""#)).unwrap()],
+            results
+        );
+
+        let bytes = parse("$..title").unwrap();
+        let cbor_path = CborPath::from_bytes(&bytes).unwrap();
+        assert_eq!(2, cbor_path.read(&cbor).len());
+    }
+}
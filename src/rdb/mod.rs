@@ -0,0 +1,378 @@
+//! RDB persistence of CBOR documents.
+//!
+//! Every layout change bumps [`RDB_ENCODING_VERSION`] and gets its own loader module,
+//! so that dumps written by any previous version of the module can still be loaded.
+//! Only the latest layout is ever saved.
+//!
+//! | encver | layout                                                      |
+//! |--------|-------------------------------------------------------------|
+//! | 1      | document                                                    |
+//! | 2      | flags, checksum, [metadata], document (see [`v2`])          |
+//!
+//! The [CDC rules](crate::cdc) are saved as auxiliary data before the keys, with the layout
+//! `number of rules, (prefix, stream)*` since version 2. Redis 7.2+ omits them when there are none.
+
+mod v1;
+mod v2;
+
+use crate::{cdc::CdcRule, util::ValidationError};
+use cbor_data::CborOwned;
+use redis_module::{raw, RedisBuffer};
+
+pub const RDB_ENCODING_V1: i32 = 1;
+pub const RDB_ENCODING_V2: i32 = 2;
+/// Encoding version of the layout written by [`save`]
+pub const RDB_ENCODING_VERSION: i32 = RDB_ENCODING_V2;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RdbError {
+    UnknownVersion(i32),
+    UnknownFlags(u64),
+    Io,
+    ChecksumMismatch,
+    InvalidMetadata(ValidationError),
+    InvalidDocument(ValidationError),
+}
+
+impl std::fmt::Display for RdbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RdbError::UnknownVersion(encver) => write!(f, "unknown encoding version {encver}"),
+            RdbError::UnknownFlags(flags) => write!(f, "unknown header flags {flags:#x}"),
+            RdbError::Io => write!(f, "failed to read payload"),
+            RdbError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            RdbError::InvalidMetadata(e) => write!(f, "invalid metadata: {}", e.message()),
+            RdbError::InvalidDocument(e) => write!(f, "invalid document: {}", e.message()),
+        }
+    }
+}
+
+/// Source of the values of a payload, so that the loaders do not depend on a live `RedisModuleIO`
+pub trait RdbInput {
+    type Buffer: AsRef<[u8]>;
+
+    fn load_unsigned(&mut self) -> Result<u64, RdbError>;
+    fn load_buffer(&mut self) -> Result<Self::Buffer, RdbError>;
+}
+
+impl RdbInput for *mut raw::RedisModuleIO {
+    type Buffer = RedisBuffer;
+
+    fn load_unsigned(&mut self) -> Result<u64, RdbError> {
+        raw::load_unsigned(*self).map_err(|_| RdbError::Io)
+    }
+
+    fn load_buffer(&mut self) -> Result<RedisBuffer, RdbError> {
+        raw::load_string_buffer(*self).map_err(|_| RdbError::Io)
+    }
+}
+
+/// Load a CBOR document with the loader matching `encver`
+pub fn load(rdb: &mut impl RdbInput, encver: i32) -> Result<CborOwned, RdbError> {
+    match encver {
+        RDB_ENCODING_V1 => v1::load(rdb),
+        RDB_ENCODING_V2 => v2::load(rdb),
+        _ => Err(RdbError::UnknownVersion(encver)),
+    }
+}
+
+/// Save a CBOR document with the [`RDB_ENCODING_VERSION`] layout
+pub fn save(rdb: *mut raw::RedisModuleIO, cbor: &CborOwned) {
+    v2::save(rdb, cbor)
+}
+
+/// Save the CDC rules as auxiliary data
+pub fn save_cdc_rules(rdb: *mut raw::RedisModuleIO, rules: &[CdcRule]) {
+    raw::save_unsigned(rdb, rules.len() as u64);
+    for rule in rules {
+        save_buffer(rdb, &rule.prefix);
+        save_buffer(rdb, &rule.stream);
+    }
+}
+
+/// Load the CDC rules saved by [`save_cdc_rules`], whose layout is the same since version 2
+pub fn load_cdc_rules(rdb: &mut impl RdbInput, encver: i32) -> Result<Vec<CdcRule>, RdbError> {
+    if !(RDB_ENCODING_V2..=RDB_ENCODING_VERSION).contains(&encver) {
+        return Err(RdbError::UnknownVersion(encver));
+    }
+
+    let len = rdb.load_unsigned()?;
+    (0..len)
+        .map(|_| {
+            Ok(CdcRule {
+                prefix: rdb.load_buffer()?.as_ref().to_vec(),
+                stream: rdb.load_buffer()?.as_ref().to_vec(),
+            })
+        })
+        .collect()
+}
+
+fn save_buffer(rdb: *mut raw::RedisModuleIO, bytes: &[u8]) {
+    unsafe {
+        raw::RedisModule_SaveStringBuffer.unwrap()(
+            rdb,
+            bytes.as_ptr().cast::<std::ffi::c_char>(),
+            bytes.len(),
+        );
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 (ISO-HDLC) of the concatenation of `parts`
+fn crc32<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> u32 {
+    let mut crc = !0u32;
+    for part in parts {
+        for byte in part {
+            crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        crc32, load, load_cdc_rules, RdbError, RdbInput, RDB_ENCODING_V1, RDB_ENCODING_V2,
+        RDB_ENCODING_VERSION,
+    };
+    use crate::{
+        cdc::CdcRule,
+        util::{cbor_to_diag, diag_to_bytes, ValidationError, MAX_DEPTH},
+    };
+
+    macro_rules! fixture {
+        ($name:literal) => {
+            include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/rdb/v1/",
+                $name
+            ))
+        };
+    }
+
+    enum Value {
+        Unsigned(u64),
+        Buffer(Vec<u8>),
+    }
+
+    /// Values of a payload, in the order they were saved
+    struct Payload(std::vec::IntoIter<Value>);
+
+    impl Payload {
+        fn new(values: Vec<Value>) -> Self {
+            Self(values.into_iter())
+        }
+
+        fn v1(document: &[u8]) -> Self {
+            Self::new(vec![Value::Buffer(document.to_vec())])
+        }
+
+        fn v2(flags: u64, checksum: u32, buffers: &[&[u8]]) -> Self {
+            let mut values = vec![Value::Unsigned(flags), Value::Unsigned(checksum as u64)];
+            values.extend(buffers.iter().map(|b| Value::Buffer(b.to_vec())));
+            Self::new(values)
+        }
+    }
+
+    impl RdbInput for Payload {
+        type Buffer = Vec<u8>;
+
+        fn load_unsigned(&mut self) -> Result<u64, RdbError> {
+            match self.0.next() {
+                Some(Value::Unsigned(value)) => Ok(value),
+                _ => Err(RdbError::Io),
+            }
+        }
+
+        fn load_buffer(&mut self) -> Result<Vec<u8>, RdbError> {
+            match self.0.next() {
+                Some(Value::Buffer(buffer)) => Ok(buffer),
+                _ => Err(RdbError::Io),
+            }
+        }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(0xcbf4_3926, crc32([b"123456789".as_slice()]));
+        assert_eq!(
+            0xcbf4_3926,
+            crc32([b"1234".as_slice(), b"56789".as_slice()])
+        );
+        assert_eq!(0, crc32(std::iter::empty()));
+    }
+
+    #[test]
+    fn load_v1() {
+        let cbor = load(&mut Payload::v1(fixture!("scalar.bin")), RDB_ENCODING_V1).unwrap();
+        assert_eq!("42", cbor_to_diag(&cbor));
+
+        let cbor = load(&mut Payload::v1(fixture!("map.bin")), RDB_ENCODING_V1).unwrap();
+        assert_eq!(
+            r#"{"obj":{"a":1,"b":2},"arr":[1,2,3],"str":"foo","bool":true,"int":42,"float":3.14}"#,
+            cbor_to_diag(&cbor)
+        );
+
+        let cbor = load(&mut Payload::v1(fixture!("binary.bin")), RDB_ENCODING_V1).unwrap();
+        assert_eq!(r#"{"bytes":h'00ff0a0d',"neg":-500}"#, cbor_to_diag(&cbor));
+    }
+
+    #[test]
+    fn load_v1_malformed() {
+        let bytes = fixture!("map.bin");
+        let mut garbage = bytes.to_vec();
+        garbage.push(0x01);
+
+        for document in [&bytes[..bytes.len() - 1], &garbage, &[]] {
+            assert_eq!(
+                Some(RdbError::InvalidDocument(ValidationError::Malformed)),
+                load(&mut Payload::v1(document), RDB_ENCODING_V1).err()
+            );
+        }
+    }
+
+    #[test]
+    fn load_v1_too_deep() {
+        let deepest = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        let mut payload = Payload::v1(&diag_to_bytes(&deepest));
+        assert!(load(&mut payload, RDB_ENCODING_V1).is_ok());
+
+        let too_deep = format!(
+            "{}1{}",
+            r#"{"a":"#.repeat(MAX_DEPTH + 1),
+            "}".repeat(MAX_DEPTH + 1)
+        );
+        assert_eq!(
+            Some(RdbError::InvalidDocument(ValidationError::TooDeep)),
+            load(&mut Payload::v1(&diag_to_bytes(&too_deep)), RDB_ENCODING_V1).err()
+        );
+    }
+
+    #[test]
+    fn load_v2() {
+        let document = diag_to_bytes(r#"{"a":[1,2,3],"b":h'00ff'}"#);
+        let mut payload = Payload::v2(0, crc32([document.as_slice()]), &[&document]);
+        let cbor = load(&mut payload, RDB_ENCODING_V2).unwrap();
+        assert_eq!(r#"{"a":[1,2,3],"b":h'00ff'}"#, cbor_to_diag(&cbor));
+
+        let metadata = diag_to_bytes(r#"{"created":1}"#);
+        let checksum = crc32([metadata.as_slice(), document.as_slice()]);
+        let mut payload = Payload::v2(1, checksum, &[&metadata, &document]);
+        let cbor = load(&mut payload, RDB_ENCODING_V2).unwrap();
+        assert_eq!(r#"{"a":[1,2,3],"b":h'00ff'}"#, cbor_to_diag(&cbor));
+    }
+
+    #[test]
+    fn load_v2_checksum_mismatch() {
+        let document = diag_to_bytes(r#"{"a":[1,2,3]}"#);
+        let mut corrupted = document.clone();
+        *corrupted.last_mut().unwrap() = 0x04;
+
+        let mut payload = Payload::v2(0, crc32([document.as_slice()]), &[&corrupted]);
+        assert_eq!(
+            Some(RdbError::ChecksumMismatch),
+            load(&mut payload, RDB_ENCODING_V2).err()
+        );
+    }
+
+    #[test]
+    fn load_wrong_version() {
+        // a v2 payload starts with its flags, not with the document buffer
+        let document = diag_to_bytes("12");
+        let mut payload = Payload::v2(0, crc32([document.as_slice()]), &[&document]);
+        assert_eq!(
+            Some(RdbError::Io),
+            load(&mut payload, RDB_ENCODING_V1).err()
+        );
+
+        let mut payload = Payload::v1(&document);
+        assert_eq!(
+            Some(RdbError::Io),
+            load(&mut payload, RDB_ENCODING_V2).err()
+        );
+    }
+
+    #[test]
+    fn load_unknown_version() {
+        for encver in [0, 3] {
+            let mut payload = Payload::v1(&diag_to_bytes("12"));
+            assert_eq!(
+                Some(RdbError::UnknownVersion(encver)),
+                load(&mut payload, encver).err()
+            );
+        }
+    }
+
+    #[test]
+    fn cdc_rules() {
+        let values = vec![
+            Value::Unsigned(2),
+            Value::Buffer(b"{orders}:".to_vec()),
+            Value::Buffer(b"{orders}:cdc".to_vec()),
+            Value::Buffer(b"".to_vec()),
+            Value::Buffer(b"all".to_vec()),
+        ];
+        assert_eq!(
+            vec![
+                CdcRule {
+                    prefix: b"{orders}:".to_vec(),
+                    stream: b"{orders}:cdc".to_vec(),
+                },
+                CdcRule {
+                    prefix: Vec::new(),
+                    stream: b"all".to_vec(),
+                },
+            ],
+            load_cdc_rules(&mut Payload::new(values), RDB_ENCODING_V2).unwrap()
+        );
+
+        let mut payload = Payload::new(vec![Value::Unsigned(0)]);
+        assert_eq!(
+            Ok(Vec::new()),
+            load_cdc_rules(&mut payload, RDB_ENCODING_V2)
+        );
+
+        // a rule without its stream
+        let values = vec![Value::Unsigned(1), Value::Buffer(b"{orders}:".to_vec())];
+        assert_eq!(
+            Some(RdbError::Io),
+            load_cdc_rules(&mut Payload::new(values), RDB_ENCODING_V2).err()
+        );
+
+        let mut payload = Payload::new(vec![Value::Unsigned(0)]);
+        assert_eq!(
+            Ok(Vec::new()),
+            load_cdc_rules(&mut payload, RDB_ENCODING_VERSION)
+        );
+
+        // versions without CDC rules, or from a newer version of the module
+        for encver in [RDB_ENCODING_V1, RDB_ENCODING_VERSION + 1] {
+            let mut payload = Payload::new(vec![Value::Unsigned(0)]);
+            assert_eq!(
+                Some(RdbError::UnknownVersion(encver)),
+                load_cdc_rules(&mut payload, encver).err()
+            );
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! Layout 1: the document as a single string buffer, stored without any header.
+
+use super::{RdbError, RdbInput};
+use crate::util::{validate, MAX_DEPTH};
+use cbor_data::CborOwned;
+
+pub fn load(rdb: &mut impl RdbInput) -> Result<CborOwned, RdbError> {
+    let buffer = rdb.load_buffer()?;
+    decode(buffer.as_ref())
+}
+
+fn decode(bytes: &[u8]) -> Result<CborOwned, RdbError> {
+    validate(bytes, MAX_DEPTH)
+        .map(|cbor| cbor.to_owned())
+        .map_err(RdbError::InvalidDocument)
+}
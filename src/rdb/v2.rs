@@ -0,0 +1,179 @@
+//! Layout 2, a header followed by the document:
+//! - `flags`: unsigned, a combination of the `FLAG_*` constants
+//! - `checksum`: unsigned, CRC-32 of `metadata` (if any) followed by `document`
+//! - `metadata`: string buffer, only present with [`FLAG_METADATA`]
+//! - `document`: string buffer
+//!
+//! Metadata is a CBOR map reserved for future use: it is validated on load, then ignored.
+
+use super::{crc32, save_buffer, RdbError, RdbInput};
+use crate::util::{validate, ValidationError, MAX_DEPTH};
+use cbor_data::{Cbor, CborOwned, ItemKind};
+use redis_module::raw;
+
+/// A metadata string buffer is stored before the document
+pub const FLAG_METADATA: u64 = 0x1;
+const KNOWN_FLAGS: u64 = FLAG_METADATA;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Payload<'a> {
+    flags: u64,
+    checksum: u64,
+    metadata: Option<&'a [u8]>,
+    document: &'a [u8],
+}
+
+pub fn load(rdb: &mut impl RdbInput) -> Result<CborOwned, RdbError> {
+    let flags = rdb.load_unsigned()?;
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(RdbError::UnknownFlags(flags));
+    }
+
+    let checksum = rdb.load_unsigned()?;
+    let metadata = if flags & FLAG_METADATA != 0 {
+        Some(rdb.load_buffer()?)
+    } else {
+        None
+    };
+    let document = rdb.load_buffer()?;
+
+    decode(Payload {
+        flags,
+        checksum,
+        metadata: metadata.as_ref().map(|m| m.as_ref()),
+        document: document.as_ref(),
+    })
+}
+
+pub fn save(rdb: *mut raw::RedisModuleIO, cbor: &Cbor) {
+    let payload = encode(cbor, None);
+
+    raw::save_unsigned(rdb, payload.flags);
+    raw::save_unsigned(rdb, payload.checksum);
+    if let Some(metadata) = payload.metadata {
+        save_buffer(rdb, metadata);
+    }
+    save_buffer(rdb, payload.document);
+}
+
+fn encode<'a>(document: &'a Cbor, metadata: Option<&'a Cbor>) -> Payload<'a> {
+    let metadata = metadata.map(|m| m.as_slice());
+    let document = document.as_slice();
+
+    Payload {
+        flags: if metadata.is_some() { FLAG_METADATA } else { 0 },
+        checksum: crc32(metadata.into_iter().chain([document])) as u64,
+        metadata,
+        document,
+    }
+}
+
+fn decode(payload: Payload) -> Result<CborOwned, RdbError> {
+    if payload.flags & !KNOWN_FLAGS != 0 {
+        return Err(RdbError::UnknownFlags(payload.flags));
+    }
+
+    let checksum = crc32(payload.metadata.into_iter().chain([payload.document])) as u64;
+    if checksum != payload.checksum {
+        return Err(RdbError::ChecksumMismatch);
+    }
+
+    if let Some(metadata) = payload.metadata {
+        let metadata = validate(metadata, MAX_DEPTH).map_err(RdbError::InvalidMetadata)?;
+        if !matches!(metadata.kind(), ItemKind::Dict(_)) {
+            return Err(RdbError::InvalidMetadata(ValidationError::Malformed));
+        }
+    }
+
+    validate(payload.document, MAX_DEPTH)
+        .map(|cbor| cbor.to_owned())
+        .map_err(RdbError::InvalidDocument)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, decode, encode, Payload, FLAG_METADATA};
+    use crate::{
+        rdb::RdbError,
+        util::{cbor_to_diag, diag_to_bytes, diag_to_cbor, ValidationError},
+    };
+
+    #[test]
+    fn round_trip() {
+        let cbor = diag_to_cbor(r#"{"a":[1,2,3],"b":h'00ff'}"#);
+
+        let payload = encode(&cbor, None);
+        assert_eq!(0, payload.flags);
+        assert_eq!(None, payload.metadata);
+        assert_eq!(cbor.as_slice(), payload.document);
+
+        let loaded = decode(payload).unwrap();
+        assert_eq!(cbor.as_slice(), loaded.as_slice());
+    }
+
+    #[test]
+    fn round_trip_with_metadata() {
+        let cbor = diag_to_cbor(r#"{"a":[1,2,3],"b":h'00ff'}"#);
+        let metadata = diag_to_cbor(r#"{"created":1}"#);
+
+        let payload = encode(&cbor, Some(&metadata));
+        assert_eq!(FLAG_METADATA, payload.flags);
+        assert_eq!(Some(metadata.as_slice()), payload.metadata);
+
+        let loaded = decode(payload).unwrap();
+        assert_eq!(r#"{"a":[1,2,3],"b":h'00ff'}"#, cbor_to_diag(&loaded));
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let cbor = diag_to_cbor(r#"{"a":[1,2,3]}"#);
+        let mut corrupted = cbor.as_slice().to_vec();
+        *corrupted.last_mut().unwrap() = 0x04;
+
+        let payload = Payload {
+            document: &corrupted,
+            ..encode(&cbor, None)
+        };
+        assert_eq!(Some(RdbError::ChecksumMismatch), decode(payload).err());
+    }
+
+    #[test]
+    fn unknown_flags() {
+        let cbor = diag_to_cbor("12");
+
+        let payload = Payload {
+            flags: 0x8,
+            ..encode(&cbor, None)
+        };
+        assert_eq!(Some(RdbError::UnknownFlags(0x8)), decode(payload).err());
+    }
+
+    #[test]
+    fn invalid_document() {
+        let bytes = diag_to_bytes(r#"{"a":[1,2,3]}"#);
+        let truncated = &bytes[..bytes.len() - 1];
+
+        let payload = Payload {
+            flags: 0,
+            checksum: crc32([truncated]) as u64,
+            metadata: None,
+            document: truncated,
+        };
+        assert_eq!(
+            Some(RdbError::InvalidDocument(ValidationError::Malformed)),
+            decode(payload).err()
+        );
+    }
+
+    #[test]
+    fn invalid_metadata() {
+        let cbor = diag_to_cbor("12");
+        let metadata = diag_to_cbor("[1,2]");
+
+        let payload = encode(&cbor, Some(&metadata));
+        assert_eq!(
+            Some(RdbError::InvalidMetadata(ValidationError::Malformed)),
+            decode(payload).err()
+        );
+    }
+}
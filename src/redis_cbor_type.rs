@@ -1,15 +1,33 @@
-use crate::util::CborOwnedExt;
-use cbor_data::CborOwned;
+use crate::{
+    cdc,
+    digest::{canonical, digest_mode, DigestMode},
+    rdb, stats,
+    util::CborOwnedExt,
+};
+use cbor_data::{Cbor, CborOwned, ItemKind};
 use redis_module::{
     native_types::RedisType, raw, RedisModuleIO, RedisModuleString, RedisModuleTypeMethods,
 };
 use std::{
-    ffi::{c_int, c_void},
+    alloc::Layout,
+    ffi::{c_char, c_int, c_ulong, c_void, CString},
+    mem::ManuallyDrop,
     ptr::null_mut,
 };
 
 pub const MODULE_TYPE_NAME: &str = "ReCBORTyp"; // MUST be 9 characters long
-pub const REDIS_CBOR_TYPE_VERSION: i32 = 1;
+pub const REDIS_CBOR_TYPE_VERSION: i32 = rdb::RDB_ENCODING_VERSION;
+
+/// CBOR encoding of the root path `["$"]`
+const ROOT_PATH: &[u8] = b"\x81\x61$";
+/// Documents bigger than this are rewritten in several commands
+const AOF_REWRITE_CHUNK_SIZE: usize = 1024 * 1024;
+/// Maximum number of array items or map entries per rewritten command
+const AOF_REWRITE_ITEMS_PER_CMD: usize = 64;
+/// Bytes of document accounted as one unit of free effort.
+/// Redis frees values in a background thread above 64 units (`LAZYFREE_THRESHOLD`),
+/// i.e. documents bigger than 256KB.
+const FREE_EFFORT_UNIT: usize = 4096;
 
 pub static REDIS_CBOR_TYPE: RedisType = RedisType::new(
     MODULE_TYPE_NAME,
@@ -20,45 +38,253 @@ pub static REDIS_CBOR_TYPE: RedisType = RedisType::new(
         rdb_save: Some(rdb_save),
         aof_rewrite: Some(aof_rewrite),
         mem_usage: Some(mem_usage),
-        digest: None,
+        digest: Some(digest),
         free: Some(free),
-        aux_load: None,
-        aux_save: None,
-        aux_save_triggers: 0,
-        free_effort: None,
+        aux_load: Some(aux_load),
+        aux_save: Some(aux_save),
+        aux_save_triggers: raw::REDISMODULE_AUX_BEFORE_RDB as i32,
+        free_effort: Some(free_effort),
         unlink: None,
         copy: Some(copy),
-        defrag: None,
+        defrag: Some(defrag),
         copy2: None,
-        free_effort2: None,
+        free_effort2: Some(free_effort2),
         mem_usage2: None,
         unlink2: None,
+        aux_save2: Some(aux_save2),
     },
 );
 
-pub extern "C" fn rdb_load(rdb: *mut raw::RedisModuleIO, _encver: c_int) -> *mut c_void {
-    let Ok(buffer) = raw::load_string_buffer(rdb) else {
-        return null_mut();
-    };
-
-    let bytes = buffer.as_ref();
-    let cbor = CborOwned::unchecked(bytes);
-    Box::into_raw(Box::new(cbor)).cast::<libc::c_void>()
+pub extern "C" fn rdb_load(mut rdb: *mut raw::RedisModuleIO, encver: c_int) -> *mut c_void {
+    match rdb::load(&mut rdb, encver) {
+        Ok(cbor) => {
+            stats::value_created(&cbor);
+            Box::into_raw(Box::new(cbor)).cast::<libc::c_void>()
+        }
+        Err(e) => {
+            log_io_error(rdb, &e.to_string());
+            null_mut()
+        }
+    }
 }
 
 pub unsafe extern "C" fn rdb_save(rdb: *mut raw::RedisModuleIO, value: *mut c_void) {
     let cbor = unsafe { &*(value as *mut CborOwned) };
-    let bytes = cbor.as_slice();
-    let str = std::str::from_utf8_unchecked(bytes); // no save_string_buffer available in redis-module :(
-    raw::save_string(rdb, str);
+    rdb::save(rdb, cbor);
+}
+
+/// Save the CDC rules, so that they survive restarts and reach replicas with a full sync
+unsafe extern "C" fn aux_save(rdb: *mut raw::RedisModuleIO, _when: c_int) {
+    rdb::save_cdc_rules(rdb, &cdc::rules());
+}
+
+/// Like [`aux_save`], but without writing anything when there are no rules.
+///
+/// Redis 7.2+ calls it instead of [`aux_save`], and omits the aux field when nothing is written,
+/// whereas older versions always write the field before calling [`aux_save`].
+unsafe extern "C" fn aux_save2(rdb: *mut raw::RedisModuleIO, when: c_int) {
+    if !cdc::rules().is_empty() {
+        aux_save(rdb, when);
+    }
+}
+
+unsafe extern "C" fn aux_load(
+    mut rdb: *mut raw::RedisModuleIO,
+    encver: c_int,
+    _when: c_int,
+) -> c_int {
+    match rdb::load_cdc_rules(&mut rdb, encver) {
+        Ok(rules) => {
+            cdc::set_rules(rules);
+            raw::REDISMODULE_OK as c_int
+        }
+        Err(e) => {
+            log_io_error(rdb, &format!("CDC rules: {e}"));
+            raw::REDISMODULE_ERR as c_int
+        }
+    }
+}
+
+fn log_io_error(rdb: *mut raw::RedisModuleIO, message: &str) {
+    let Ok(message) = CString::new(format!("{MODULE_TYPE_NAME}: {message}")) else {
+        return;
+    };
+
+    unsafe {
+        raw::RedisModule_LogIOError.unwrap()(
+            rdb,
+            b"warning\0".as_ptr().cast::<c_char>(),
+            b"%s\0".as_ptr().cast::<c_char>(),
+            message.as_ptr(),
+        );
+    }
 }
 
 unsafe extern "C" fn aof_rewrite(
-    _aof: *mut RedisModuleIO,
-    _key: *mut RedisModuleString,
-    _value: *mut c_void,
+    aof: *mut RedisModuleIO,
+    key: *mut RedisModuleString,
+    value: *mut c_void,
 ) {
-    todo!();
+    let cbor = unsafe { &*(value as *mut CborOwned) };
+
+    for command in aof_commands(cbor, AOF_REWRITE_CHUNK_SIZE) {
+        match command {
+            AofCommand::Set(value) => emit_aof(aof, b"CBOR.SET\0", key, vec![ROOT_PATH, value]),
+            AofCommand::ArrAppend(values) => {
+                let mut args = Vec::with_capacity(values.len() + 1);
+                args.push(ROOT_PATH);
+                args.extend(values.into_iter().map(|v| v.as_slice()));
+                emit_aof(aof, b"CBOR.ARRAPPEND\0", key, args)
+            }
+            AofCommand::MapAppend(entries) => {
+                let mut args = Vec::with_capacity(2 * entries.len() + 1);
+                args.push(ROOT_PATH);
+                for (k, v) in entries {
+                    args.push(k.as_slice());
+                    args.push(v.as_slice());
+                }
+                emit_aof(aof, b"CBOR.MAPAPPEND\0", key, args)
+            }
+        }
+    }
+}
+
+/// Emit `command key arg [arg ...]` in the AOF, `command` being a nul-terminated string
+unsafe fn emit_aof(
+    aof: *mut RedisModuleIO,
+    command: &[u8],
+    key: *mut RedisModuleString,
+    args: Vec<&[u8]>,
+) {
+    let args = args
+        .into_iter()
+        .map(|arg| {
+            raw::RedisModule_CreateString.unwrap()(
+                null_mut(),
+                arg.as_ptr().cast::<c_char>(),
+                arg.len(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    raw::RedisModule_EmitAOF.unwrap()(
+        aof,
+        command.as_ptr().cast::<c_char>(),
+        b"sv\0".as_ptr().cast::<c_char>(),
+        key,
+        args.as_ptr(),
+        args.len(),
+    );
+
+    for arg in args {
+        raw::RedisModule_FreeString.unwrap()(null_mut(), arg);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum AofCommand<'a> {
+    /// CBOR.SET key $ value
+    Set(&'a [u8]),
+    /// CBOR.ARRAPPEND key $ value [value ...]
+    ArrAppend(Vec<&'a Cbor>),
+    /// CBOR.MAPAPPEND key $ map_key map_value [map_key map_value ...]
+    MapAppend(Vec<(&'a Cbor, &'a Cbor)>),
+}
+
+/// Commands rebuilding `cbor` byte-for-byte when replayed on an empty key.
+///
+/// Documents up to `chunk_size` bytes are emitted as a single `CBOR.SET`.
+/// Bigger root arrays and maps are created empty, then filled by chunks of
+/// at most `chunk_size` bytes or `AOF_REWRITE_ITEMS_PER_CMD` items.
+/// Containers that append commands would not re-encode identically
+/// (tagged, indefinite-length or non-minimal headers) are always emitted as a single command.
+fn aof_commands(cbor: &Cbor, chunk_size: usize) -> Vec<AofCommand<'_>> {
+    let bytes = cbor.as_slice();
+    if bytes.len() <= chunk_size {
+        return vec![AofCommand::Set(bytes)];
+    }
+
+    match cbor.kind() {
+        ItemKind::Array(array) if bytes[0] >> 5 == 4 => {
+            let items = array.collect::<Vec<_>>();
+            let items_len = items.iter().map(|i| i.as_slice().len()).sum::<usize>();
+            if bytes.len() - items_len != minimal_header_len(items.len()) {
+                return vec![AofCommand::Set(bytes)];
+            }
+
+            let mut commands = vec![AofCommand::Set(b"\x80")];
+            let mut chunk = Vec::new();
+            let mut chunk_len = 0;
+            for item in items {
+                chunk_len += item.as_slice().len();
+                chunk.push(item);
+                if chunk_len >= chunk_size || chunk.len() == AOF_REWRITE_ITEMS_PER_CMD {
+                    commands.push(AofCommand::ArrAppend(std::mem::take(&mut chunk)));
+                    chunk_len = 0;
+                }
+            }
+            if !chunk.is_empty() {
+                commands.push(AofCommand::ArrAppend(chunk));
+            }
+            commands
+        }
+        ItemKind::Dict(dict) if bytes[0] >> 5 == 5 => {
+            let entries = dict.collect::<Vec<_>>();
+            let entries_len = entries
+                .iter()
+                .map(|(k, v)| k.as_slice().len() + v.as_slice().len())
+                .sum::<usize>();
+            if bytes.len() - entries_len != minimal_header_len(entries.len()) {
+                return vec![AofCommand::Set(bytes)];
+            }
+
+            let mut commands = vec![AofCommand::Set(b"\xa0")];
+            let mut chunk = Vec::new();
+            let mut chunk_len = 0;
+            for (k, v) in entries {
+                chunk_len += k.as_slice().len() + v.as_slice().len();
+                chunk.push((k, v));
+                if chunk_len >= chunk_size || chunk.len() == AOF_REWRITE_ITEMS_PER_CMD {
+                    commands.push(AofCommand::MapAppend(std::mem::take(&mut chunk)));
+                    chunk_len = 0;
+                }
+            }
+            if !chunk.is_empty() {
+                commands.push(AofCommand::MapAppend(chunk));
+            }
+            commands
+        }
+        _ => vec![AofCommand::Set(bytes)],
+    }
+}
+
+/// Length of the shortest CBOR header encoding `len`
+#[inline]
+fn minimal_header_len(len: usize) -> usize {
+    match len {
+        0..=23 => 1,
+        24..=0xff => 2,
+        0x100..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+unsafe extern "C" fn digest(md: *mut raw::RedisModuleDigest, value: *mut c_void) {
+    let cbor = unsafe { &*(value as *mut CborOwned) };
+
+    let add_string_buffer = raw::RedisModule_DigestAddStringBuffer.unwrap();
+    match digest_mode() {
+        DigestMode::Raw => {
+            let bytes = cbor.as_slice();
+            add_string_buffer(md, bytes.as_ptr().cast_mut().cast(), bytes.len());
+        }
+        DigestMode::Semantic => {
+            let bytes = canonical(cbor);
+            add_string_buffer(md, bytes.as_ptr().cast_mut().cast(), bytes.len());
+        }
+    }
+    raw::RedisModule_DigestEndSequence.unwrap()(md);
 }
 
 unsafe extern "C" fn mem_usage(value: *const c_void) -> usize {
@@ -72,8 +298,104 @@ unsafe extern "C" fn free(value: *mut c_void) {
         return;
     }
 
-    let cbor = value.cast::<CborOwned>();
-    std::mem::drop(Box::from_raw(cbor));
+    let cbor = Box::from_raw(value.cast::<CborOwned>());
+    stats::value_freed(&cbor);
+    std::mem::drop(cbor);
+}
+
+unsafe extern "C" fn free_effort(_key: *mut RedisModuleString, value: *const c_void) -> usize {
+    let cbor = unsafe { &*(value as *const CborOwned) };
+    document_free_effort(cbor)
+}
+
+unsafe extern "C" fn free_effort2(
+    _ctx: *mut raw::RedisModuleKeyOptCtx,
+    value: *const c_void,
+) -> usize {
+    let cbor = unsafe { &*(value as *const CborOwned) };
+    document_free_effort(cbor)
+}
+
+#[inline]
+fn document_free_effort(cbor: &CborOwned) -> usize {
+    1 + cbor.mem_usage() / FREE_EFFORT_UNIT
+}
+
+/// Let the allocator move the document, then its buffer, out of fragmented pages.
+/// For large documents, Redis may ask to stop between the two steps and resume later.
+unsafe extern "C" fn defrag(
+    ctx: *mut raw::RedisModuleDefragCtx,
+    _key: *mut RedisModuleString,
+    value: *mut *mut c_void,
+) -> c_int {
+    let mut step = DEFRAG_DOCUMENT;
+    raw::RedisModule_DefragCursorGet.unwrap()(ctx, &mut step);
+
+    match defrag_document(&RedisDefragCtx(ctx), &mut *value, step) {
+        Some(next_step) => {
+            raw::RedisModule_DefragCursorSet.unwrap()(ctx, next_step);
+            1
+        }
+        None => 0,
+    }
+}
+
+const DEFRAG_DOCUMENT: c_ulong = 0;
+const DEFRAG_BUFFER: c_ulong = 1;
+
+/// Allocator operations of a defragmentation pass
+trait Defragmenter {
+    /// New location of the allocation `ptr` made with `layout`, if the allocator moved it,
+    /// in which case `ptr` was freed
+    unsafe fn defrag_alloc(&self, ptr: *mut c_void, layout: Layout) -> Option<*mut c_void>;
+    fn should_stop(&self) -> bool;
+}
+
+struct RedisDefragCtx(*mut raw::RedisModuleDefragCtx);
+
+impl Defragmenter for RedisDefragCtx {
+    unsafe fn defrag_alloc(&self, ptr: *mut c_void, _layout: Layout) -> Option<*mut c_void> {
+        let moved = raw::RedisModule_DefragAlloc.unwrap()(self.0, ptr);
+        (!moved.is_null()).then_some(moved)
+    }
+
+    fn should_stop(&self) -> bool {
+        unsafe { raw::RedisModule_DefragShouldStop.unwrap()(self.0) != 0 }
+    }
+}
+
+/// Defragment the boxed document `value` from `step`, returning the step to resume from if stopped
+unsafe fn defrag_document(
+    defragmenter: &impl Defragmenter,
+    value: &mut *mut c_void,
+    step: c_ulong,
+) -> Option<c_ulong> {
+    if step == DEFRAG_DOCUMENT {
+        if let Some(moved) = defragmenter.defrag_alloc(*value, Layout::new::<CborOwned>()) {
+            *value = moved;
+        }
+        if defragmenter.should_stop() {
+            return Some(DEFRAG_BUFFER);
+        }
+    }
+
+    let cbor = &mut *(*value).cast::<CborOwned>();
+    let len = cbor.as_slice().len();
+    // smaller documents are stored in place, and moved along with the box
+    if len > 16 {
+        // take the spilled buffer out of the document, without copying it, for its capacity
+        let buffer = ManuallyDrop::new(std::ptr::read(cbor).into_vec());
+        let (buffer, capacity) = (buffer.as_ptr().cast_mut(), buffer.capacity());
+        let buffer = defragmenter
+            .defrag_alloc(buffer.cast(), Layout::array::<u8>(capacity).unwrap())
+            .map_or(buffer, |moved| moved.cast());
+        // the document owns the moved buffer, the old one having been freed by the allocator
+        std::ptr::write(
+            cbor,
+            CborOwned::unchecked(Vec::from_raw_parts(buffer, len, capacity)),
+        );
+    }
+    None
 }
 
 unsafe extern "C" fn copy(
@@ -83,5 +405,223 @@ unsafe extern "C" fn copy(
 ) -> *mut c_void {
     let cbor = unsafe { &*(value as *mut CborOwned) };
     let cbor_cloned = cbor.clone();
+    stats::value_created(&cbor_cloned);
     Box::into_raw(Box::new(cbor_cloned)).cast::<c_void>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        aof_commands, defrag_document, document_free_effort, AofCommand, Defragmenter,
+        DEFRAG_BUFFER,
+    };
+    use crate::{
+        commands::{array_append, map_append, set, SetOptions, SetResult},
+        util::{diag_to_bytes, diag_to_cbor, CborOwnedExt},
+    };
+    use cbor_data::{Cbor, CborBuilder, CborOwned, Writer};
+    use cborpath::CborPath;
+    use std::{
+        alloc::{alloc, dealloc, Layout},
+        cell::RefCell,
+        ffi::c_void,
+    };
+
+    /// Replay the commands on an empty key as CBOR.SET/ARRAPPEND/MAPAPPEND would
+    fn replay(commands: Vec<AofCommand>) -> CborOwned {
+        let mut document: Option<CborOwned> = None;
+
+        for command in commands {
+            document = match command {
+                AofCommand::Set(value) => {
+                    match set(
+                        document.as_ref(),
+                        &CborPath::root(),
+                        Cbor::checked(value).unwrap(),
+                        SetOptions::None,
+                    ) {
+                        SetResult::Updated(new_value) => Some(new_value),
+                        result => panic!("unexpected set result {result:?}"),
+                    }
+                }
+                AofCommand::ArrAppend(values) => {
+                    array_append(document.as_ref().unwrap(), &CborPath::root(), values)
+                        .unwrap()
+                        .0
+                }
+                AofCommand::MapAppend(entries) => {
+                    map_append(document.as_ref().unwrap(), &CborPath::root(), entries)
+                        .unwrap()
+                        .0
+                }
+            };
+        }
+
+        document.unwrap()
+    }
+
+    #[test]
+    fn small_document() {
+        let cbor = diag_to_cbor(r#"{"a":[1,2,3],"b":"foo"}"#);
+
+        let commands = aof_commands(&cbor, 1024);
+        assert_eq!(vec![AofCommand::Set(cbor.as_slice())], commands);
+        assert_eq!(cbor.as_slice(), replay(commands).as_slice());
+    }
+
+    #[test]
+    fn large_array() {
+        let items = (0..200)
+            .map(|i| format!(r#""item{i}""#))
+            .collect::<Vec<_>>()
+            .join(",");
+        let cbor = diag_to_cbor(&format!("[{items}]"));
+
+        let commands = aof_commands(&cbor, 100);
+        assert!(commands.len() > 2);
+        assert_eq!(AofCommand::Set(b"\x80"), commands[0]);
+        assert!(commands[1..]
+            .iter()
+            .all(|c| matches!(c, AofCommand::ArrAppend(values) if !values.is_empty())));
+        assert_eq!(cbor.as_slice(), replay(commands).as_slice());
+    }
+
+    #[test]
+    fn large_map() {
+        let entries = (0..200)
+            .map(|i| format!(r#""key{i}":[{i},"value{i}"]"#))
+            .collect::<Vec<_>>()
+            .join(",");
+        let cbor = diag_to_cbor(&format!("{{{entries}}}"));
+
+        let commands = aof_commands(&cbor, 100);
+        assert!(commands.len() > 2);
+        assert_eq!(AofCommand::Set(b"\xa0"), commands[0]);
+        assert_eq!(cbor.as_slice(), replay(commands).as_slice());
+    }
+
+    #[test]
+    fn large_scalar() {
+        let cbor = diag_to_cbor(&format!(r#""{}""#, "a".repeat(200)));
+
+        let commands = aof_commands(&cbor, 100);
+        assert_eq!(vec![AofCommand::Set(cbor.as_slice())], commands);
+        assert_eq!(cbor.as_slice(), replay(commands).as_slice());
+    }
+
+    #[test]
+    fn indefinite_length_array() {
+        let items = (0..200)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let bytes = diag_to_bytes(&format!("[_ {items}]"));
+        let cbor = CborOwned::unchecked(bytes);
+
+        let commands = aof_commands(&cbor, 100);
+        assert_eq!(vec![AofCommand::Set(cbor.as_slice())], commands);
+        assert_eq!(cbor.as_slice(), replay(commands).as_slice());
+    }
+
+    #[test]
+    fn tagged_array() {
+        let items = (0..200)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let cbor = diag_to_cbor(&format!("1000([{items}])"));
+
+        let commands = aof_commands(&cbor, 100);
+        assert_eq!(vec![AofCommand::Set(cbor.as_slice())], commands);
+        assert_eq!(cbor.as_slice(), replay(commands).as_slice());
+    }
+
+    /// Moves every allocation, or none, freeing the moved allocations like Redis
+    struct TestDefragmenter {
+        move_allocations: bool,
+        stop: bool,
+        /// New locations of the moved allocations
+        moves: RefCell<Vec<*mut c_void>>,
+    }
+
+    impl Defragmenter for TestDefragmenter {
+        unsafe fn defrag_alloc(&self, ptr: *mut c_void, layout: Layout) -> Option<*mut c_void> {
+            if !self.move_allocations {
+                return None;
+            }
+            let moved = alloc(layout);
+            std::ptr::copy_nonoverlapping(ptr.cast::<u8>(), moved, layout.size());
+            dealloc(ptr.cast(), layout);
+            self.moves.borrow_mut().push(moved.cast());
+            Some(moved.cast())
+        }
+
+        fn should_stop(&self) -> bool {
+            self.stop
+        }
+    }
+
+    #[test]
+    fn defrag_document_contents() {
+        let items = (0..200)
+            .map(|i| format!(r#""item{i}""#))
+            .collect::<Vec<_>>()
+            .join(",");
+        let cbor = diag_to_cbor(&format!("[{items}]"));
+
+        for (move_allocations, stop) in [(false, false), (true, false), (true, true)] {
+            let defragmenter = TestDefragmenter {
+                move_allocations,
+                stop,
+                moves: RefCell::new(Vec::new()),
+            };
+            let mut value = Box::into_raw(Box::new(cbor.clone())).cast::<c_void>();
+
+            let mut step = 0;
+            while let Some(next_step) = unsafe { defrag_document(&defragmenter, &mut value, step) }
+            {
+                assert_eq!(DEFRAG_BUFFER, next_step);
+                step = next_step;
+            }
+
+            let defragged = unsafe { Box::from_raw(value.cast::<CborOwned>()) };
+            assert_eq!(cbor.as_slice(), defragged.as_slice());
+            // the stats account the same size when the document is freed
+            assert_eq!(cbor.mem_usage(), defragged.mem_usage());
+
+            let moves = defragmenter.moves.into_inner();
+            if move_allocations {
+                // the document lives where the allocator moved the box, then the buffer
+                assert_eq!(
+                    vec![value, defragged.as_slice().as_ptr().cast_mut().cast()],
+                    moves
+                );
+            } else {
+                assert!(moves.is_empty());
+            }
+            // dropping the document frees the moved allocations
+        }
+    }
+
+    #[test]
+    fn free_effort() {
+        // Redis frees values in a background thread above this effort
+        const LAZYFREE_THRESHOLD: usize = 64;
+
+        let cbor = diag_to_cbor("12");
+        assert_eq!(1, document_free_effort(&cbor));
+
+        let cbor = diag_to_cbor(r#"{"a":[1,2,3],"b":"foo"}"#);
+        assert_eq!(1, document_free_effort(&cbor));
+
+        let cbor = diag_to_cbor(&format!(r#""{}""#, "a".repeat(64 * 1024)));
+        let effort = document_free_effort(&cbor);
+        assert!(effort > 1 && effort <= LAZYFREE_THRESHOLD);
+
+        let cbor = diag_to_cbor(&format!(r#""{}""#, "a".repeat(1024 * 1024)));
+        assert!(document_free_effort(&cbor) > LAZYFREE_THRESHOLD);
+
+        let cbor = CborBuilder::new().write_bytes(&vec![0; 100 * 1024 * 1024], None);
+        assert!(document_free_effort(&cbor) > LAZYFREE_THRESHOLD);
+    }
+}
@@ -0,0 +1,137 @@
+//! Replication of write commands.
+//!
+//! In [`ReplicationMode::Verbatim`] mode, write commands are replicated as is,
+//! and replicas compute their results again.
+//! In [`ReplicationMode::Effects`] mode, the resulting documents are replicated instead,
+//! so that replicas and the AOF do not depend on how a module version computes the results:
+//! * a deleted key is replicated as `DEL key`,
+//! * a new key, or a document up to [`full_document_threshold`] bytes,
+//!   is replicated as `CBOR.SET key $ document`,
+//! * a larger document is replicated as the `CBOR.PATCH key patch` computed by `CBOR.DIFF`,
+//!   unless the patch is not smaller than the document or does not reproduce the document byte for byte.
+//!
+//! Changes are replicated as one `CBOR.PATCH` rather than as one `CBOR.SET` of the final value
+//! at each affected path: `CBOR.SET` can neither remove values nor insert array items,
+//! and a single command applies all the changes of a write atomically.
+
+use crate::commands::{apply_patch, diff, parse_patch};
+use cbor_data::CborOwned;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ReplicationMode {
+    Verbatim = 0,
+    Effects = 1,
+}
+
+/// Default size in bytes up to which documents are replicated whole
+pub const DEFAULT_FULL_DOCUMENT_THRESHOLD: usize = 1024;
+
+static REPLICATION_MODE: AtomicU8 = AtomicU8::new(ReplicationMode::Verbatim as u8);
+static FULL_DOCUMENT_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_FULL_DOCUMENT_THRESHOLD);
+
+#[inline]
+pub fn replication_mode() -> ReplicationMode {
+    match REPLICATION_MODE.load(Ordering::Relaxed) {
+        1 => ReplicationMode::Effects,
+        _ => ReplicationMode::Verbatim,
+    }
+}
+
+#[inline]
+pub fn set_replication_mode(mode: ReplicationMode) {
+    REPLICATION_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Size in bytes up to which documents are replicated whole in [`ReplicationMode::Effects`] mode
+#[inline]
+pub fn full_document_threshold() -> usize {
+    FULL_DOCUMENT_THRESHOLD.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub fn set_full_document_threshold(threshold: usize) {
+    FULL_DOCUMENT_THRESHOLD.store(threshold, Ordering::Relaxed);
+}
+
+/// The effect of a write command on a key
+#[derive(Debug, PartialEq)]
+pub enum Effect {
+    /// `DEL key`
+    Delete,
+    /// `CBOR.SET key $ document`
+    Set(CborOwned),
+    /// `CBOR.PATCH key patch`
+    Patch(CborOwned),
+}
+
+/// The effect of changing a key from `old` to `new`,
+/// `None` standing for a new key or a deleted key
+pub fn effect(old: Option<&CborOwned>, new: Option<&CborOwned>, threshold: usize) -> Effect {
+    let (old, new) = match (old, new) {
+        (_, None) => return Effect::Delete,
+        (Some(old), Some(new)) if new.as_slice().len() > threshold => (old, new),
+        (_, Some(new)) => return Effect::Set(new.clone()),
+    };
+
+    let patch = diff(old, new);
+    // the diff ignores the encoding of values, e.g. float widths
+    let reproduces =
+        || match parse_patch(&patch).and_then(|operations| apply_patch(old, &operations)) {
+            Ok(Some(patched)) => patched.as_slice() == new.as_slice(),
+            Ok(None) => old.as_slice() == new.as_slice(),
+            Err(_) => false,
+        };
+
+    if patch.as_slice().len() < new.as_slice().len() && reproduces() {
+        Effect::Patch(patch)
+    } else {
+        Effect::Set(new.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{effect, Effect};
+    use crate::util::{cbor_to_diag, diag_to_bytes, diag_to_cbor};
+    use cbor_data::CborOwned;
+
+    #[test]
+    fn effects() {
+        let old = diag_to_cbor(
+            r#"{"a":[1,2,3],"b":"a string long enough for the patch to be smaller than the document"}"#,
+        );
+        let new = diag_to_cbor(
+            r#"{"a":[1,2,4],"b":"a string long enough for the patch to be smaller than the document"}"#,
+        );
+
+        assert_eq!(Effect::Delete, effect(Some(&old), None, 0));
+        assert_eq!(Effect::Set(new.clone()), effect(None, Some(&new), 0));
+        assert_eq!(
+            Effect::Set(new.clone()),
+            effect(Some(&old), Some(&new), 1024)
+        );
+
+        let Effect::Patch(patch) = effect(Some(&old), Some(&new), 0) else {
+            panic!("expected a patch");
+        };
+        assert_eq!(
+            r##"[{"op":"replace","path":["$","a",{"#":2}],"value":4}]"##,
+            cbor_to_diag(&patch)
+        );
+    }
+
+    #[test]
+    fn encoding_changes() {
+        // semantically equal documents with different float widths
+        let old = CborOwned::unchecked(diag_to_bytes(
+            r#"{"a":1.5_3,"b":"a string long enough for the patch to be smaller than the document"}"#,
+        ));
+        let new = CborOwned::unchecked(diag_to_bytes(
+            r#"{"a":1.5_1,"b":"a string long enough for the patch to be smaller than the document"}"#,
+        ));
+
+        assert_eq!(Effect::Set(new.clone()), effect(Some(&old), Some(&new), 0));
+    }
+}
@@ -0,0 +1,257 @@
+//! Module statistics, reported by `INFO recbor`.
+//!
+//! The default `ReCBOR` section reports the number of CBOR keys, their total size in bytes
+//! as reported by `MEMORY USAGE`, and the number of rejected inputs.
+//! The `commandstats` and `latencystats` sections report, for each command called at least once,
+//! its calls, failed calls and time spent, and the histogram of its latencies.
+
+use crate::util::CborOwnedExt;
+use cbor_data::CborOwned;
+use redis_module::{Context, InfoContext, RedisResult, RedisString};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Names of the module commands, in alphabetical order
+const COMMANDS: [&str; 27] = [
+    "cbor.arrappend",
+    "cbor.arrindex",
+    "cbor.arrinsert",
+    "cbor.arrlen",
+    "cbor.arrpop",
+    "cbor.arrtrim",
+    "cbor.cdc",
+    "cbor.clear",
+    "cbor.debug",
+    "cbor.del",
+    "cbor.diff",
+    "cbor.get",
+    "cbor.mapappend",
+    "cbor.mapkeys",
+    "cbor.maplen",
+    "cbor.merge",
+    "cbor.mget",
+    "cbor.numincrby",
+    "cbor.nummultby",
+    "cbor.numop",
+    "cbor.patch",
+    "cbor.resp",
+    "cbor.set",
+    "cbor.strappend",
+    "cbor.strlen",
+    "cbor.toggle",
+    "cbor.type",
+];
+
+/// Upper bounds in microseconds of the latency histogram buckets, the last bucket being unbounded
+const LATENCY_BOUNDS: [u64; 6] = [10, 100, 1_000, 10_000, 100_000, 1_000_000];
+const LATENCY_BUCKET_NAMES: [&str; 7] = ["10", "100", "1000", "10000", "100000", "1000000", "inf"];
+
+/// Input rejected by a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// A value that is not valid CBOR, or JSON or diagnostic notation with the `FORMAT` option
+    InvalidCbor = 0,
+    /// A path that is not a valid CBORPath
+    InvalidPath = 1,
+    /// A document exceeding the maximum nesting depth
+    TooDeep = 2,
+    /// A document exceeding the maximum size
+    TooLarge = 3,
+}
+
+const REJECTION_NAMES: [&str; 4] = [
+    "rejected_invalid_cbor",
+    "rejected_invalid_path",
+    "rejected_too_deep",
+    "rejected_too_large",
+];
+
+struct CommandStats {
+    calls: AtomicU64,
+    failed_calls: AtomicU64,
+    usec: AtomicU64,
+    latency: [AtomicU64; LATENCY_BUCKET_NAMES.len()],
+}
+
+impl CommandStats {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW: CommandStats = CommandStats {
+        calls: AtomicU64::new(0),
+        failed_calls: AtomicU64::new(0),
+        usec: AtomicU64::new(0),
+        latency: [ZERO; LATENCY_BUCKET_NAMES.len()],
+    };
+
+    fn record(&self, elapsed: Duration, failed: bool) {
+        let usec = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let bucket = LATENCY_BOUNDS
+            .iter()
+            .position(|bound| usec <= *bound)
+            .unwrap_or(LATENCY_BOUNDS.len());
+
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.failed_calls.fetch_add(1, Ordering::Relaxed);
+        }
+        self.usec.fetch_add(usec, Ordering::Relaxed);
+        self.latency[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+static KEYS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+static REJECTIONS: [AtomicU64; REJECTION_NAMES.len()] = [ZERO; REJECTION_NAMES.len()];
+static COMMAND_STATS: [CommandStats; COMMANDS.len()] = [CommandStats::NEW; COMMANDS.len()];
+
+/// Account for a CBOR value stored in a key
+#[inline]
+pub fn value_created(value: &CborOwned) {
+    KEYS.fetch_add(1, Ordering::Relaxed);
+    BYTES.fetch_add(value.mem_usage() as u64, Ordering::Relaxed);
+}
+
+/// Account for a CBOR value freed by Redis
+#[inline]
+pub fn value_freed(value: &CborOwned) {
+    KEYS.fetch_sub(1, Ordering::Relaxed);
+    BYTES.fetch_sub(value.mem_usage() as u64, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn reject(rejection: Rejection) {
+    REJECTIONS[rejection as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Call the `name` command, recording its latency and whether it failed
+pub fn call(
+    name: &str,
+    ctx: &Context,
+    args: Vec<RedisString>,
+    command: fn(&Context, Vec<RedisString>) -> RedisResult,
+) -> RedisResult {
+    let start = Instant::now();
+    let result = command(ctx, args);
+    if let Ok(index) = COMMANDS.binary_search(&name) {
+        COMMAND_STATS[index].record(start.elapsed(), result.is_err());
+    }
+    result
+}
+
+/// Wrap a command handler so that its calls are recorded under the command name
+macro_rules! instrumented {
+    ($name:literal, $command:path) => {
+        |ctx: &redis_module::Context, args: Vec<redis_module::RedisString>| {
+            $crate::stats::call($name, ctx, args, $command)
+        }
+    };
+}
+pub(crate) use instrumented;
+
+/// Write the `INFO recbor` sections
+pub fn add_info(ctx: &InfoContext, _for_crash_report: bool) {
+    ctx.add_info_section(None);
+    ctx.add_info_field_long_long("keys", load(&KEYS));
+    ctx.add_info_field_long_long("bytes", load(&BYTES));
+    for (name, count) in REJECTION_NAMES.iter().zip(&REJECTIONS) {
+        ctx.add_info_field_long_long(name, load(count));
+    }
+
+    let called = || {
+        COMMANDS
+            .iter()
+            .zip(&COMMAND_STATS)
+            .filter(|(_, stats)| stats.calls.load(Ordering::Relaxed) > 0)
+    };
+
+    ctx.add_info_section(Some("commandstats"));
+    for (name, stats) in called() {
+        let calls = stats.calls.load(Ordering::Relaxed);
+        let usec = stats.usec.load(Ordering::Relaxed);
+        ctx.add_info_field_str(
+            &format!("cmdstat_{name}"),
+            &format!(
+                "calls={calls},usec={usec},usec_per_call={:.2},failed_calls={}",
+                usec as f64 / calls as f64,
+                stats.failed_calls.load(Ordering::Relaxed)
+            ),
+        );
+    }
+
+    ctx.add_info_section(Some("latencystats"));
+    for (name, stats) in called() {
+        ctx.add_info_field_str(
+            &format!("latency_histogram_usec_{name}"),
+            &latency_histogram(&stats.latency),
+        );
+    }
+}
+
+#[inline]
+fn load(counter: &AtomicU64) -> i64 {
+    counter.load(Ordering::Relaxed) as i64
+}
+
+/// Cumulative counts of the calls, by latency upper bound: `le_10=2,le_100=5,...,le_inf=6`
+fn latency_histogram(latency: &[AtomicU64]) -> String {
+    let mut cumulative = 0;
+    LATENCY_BUCKET_NAMES
+        .iter()
+        .zip(latency)
+        .map(|(bound, count)| {
+            cumulative += count.load(Ordering::Relaxed);
+            format!("le_{bound}={cumulative}")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{latency_histogram, CommandStats, COMMANDS};
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    #[test]
+    fn commands_sorted() {
+        assert!(COMMANDS.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn commands_registered() {
+        // ["cbor.get", instrumented!("cbor.get", commands::cbor_get), ...]
+        let mut registered = include_str!("lib.rs")
+            .lines()
+            .filter(|line| line.contains("instrumented!(\""))
+            .map(|line| {
+                let names = line.split('"').collect::<Vec<_>>();
+                assert_eq!(names[1], names[3], "{line}");
+                names[3]
+            })
+            .collect::<Vec<_>>();
+        registered.sort_unstable();
+
+        assert_eq!(COMMANDS.to_vec(), registered);
+    }
+
+    #[test]
+    fn histogram() {
+        let stats = CommandStats::NEW;
+        stats.record(Duration::from_micros(5), false);
+        stats.record(Duration::from_micros(10), false);
+        stats.record(Duration::from_micros(250), true);
+        stats.record(Duration::from_secs(2), false);
+
+        assert_eq!(4, stats.calls.load(Ordering::Relaxed));
+        assert_eq!(1, stats.failed_calls.load(Ordering::Relaxed));
+        assert_eq!(2_000_265, stats.usec.load(Ordering::Relaxed));
+
+        assert_eq!(
+            "le_10=2,le_100=2,le_1000=3,le_10000=3,le_100000=3,le_1000000=3,le_inf=4",
+            latency_histogram(&stats.latency)
+        );
+    }
+}
@@ -1,27 +1,359 @@
-use crate::redis_cbor_type::REDIS_CBOR_TYPE;
+use crate::{
+    cdc::{self, change_entry},
+    config::{self, max_depth, max_document_size, output_format, PathSyntax},
+    events::{detailed_event, detailed_events, EVENTS_CHANNEL},
+    json::{cbor_to_json, json_to_cbor},
+    path_syntax::{self, is_text_path},
+    redis_cbor_type::REDIS_CBOR_TYPE,
+    replication::{effect, full_document_threshold, replication_mode, Effect, ReplicationMode},
+    stats::{self, Rejection},
+};
 use cbor_data::Cbor;
 use cbor_data::CborOwned;
+use cbor_data::ItemKind;
 #[cfg(test)]
-use cbor_diag::{parse_bytes, parse_diag};
+use cbor_diag::parse_bytes;
+use cbor_diag::parse_diag;
 use cborpath::CborPath;
 use redis_module::{
-    key::{RedisKey, RedisKeyWritable},
-    Context, NotifyEvent, RedisError, RedisString, Status,
+    key::{KeyType, RedisKey, RedisKeyWritable},
+    Context, ContextFlags, NotifyEvent, RedisError, RedisString, RedisValue, Status,
+};
+use std::{
+    borrow::Cow,
+    fmt::{self, Debug, Display, Formatter},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+/// Error replies of the module commands.
+///
+/// The first word of each reply is a stable prefix clients can match on:
+/// * `ERR`: generic errors, such as syntax errors or invalid arguments
+/// * `WRONGTYPE`: a value does not have the type required by the operation
+/// * `NONEXISTENT`: the key or the path does not exist
+#[derive(Debug, PartialEq, Eq)]
+pub enum CborError {
+    Err(Cow<'static, str>),
+    WrongType(Cow<'static, str>),
+    NonExistent(Cow<'static, str>),
+}
+
+impl CborError {
+    #[inline]
+    pub fn syntax() -> Self {
+        CborError::Err("syntax error".into())
+    }
+
+    #[inline]
+    pub fn nonexistent_key() -> Self {
+        CborError::NonExistent(
+            "could not perform this operation on a key that doesn't exist".into(),
+        )
+    }
+
+    #[inline]
+    pub fn no_match() -> Self {
+        CborError::NonExistent("path does not match any value".into())
+    }
+
+    #[inline]
+    pub fn expected_root() -> Self {
+        CborError::Err("new CBOR documents must be created with a root path".into())
+    }
+
+    #[inline]
+    pub fn integer_overflow() -> Self {
+        CborError::Err("integer overflow - the result does not fit in a CBOR integer".into())
+    }
+
+    /// Failure to write the values matching a CBORPath
+    #[inline]
+    pub fn path_write(e: impl Debug) -> Self {
+        CborError::Err(format!("could not write the values matching the path: {e:?}").into())
+    }
+
+    #[inline]
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            CborError::Err(_) => "ERR",
+            CborError::WrongType(_) => "WRONGTYPE",
+            CborError::NonExistent(_) => "NONEXISTENT",
+        }
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        match self {
+            CborError::Err(message)
+            | CborError::WrongType(message)
+            | CborError::NonExistent(message) => message,
+        }
+    }
+
+    /// Prepend `context` to the message, keeping the prefix
+    pub fn context(self, context: impl Display) -> Self {
+        let message = format!("{context} - {}", self.message()).into();
+        match self {
+            CborError::Err(_) => CborError::Err(message),
+            CborError::WrongType(_) => CborError::WrongType(message),
+            CborError::NonExistent(_) => CborError::NonExistent(message),
+        }
+    }
+}
+
+impl Display for CborError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.prefix(), self.message())
+    }
+}
+
+impl From<CborError> for RedisError {
+    #[inline]
+    fn from(e: CborError) -> Self {
+        RedisError::String(e.to_string())
+    }
+}
+
+/// Write the new value of a key, or delete the key if `new_value` is `None`,
+/// then notify the `command` event and replicate the command, or its effect,
+/// depending on the [replication mode](crate::replication).
+/// `args` are the arguments of the command to replicate, starting with its name and the key name.
+///
+/// The new value is first checked against the configured size and nesting depth limits,
+/// then the change is appended to the CDC streams of the key, if any:
+/// the key is left unchanged if the value exceeds a limit or the change cannot be captured.
+///
+/// The time to live of the key is kept.
 pub fn apply_changes(
     ctx: &Context,
     command: &str,
-    key_name: &RedisString,
+    args: &[RedisString],
+    key: &RedisKeyWritable,
+    new_value: Option<CborOwned>,
 ) -> Result<(), RedisError> {
-    if ctx.notify_keyspace_event(NotifyEvent::MODULE, command, key_name) != Status::Ok {
-        Err(RedisError::Str("failed notify key space event"))
+    apply_changes_with_expire(ctx, command, args, key, new_value, KeyExpire::Keep)
+}
+
+/// Expiration of a key written by [`apply_changes_with_expire`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyExpire {
+    /// Keep the time to live of the key, if any
+    Keep,
+    /// Discard the time to live of the key, like `SET`
+    Discard,
+    /// Expire at this time, in milliseconds since the Unix epoch
+    At(u64),
+}
+
+/// [`apply_changes`], setting the expiration of the key with `expire`.
+///
+/// Writing a value deletes the key, along with its expire, before adding it again:
+/// the expire is restored, and replicated as an absolute time so that it is the same on the replicas.
+pub fn apply_changes_with_expire(
+    ctx: &Context,
+    command: &str,
+    args: &[RedisString],
+    key: &RedisKeyWritable,
+    new_value: Option<CborOwned>,
+    expire: KeyExpire,
+) -> Result<(), RedisError> {
+    let key_name = args.get(1).ok_or(RedisError::WrongArity)?;
+    let old_value = key.get_cbor_value()?;
+
+    let now = now_millis();
+    let ttl = match (expire, &old_value, &new_value) {
+        (KeyExpire::Keep, Some(_), Some(_)) => key_ttl(ctx, key_name)?,
+        _ => None,
+    };
+    let expire_at = if new_value.is_some() {
+        expire_at(expire, ttl, now)
     } else {
-        ctx.replicate_verbatim();
-        Ok(())
+        None
+    };
+
+    // changes replicated from a master or loaded from the AOF were already checked
+    // against the limits of the master, and come with their stream entries
+    if ctx
+        .get_flags()
+        .intersects(ContextFlags::REPLICATED | ContextFlags::LOADING)
+    {
+        // deeper documents would be rejected when loading the RDB
+        if matches!(&new_value, Some(new_value) if exceeds_depth(new_value, MAX_DEPTH)) {
+            return Err(ValidationError::TooDeep.into());
+        }
+    } else {
+        // the configured maximum depth is at most MAX_DEPTH
+        if let Some(new_value) = &new_value {
+            check_limits(new_value)?;
+        }
+
+        let streams = cdc::streams(key_name.as_slice());
+        if !streams.is_empty() {
+            // a change is captured by all its streams or by none
+            for stream in &streams {
+                check_stream(ctx, stream)?;
+            }
+            let changes = change_entry(old_value.map(|v| &**v), new_value.as_deref());
+            for stream in streams {
+                capture_change(ctx, &stream, command, key_name, &changes)?;
+            }
+        }
+    }
+
+    let event = if detailed_events() {
+        Some(detailed_event(
+            command,
+            key_name.as_slice(),
+            old_value.map(|v| &**v),
+            new_value.as_deref(),
+        ))
+    } else {
+        None
+    };
+
+    let effect = match replication_mode() {
+        ReplicationMode::Verbatim => None,
+        ReplicationMode::Effects => Some(effect(
+            old_value,
+            new_value.as_ref(),
+            full_document_threshold(),
+        )),
+    };
+
+    match new_value {
+        Some(new_value) => key.set_cbor_value(new_value)?,
+        None => {
+            key.delete()?;
+        }
+    }
+
+    if ctx.notify_keyspace_event(NotifyEvent::MODULE, command, key_name) != Status::Ok {
+        return Err(CborError::Err("failed notify key space event".into()).into());
+    }
+
+    if let Some(event) = event {
+        let channel = ctx.create_string(EVENTS_CHANNEL);
+        let message = ctx.create_string(event.into_vec());
+        ctx.call("PUBLISH", &[&channel, &message][..])?;
+    }
+
+    match effect {
+        // not verbatim, which must not be mixed with the replication of the CDC entries
+        None => {
+            let (command, args) = args.split_first().ok_or(RedisError::WrongArity)?;
+            let args = args.iter().collect::<Vec<_>>();
+            ctx.replicate(command.try_as_str()?, &args[..]);
+        }
+        Some(Effect::Delete) => {
+            ctx.replicate("DEL", &[key_name][..]);
+        }
+        Some(Effect::Set(document)) => {
+            // the CBOR encoding of the root path, which replicas accept whatever their path syntax
+            let root = ctx.create_string(ROOT_PATH);
+            let document = ctx.create_string(document.into_vec());
+            ctx.replicate("CBOR.SET", &[key_name, &root, &document][..]);
+        }
+        Some(Effect::Patch(patch)) => {
+            let patch = ctx.create_string(patch.into_vec());
+            ctx.replicate("CBOR.PATCH", &[key_name, &patch][..]);
+        }
+    }
+
+    if let Some(expire_at) = expire_at {
+        key.set_expire(Duration::from_millis(expire_at.saturating_sub(now)))?;
+        // replicas and the AOF get the absolute expire time,
+        // whether the command, with a relative one, or its effect was replicated
+        let expire_at = ctx.create_string(expire_at.to_string());
+        ctx.replicate("PEXPIREAT", &[key_name, &expire_at][..]);
+    }
+
+    Ok(())
+}
+
+/// The time, in milliseconds since the Unix epoch, at which a written key expires, if any,
+/// given its remaining time to live before the write
+fn expire_at(expire: KeyExpire, ttl: Option<Duration>, now: u64) -> Option<u64> {
+    match expire {
+        KeyExpire::Keep => ttl.map(|ttl| now.saturating_add(ttl.as_millis() as u64)),
+        KeyExpire::Discard => None,
+        KeyExpire::At(at) => Some(at),
     }
 }
 
+/// The remaining time to live of a key, or `None` if the key does not exist or has no expire
+fn key_ttl(ctx: &Context, key_name: &RedisString) -> Result<Option<Duration>, RedisError> {
+    match ctx.call("PTTL", &[key_name][..])? {
+        RedisValue::Integer(ttl) if ttl >= 0 => Ok(Some(Duration::from_millis(ttl as u64))),
+        _ => Ok(None),
+    }
+}
+
+/// The current time, in milliseconds since the Unix epoch
+#[inline]
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+/// `["$"]`
+const ROOT_PATH: &[u8] = b"\x81\x61$";
+
+/// Check that a CDC stream key is either empty or a stream, before appending to any stream
+fn check_stream(ctx: &Context, stream: &[u8]) -> Result<(), CborError> {
+    match ctx.open_key(&ctx.create_string(stream)).key_type() {
+        KeyType::Empty | KeyType::Stream => Ok(()),
+        _ => Err(CborError::WrongType(
+            format!(
+                "CDC stream '{}' holds a value that is not a stream",
+                String::from_utf8_lossy(stream)
+            )
+            .into(),
+        )),
+    }
+}
+
+/// Append a change entry to a CDC stream,
+/// and replicate the entry with its generated id so that replicas and the AOF get the same entry
+fn capture_change(
+    ctx: &Context,
+    stream: &[u8],
+    command: &str,
+    key_name: &RedisString,
+    changes: &Cbor,
+) -> Result<(), RedisError> {
+    let stream = ctx.create_string(stream);
+    let auto_id = ctx.create_string("*");
+    let command_field = ctx.create_string("command");
+    let command = ctx.create_string(command);
+    let key_field = ctx.create_string("key");
+    let changes_field = ctx.create_string("changes");
+    let changes = ctx.create_string(changes.as_slice());
+
+    let fields = [
+        &command_field,
+        &command,
+        &key_field,
+        key_name,
+        &changes_field,
+        &changes,
+    ];
+    let args = [&stream, &auto_id]
+        .into_iter()
+        .chain(fields)
+        .collect::<Vec<_>>();
+
+    let id = match ctx.call("XADD", &args[..])? {
+        RedisValue::SimpleString(id) | RedisValue::BulkString(id) => ctx.create_string(id),
+        RedisValue::StringBuffer(id) => ctx.create_string(id),
+        _ => return Err(CborError::Err("could not append to the CDC stream".into()).into()),
+    };
+
+    let args = [&stream, &id].into_iter().chain(fields).collect::<Vec<_>>();
+    ctx.replicate("XADD", &args[..]);
+    Ok(())
+}
+
 pub trait CborKey {
     fn get_cbor_value(&self) -> Result<Option<&CborOwned>, RedisError>;
 }
@@ -50,21 +382,72 @@ impl CborKeyWritable for RedisKeyWritable {
 
     #[inline]
     fn set_cbor_value(&self, value: CborOwned) -> Result<(), RedisError> {
-        self.set_value(&REDIS_CBOR_TYPE, value)
+        self.set_value(&REDIS_CBOR_TYPE, value)?;
+        // only account for values actually stored, as Redis frees them with the key
+        if let Some(value) = self.get_cbor_value()? {
+            stats::value_created(value);
+        }
+        Ok(())
     }
 }
 
 pub trait CborPathExt: Sized {
-    fn from_arg(arg: &RedisString) -> Result<Self, RedisError>;
+    fn from_arg(ctx: &Context, arg: &RedisString) -> Result<Self, RedisError>;
 }
 
 impl CborPathExt for CborPath {
     #[inline]
-    fn from_arg(arg: &RedisString) -> Result<Self, RedisError> {
-        CborPath::from_bytes(arg.as_slice()).map_err(|_| RedisError::Str("ERR Invalid CBORPath"))
+    fn from_arg(ctx: &Context, arg: &RedisString) -> Result<Self, RedisError> {
+        path_bytes(ctx, arg)
+            .and_then(|bytes| CborPath::from_bytes(&bytes).map_err(|_| invalid_path()))
+            .map_err(|e| {
+                stats::reject(Rejection::InvalidPath);
+                e.into()
+            })
+    }
+}
+
+/// The CBOR encoding of a path argument, which may be given in its textual syntax
+pub fn path_bytes<'a>(ctx: &Context, arg: &'a RedisString) -> Result<Cow<'a, [u8]>, CborError> {
+    let syntax = accepted_path_syntax(ctx.get_flags(), config::path_syntax());
+    parse_path_bytes(arg.as_slice(), syntax)
+}
+
+/// The path syntax accepted with the `configured` one.
+///
+/// Commands replicated from a master or loaded from the AOF are accepted with both syntaxes:
+/// they were already checked against the syntax of the master, which may differ,
+/// and AOF rewrites use CBOR encoded paths.
+pub fn accepted_path_syntax(flags: ContextFlags, configured: PathSyntax) -> PathSyntax {
+    if flags.intersects(ContextFlags::REPLICATED | ContextFlags::LOADING) {
+        PathSyntax::Auto
+    } else {
+        configured
+    }
+}
+
+/// The CBOR encoding of the path `bytes`, parsed as a textual path depending on `syntax`
+pub fn parse_path_bytes(bytes: &[u8], syntax: PathSyntax) -> Result<Cow<'_, [u8]>, CborError> {
+    let is_text = match syntax {
+        PathSyntax::Auto => is_text_path(bytes),
+        PathSyntax::Cbor => false,
+        PathSyntax::Text => true,
+    };
+    if is_text {
+        let text = std::str::from_utf8(bytes).map_err(|_| invalid_path())?;
+        let bytes = path_syntax::parse(text)
+            .map_err(|e| CborError::Err(format!("Invalid CBORPath: {e}").into()))?;
+        Ok(Cow::Owned(bytes))
+    } else {
+        Ok(Cow::Borrowed(bytes))
     }
 }
 
+#[inline]
+fn invalid_path() -> CborError {
+    CborError::Err("Invalid CBORPath".into())
+}
+
 pub trait CborExt {
     fn from_arg(arg: &RedisString) -> Result<&Cbor, RedisError>;
 }
@@ -72,7 +455,167 @@ pub trait CborExt {
 impl CborExt for Cbor {
     #[inline]
     fn from_arg(arg: &RedisString) -> Result<&Cbor, RedisError> {
-        Cbor::checked(arg.as_slice()).map_err(|_| RedisError::Str("ERR Invalid CBOR value"))
+        Ok(Cbor::checked(arg.as_slice()).map_err(|_| {
+            stats::reject(Rejection::InvalidCbor);
+            CborError::Err("Invalid CBOR value".into())
+        })?)
+    }
+}
+
+/// Maximum nesting depth of arrays and maps in a stored CBOR document
+pub const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    Malformed,
+    TooDeep,
+    TooLarge,
+}
+
+impl ValidationError {
+    #[inline]
+    pub fn message(&self) -> &'static str {
+        match self {
+            ValidationError::Malformed => "ERR malformed CBOR document",
+            ValidationError::TooDeep => "ERR CBOR document exceeds the maximum nesting depth",
+            ValidationError::TooLarge => "ERR CBOR document exceeds the maximum size",
+        }
+    }
+}
+
+impl From<ValidationError> for RedisError {
+    #[inline]
+    fn from(e: ValidationError) -> Self {
+        RedisError::Str(e.message())
+    }
+}
+
+/// Check that `bytes` hold exactly one well-formed CBOR item
+/// whose arrays and maps are not nested deeper than `max_depth`
+pub fn validate(bytes: &[u8], max_depth: usize) -> Result<&Cbor, ValidationError> {
+    let cbor = Cbor::checked(bytes).map_err(|_| ValidationError::Malformed)?;
+    if exceeds_depth(cbor, max_depth) {
+        Err(ValidationError::TooDeep)
+    } else {
+        Ok(cbor)
+    }
+}
+
+/// Check that a document to be written is within the configured
+/// [maximum size](max_document_size) and [maximum nesting depth](max_depth)
+pub fn check_limits(cbor: &Cbor) -> Result<(), ValidationError> {
+    if matches!(max_document_size(), Some(max) if cbor.as_slice().len() > max) {
+        stats::reject(Rejection::TooLarge);
+        Err(ValidationError::TooLarge)
+    } else if exceeds_depth(cbor, max_depth()) {
+        stats::reject(Rejection::TooDeep);
+        Err(ValidationError::TooDeep)
+    } else {
+        Ok(())
+    }
+}
+
+fn exceeds_depth(cbor: &Cbor, max_depth: usize) -> bool {
+    match cbor.kind() {
+        ItemKind::Array(mut array) => {
+            max_depth == 0 || array.any(|item| exceeds_depth(item, max_depth - 1))
+        }
+        ItemKind::Dict(mut dict) => {
+            max_depth == 0
+                || dict.any(|(k, v)| {
+                    exceeds_depth(k, max_depth - 1) || exceeds_depth(v, max_depth - 1)
+                })
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Cbor,
+    Json,
+    Diag,
+}
+
+impl Format {
+    pub fn from_arg(arg: &RedisString) -> Result<Self, RedisError> {
+        match arg.try_as_str()? {
+            s if s.eq_ignore_ascii_case("CBOR") => Ok(Format::Cbor),
+            s if s.eq_ignore_ascii_case("JSON") => Ok(Format::Json),
+            s if s.eq_ignore_ascii_case("DIAG") => Ok(Format::Diag),
+            _ => Err(CborError::Err("unknown format - expected CBOR, JSON or DIAG".into()).into()),
+        }
+    }
+}
+
+/// Whether `arg` is the `FORMAT` option keyword
+#[inline]
+pub fn is_format_arg(arg: &RedisString) -> bool {
+    arg.as_slice().eq_ignore_ascii_case(b"FORMAT")
+}
+
+/// Split a trailing `FORMAT fmt` option from `args`,
+/// as long as at least `min_len` arguments remain.
+///
+/// `FORMAT` is neither a valid CBOR, JSON or diagnostic notation value, nor a valid CBORPath,
+/// so it cannot be mistaken for the positional argument preceding the option.
+pub fn split_format_arg(
+    args: &[RedisString],
+    min_len: usize,
+) -> Result<(&[RedisString], Format), RedisError> {
+    split_format_arg_or(args, min_len, Format::Cbor)
+}
+
+/// Split a trailing `FORMAT fmt` option from the arguments of a command
+/// whose format only applies to its value replies,
+/// defaulting to the [configured output format](output_format).
+pub fn split_output_format_arg(
+    args: &[RedisString],
+    min_len: usize,
+) -> Result<(&[RedisString], Format), RedisError> {
+    split_format_arg_or(args, min_len, output_format())
+}
+
+fn split_format_arg_or(
+    args: &[RedisString],
+    min_len: usize,
+    default: Format,
+) -> Result<(&[RedisString], Format), RedisError> {
+    let len = args.len();
+    if len >= min_len + 2 && is_format_arg(&args[len - 2]) {
+        Ok((&args[..len - 2], Format::from_arg(&args[len - 1])?))
+    } else {
+        Ok((args, default))
+    }
+}
+
+/// Read a value argument in the given input format
+pub fn value_from_arg(arg: &RedisString, format: Format) -> Result<Cow<'_, Cbor>, RedisError> {
+    match format {
+        Format::Cbor => Ok(Cow::Borrowed(Cbor::from_arg(arg)?)),
+        Format::Json => Ok(Cow::Owned(json_to_cbor(arg.try_as_str()?).map_err(
+            |_| {
+                stats::reject(Rejection::InvalidCbor);
+                CborError::Err("Invalid JSON value".into())
+            },
+        )?)),
+        Format::Diag => Ok(Cow::Owned(CborOwned::unchecked(
+            parse_diag(arg.try_as_str()?)
+                .map_err(|_| {
+                    stats::reject(Rejection::InvalidCbor);
+                    CborError::Err("Invalid CBOR diagnostic notation value".into())
+                })?
+                .to_bytes(),
+        ))),
+    }
+}
+
+/// Write a value reply in the given output format
+pub fn value_to_reply(value: CborOwned, format: Format) -> RedisValue {
+    match format {
+        Format::Cbor => RedisValue::StringBuffer(value.into_vec()),
+        Format::Json => RedisValue::BulkString(cbor_to_json(&value)),
+        Format::Diag => RedisValue::BulkString(format!("{value}")),
     }
 }
 
@@ -153,3 +696,46 @@ pub fn cbor_to_diag(cbor: &Cbor) -> String {
 pub fn bytes_to_diag(cbor: &[u8]) -> String {
     parse_bytes(cbor).unwrap().to_diag()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        accepted_path_syntax, diag_to_bytes, expire_at, parse_path_bytes, KeyExpire, ROOT_PATH,
+    };
+    use crate::config::PathSyntax;
+    use redis_module::ContextFlags;
+    use std::time::Duration;
+
+    #[test]
+    fn expire_of_written_keys() {
+        let now = 1_700_000_000_000;
+        let ttl = Some(Duration::from_secs(60));
+
+        // a write command on a key with a time to live keeps it
+        assert_eq!(Some(now + 60_000), expire_at(KeyExpire::Keep, ttl, now));
+        assert_eq!(None, expire_at(KeyExpire::Keep, None, now));
+        // CBOR.SET without an expire option discards it, like SET
+        assert_eq!(None, expire_at(KeyExpire::Discard, ttl, now));
+        assert_eq!(Some(now + 5), expire_at(KeyExpire::At(now + 5), ttl, now));
+    }
+
+    #[test]
+    fn replayed_paths() {
+        let text_path = diag_to_bytes(r#"["$","a"]"#);
+
+        for configured in [PathSyntax::Auto, PathSyntax::Cbor, PathSyntax::Text] {
+            for flags in [ContextFlags::REPLICATED, ContextFlags::LOADING] {
+                let syntax = accepted_path_syntax(flags, configured);
+                // the root path of the AOF rewrites and of the replicated effects
+                assert_eq!(ROOT_PATH, &*parse_path_bytes(ROOT_PATH, syntax).unwrap());
+                // a textual path of a command replicated from a master
+                assert_eq!(text_path, &*parse_path_bytes(b"$.a", syntax).unwrap());
+            }
+        }
+
+        let syntax = accepted_path_syntax(ContextFlags::empty(), PathSyntax::Text);
+        assert!(parse_path_bytes(ROOT_PATH, syntax).is_err());
+        let syntax = accepted_path_syntax(ContextFlags::empty(), PathSyntax::Cbor);
+        assert_eq!(b"$.a", &*parse_path_bytes(b"$.a", syntax).unwrap());
+    }
+}